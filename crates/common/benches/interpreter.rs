@@ -0,0 +1,86 @@
+use common::{expression::Program, interpreter::Interpreter, parser::Parser, scanner::Scanner};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+// nothing is printed inside the loops, so the benchmarks measure evaluation and not stdout
+const LOOP: &str = "
+var sum = 0;
+for (var i = 0; i < 10000; i = i + 1) {
+    var square = i * i;
+    if (square > 100 and i != 50) {
+        sum = sum + square / (i + 1);
+    } else {
+        sum = sum - 1;
+    }
+}
+";
+
+const FIB: &str = "
+fun fib(n) {
+    if (n < 2) return n;
+    return fib(n - 1) + fib(n - 2);
+}
+var result = fib(20);
+";
+
+const VARIABLES: &str = "
+fun work(a, b, c) {
+    var total = 0;
+    for (var i = 0; i < 10000; i = i + 1) {
+        var d = a + b;
+        {
+            var e = d * c;
+            total = total + e - i;
+        }
+    }
+    return total;
+}
+var result = work(1, 2, 3);
+";
+
+const STRINGS: &str = "
+var text = \"lox \";
+for (var i = 0; i < 8; i = i + 1) text = text + text;
+var matches = 0;
+for (var i = 0; i < 10000; i = i + 1) {
+    var copy = text;
+    if (copy == text) {
+        matches = matches + 1;
+    }
+    var line = \"> \" + copy;
+}
+";
+
+fn parse(source: &str) -> Program {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens();
+    Parser::new(&scanner.tokens).parse().unwrap()
+}
+
+fn run(c: &mut Criterion, name: &str, source: &str) {
+    c.bench_function(name, |b| {
+        b.iter_batched(
+            || parse(source),
+            |program| Interpreter::new().interpret(black_box(program)).unwrap(),
+            criterion::BatchSize::SmallInput,
+        )
+    });
+}
+
+fn loops(c: &mut Criterion) {
+    run(c, "loop", LOOP);
+}
+
+fn calls(c: &mut Criterion) {
+    run(c, "fib", FIB);
+}
+
+fn variables(c: &mut Criterion) {
+    run(c, "variables", VARIABLES);
+}
+
+fn strings(c: &mut Criterion) {
+    run(c, "strings", STRINGS);
+}
+
+criterion_group!(benches, loops, calls, variables, strings);
+criterion_main!(benches);
@@ -0,0 +1,78 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use crate::{
+    expression::{ExprArena, Stmt},
+    interpreter::Scope,
+    resolver::Locals,
+    token::Token,
+    value::Value,
+};
+
+/// A function declared in Lox. The body is shared with the `Stmt::Function` it was declared by,
+/// and `arena` and `locals` keep the expressions of the program that declared it alive.
+/// `file` is where that program was read from, for stack traces.
+pub struct Function {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Rc<[Stmt]>,
+    pub arena: Rc<ExprArena>,
+    pub locals: Rc<Locals>,
+    pub file: Rc<str>,
+    pub closure: Option<Rc<RefCell<Scope>>>,
+}
+
+/// A function implemented in Rust. Errors are reported as runtime errors on the line of the call.
+pub struct NativeFunction {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: fn(&[Value]) -> Result<Value, String>,
+}
+
+#[derive(Clone)]
+pub enum Callable {
+    Function(Rc<Function>),
+    Native(Rc<NativeFunction>),
+}
+
+impl Callable {
+    pub fn arity(&self) -> usize {
+        match self {
+            Callable::Function(function) => function.params.len(),
+            Callable::Native(native) => native.arity,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Callable::Function(function) => function.name.lexeme(),
+            Callable::Native(native) => native.name,
+        }
+    }
+}
+
+// Functions are compared by identity, two declarations with the same body are still different
+impl PartialEq for Callable {
+    fn eq(&self, other: &Callable) -> bool {
+        match (self, other) {
+            (Callable::Function(s), Callable::Function(o)) => Rc::ptr_eq(s, o),
+            (Callable::Native(s), Callable::Native(o)) => Rc::ptr_eq(s, o),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Callable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Callable::Function(function) => write!(f, "<fn {}>", function.name.lexeme()),
+            Callable::Native(_) => write!(f, "<native fn>"),
+        }
+    }
+}
+
+// The closure can contain the function itself, so Debug must not walk into it
+impl fmt::Debug for Callable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{self}")
+    }
+}
@@ -0,0 +1,514 @@
+/**
+* A step debugger, built on `Hook`.
+*
+* The `Debugger` decides where the program stops: on its first statement, at line breakpoints,
+* and after stepping. Where a statement is on the same line and in the same call as the one before
+* it, stepping doesn't stop again, so it goes a line at a time. A breakpoint stops every time its
+* line is reached again, which includes every pass of a loop whose body is that one line.
+*
+*  step in    stop at the next line, in whatever function that is
+*  step over  stop at the next line of this function, or of its caller once it returns
+*  step out   stop once the function returns
+*  continue   only stop at breakpoints
+*
+* What happens at a stop is up to a `Frontend`: `Console` reads commands from a terminal, a Debug
+* Adapter Protocol server would send a `stopped` event and answer requests until it gets one of
+* `continue`, `next`, `stepIn` or `stepOut`. Both inspect the program through the `Interpreter`
+* they are handed, see `hook`.
+*/
+use std::{
+    collections::BTreeSet,
+    io::{BufRead, Write},
+    rc::Rc,
+};
+
+use crate::{
+    expression::Stmt,
+    hook::Hook,
+    interpreter::{Error, Interpreter},
+    value::Value,
+};
+
+/// What the program does after a stop
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Continue,
+    StepIn,
+    StepOver,
+    StepOut,
+    Quit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+/// The program stopped before the statement on `line`
+pub struct Stop<'a> {
+    pub interpreter: &'a Interpreter,
+    pub line: usize,
+    pub reason: Reason,
+    /// The lines with a breakpoint, the frontend can change them before it resumes
+    pub breakpoints: &'a mut BTreeSet<usize>,
+}
+
+pub trait Frontend {
+    fn stopped(&mut self, stop: Stop<'_>) -> Command;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Continue,
+    StepIn,
+    // the depths the step started at
+    StepOver(usize),
+    StepOut(usize),
+}
+
+pub struct Debugger<F> {
+    frontend: F,
+    breakpoints: BTreeSet<usize>,
+    mode: Mode,
+    // the line and depth of the last statement, to only stop once per line
+    last: Option<(usize, usize)>,
+    // the addresses of the statements run on that line since it was reached, when one of them
+    // runs again a loop has reached the line again
+    ran: Vec<usize>,
+    quit: bool,
+}
+
+impl<F: Frontend> Debugger<F> {
+    /// A debugger that stops on the first statement
+    pub fn new(frontend: F) -> Self {
+        Debugger {
+            frontend,
+            breakpoints: BTreeSet::new(),
+            mode: Mode::StepIn,
+            last: None,
+            ran: vec![],
+            quit: false,
+        }
+    }
+
+    pub fn frontend(&self) -> &F {
+        &self.frontend
+    }
+
+    fn reason(&mut self, stmt: &Stmt, line: usize, depth: usize) -> Option<Reason> {
+        let address = stmt as *const Stmt as usize;
+        let same_line = self.last == Some((line, depth));
+        let reached = !same_line || self.ran.contains(&address);
+        if reached {
+            self.ran.clear();
+        }
+        self.ran.push(address);
+        let last = self.last.replace((line, depth));
+        if reached && self.breakpoints.contains(&line) {
+            return Some(Reason::Breakpoint);
+        }
+        let stepped = match self.mode {
+            Mode::Continue => false,
+            Mode::StepIn => true,
+            Mode::StepOver(start) => depth <= start,
+            Mode::StepOut(start) => depth < start,
+        };
+        match last {
+            _ if !stepped || same_line => None,
+            None => Some(Reason::Entry),
+            Some(_) => Some(Reason::Step),
+        }
+    }
+}
+
+impl<F: Frontend> Hook for Debugger<F> {
+    fn before_statement(
+        &mut self,
+        interpreter: &Interpreter,
+        stmt: &Stmt,
+        line: usize,
+    ) -> Result<(), Error> {
+        if self.quit {
+            return Err(Error::Abort);
+        }
+        let depth = interpreter.depth();
+        let Some(reason) = self.reason(stmt, line, depth) else {
+            return Ok(());
+        };
+
+        let stop = Stop {
+            interpreter,
+            line,
+            reason,
+            breakpoints: &mut self.breakpoints,
+        };
+        self.mode = match self.frontend.stopped(stop) {
+            Command::Continue => Mode::Continue,
+            Command::StepIn => Mode::StepIn,
+            Command::StepOver => Mode::StepOver(depth),
+            Command::StepOut => Mode::StepOut(depth),
+            Command::Quit => {
+                self.quit = true;
+                return Err(Error::Abort);
+            }
+        };
+        Ok(())
+    }
+}
+
+const HELP: &str = "\
+Commands:
+  break <line>, b     stop at a line, without a line list the breakpoints
+  delete <line>, d    remove a breakpoint
+  continue, c         run until the next breakpoint
+  step, s             run to the next line, stepping into calls
+  next, n             run to the next line, stepping over calls
+  out, o              run until the current function returns
+  backtrace, bt       show the call stack
+  frame <n>, f        select a frame of the call stack for vars and print
+  vars, v             show the variables of the selected frame and the globals
+  print <name>, p     show a variable
+  list, l             show the source around the current line
+  quit, q             stop the program";
+
+// the names and values of variables
+type Variables = Vec<(Rc<str>, Value)>;
+
+/// A frontend for a terminal, reading commands from `input` and writing to `output`
+pub struct Console<R, W> {
+    input: R,
+    output: W,
+    source: Vec<String>,
+    // the frame of the call stack `vars` and `print` look at, 0 is the innermost
+    frame: usize,
+}
+
+impl<R: BufRead, W: Write> Console<R, W> {
+    pub fn new(source: &str, input: R, output: W) -> Self {
+        Console {
+            input,
+            output,
+            source: source.lines().map(str::to_string).collect(),
+            frame: 0,
+        }
+    }
+
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    fn source_line(&self, line: usize) -> &str {
+        self.source.get(line - 1).map_or("", |text| text.as_str())
+    }
+
+    // the variables of the selected frame by scope from the innermost out, then the globals
+    fn variables(&self, interpreter: &Interpreter) -> Vec<(String, Variables)> {
+        let mut groups = vec![];
+        for (i, scope) in interpreter.scopes(self.frame).into_iter().enumerate() {
+            let variables = scope
+                .into_iter()
+                .enumerate()
+                .map(|(slot, (name, value))| {
+                    (
+                        name.unwrap_or_else(|| Rc::from(format!("<slot {slot}>"))),
+                        value,
+                    )
+                })
+                .collect();
+            groups.push((format!("scope {i}"), variables));
+        }
+        let globals = interpreter.globals().into_iter();
+        let globals = globals.map(|(name, value)| (Rc::from(name), value));
+        groups.push(("globals".to_string(), globals.collect()));
+        groups
+    }
+
+    // runs one command, `None` when it doesn't resume the program
+    fn command(&mut self, stop: &mut Stop<'_>, line: &str) -> std::io::Result<Option<Command>> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let argument = words.next();
+        let number = argument.and_then(|argument| argument.parse::<usize>().ok());
+        let out = &mut self.output;
+        match (command, number) {
+            ("continue" | "c", _) => return Ok(Some(Command::Continue)),
+            ("step" | "s", _) => return Ok(Some(Command::StepIn)),
+            ("next" | "n", _) => return Ok(Some(Command::StepOver)),
+            ("out" | "o", _) => return Ok(Some(Command::StepOut)),
+            ("quit" | "q", _) => return Ok(Some(Command::Quit)),
+            ("break" | "b", Some(line)) => {
+                stop.breakpoints.insert(line);
+                writeln!(out, "Breakpoint at line {line}.")?;
+            }
+            ("break" | "b", None) if argument.is_none() => {
+                let lines: Vec<_> = stop.breakpoints.iter().map(usize::to_string).collect();
+                match lines.is_empty() {
+                    true => writeln!(out, "No breakpoints.")?,
+                    false => writeln!(out, "Breakpoints at lines {}.", lines.join(", "))?,
+                }
+            }
+            ("delete" | "d", Some(line)) => match stop.breakpoints.remove(&line) {
+                true => writeln!(out, "Deleted the breakpoint at line {line}.")?,
+                false => writeln!(out, "No breakpoint at line {line}.")?,
+            },
+            ("backtrace" | "bt", _) => {
+                let frames = stop.interpreter.call_stack(stop.line);
+                for (i, frame) in frames.iter().enumerate() {
+                    let marker = if i == self.frame { '*' } else { ' ' };
+                    writeln!(out, "{marker} #{i} {frame}")?;
+                }
+            }
+            ("frame" | "f", Some(frame)) => {
+                let frames = stop.interpreter.call_stack(stop.line);
+                match frames.get(frame) {
+                    Some(selected) => {
+                        writeln!(out, "#{frame} {selected}")?;
+                        self.frame = frame;
+                    }
+                    None => writeln!(out, "No frame {frame}, the stack has {}.", frames.len())?,
+                }
+            }
+            ("vars" | "v", _) => {
+                for (group, variables) in self.variables(stop.interpreter) {
+                    writeln!(self.output, "{group}:")?;
+                    for (name, value) in variables {
+                        writeln!(self.output, "  {name} = {value}")?;
+                    }
+                }
+            }
+            ("print" | "p", _) if argument.is_some() => {
+                let name = argument.unwrap_or_default();
+                let found = self
+                    .variables(stop.interpreter)
+                    .into_iter()
+                    .flat_map(|(_, variables)| variables.into_iter().rev())
+                    .find(|(variable, _)| &**variable == name);
+                match found {
+                    Some((_, value)) => writeln!(self.output, "{name} = {value}")?,
+                    None => writeln!(self.output, "No variable '{name}' in frame {}.", self.frame)?,
+                }
+            }
+            ("list" | "l", _) => {
+                let first = stop.line.saturating_sub(3).max(1);
+                let last = (stop.line + 3).min(self.source.len());
+                for line in first..=last {
+                    let marker = if line == stop.line { "->" } else { "  " };
+                    let text = self.source_line(line).to_string();
+                    writeln!(self.output, "{marker} {line:>4}  {text}")?;
+                }
+            }
+            ("help" | "h", _) => writeln!(out, "{HELP}")?,
+            ("", _) => {}
+            _ => writeln!(out, "Unknown command '{}', try 'help'.", line.trim())?,
+        }
+        Ok(None)
+    }
+
+    fn prompt(&mut self, stop: &mut Stop<'_>) -> std::io::Result<Command> {
+        let reason = match stop.reason {
+            Reason::Entry | Reason::Step => "Stopped",
+            Reason::Breakpoint => "Breakpoint",
+        };
+        let text = self.source_line(stop.line).trim().to_string();
+        writeln!(self.output, "{reason} at line {}: {text}", stop.line)?;
+        loop {
+            write!(self.output, "(debug) ")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            // the end of the input quits, like it would a shell
+            if self.input.read_line(&mut line)? == 0 {
+                writeln!(self.output)?;
+                return Ok(Command::Quit);
+            }
+            if let Some(command) = self.command(stop, &line)? {
+                return Ok(command);
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Frontend for Console<R, W> {
+    fn stopped(&mut self, mut stop: Stop<'_>) -> Command {
+        // a terminal that can't be written to can't drive the debugger either
+        self.frame = 0;
+        self.prompt(&mut stop).unwrap_or(Command::Quit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, collections::VecDeque, io::Cursor, rc::Rc};
+
+    use super::{Command, Console, Debugger, Frontend, Reason, Stop};
+    use crate::{interpreter::Interpreter, parser::Parser, scanner::Scanner};
+
+    const SOURCE: &str = "\
+fun add(a, b) {
+  var sum = a + b;
+  return sum;
+}
+var x = add(1, 2);
+print x;
+print add(x, 4);";
+
+    // answers every stop with the next command, and remembers where it stopped
+    #[derive(Default)]
+    struct Script {
+        commands: VecDeque<Command>,
+        breakpoints: Vec<usize>,
+        stops: Vec<(usize, Reason, usize)>,
+        variables: Vec<String>,
+    }
+
+    impl Frontend for Script {
+        fn stopped(&mut self, stop: Stop<'_>) -> Command {
+            let depth = stop.interpreter.depth();
+            self.stops.push((stop.line, stop.reason, depth));
+            stop.breakpoints.extend(self.breakpoints.drain(..));
+            let locals = stop.interpreter.scopes(0).into_iter().flatten();
+            self.variables.push(
+                locals
+                    .map(|(name, value)| format!("{}={value}", name.unwrap()))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            );
+            self.commands.pop_front().unwrap_or(Command::Continue)
+        }
+    }
+
+    fn debug<F: Frontend + 'static>(source: &str, frontend: F) -> (Rc<RefCell<Debugger<F>>>, bool) {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.scan_tokens();
+        let program = Parser::new(&scanner.tokens).parse().unwrap();
+        let debugger = Rc::new(RefCell::new(Debugger::new(frontend)));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_hook(debugger.clone());
+        let finished = interpreter.interpret(program).is_ok();
+        (debugger, finished)
+    }
+
+    fn stops(
+        source: &str,
+        commands: &[Command],
+        breakpoints: &[usize],
+    ) -> Vec<(usize, Reason, usize)> {
+        let script = Script {
+            commands: commands.iter().copied().collect(),
+            breakpoints: breakpoints.to_vec(),
+            ..Script::default()
+        };
+        let (debugger, finished) = debug(source, script);
+        assert!(finished);
+        let stops = debugger.borrow().frontend().stops.clone();
+        stops
+    }
+
+    #[test]
+    fn steps_in_over_and_out() {
+        use Command::*;
+        use Reason::*;
+        assert_eq!(
+            stops(
+                SOURCE,
+                &[StepIn, StepIn, StepIn, StepIn, StepOver, StepOver],
+                &[]
+            ),
+            vec![
+                (1, Entry, 0),
+                (5, Step, 0),
+                (2, Step, 1),
+                (3, Step, 1),
+                (6, Step, 0),
+                (7, Step, 0),
+            ]
+        );
+        assert_eq!(
+            stops(SOURCE, &[StepIn, StepIn, StepOut], &[]),
+            vec![(1, Entry, 0), (5, Step, 0), (2, Step, 1), (6, Step, 0)]
+        );
+    }
+
+    #[test]
+    fn continues_to_breakpoints() {
+        use Command::*;
+        use Reason::*;
+        assert_eq!(
+            stops(SOURCE, &[Continue, Continue], &[3]),
+            vec![(1, Entry, 0), (3, Breakpoint, 1), (3, Breakpoint, 1)]
+        );
+    }
+
+    #[test]
+    fn stops_at_breakpoints_on_every_pass_of_a_loop() {
+        use Command::*;
+        use Reason::*;
+        let unbraced = "var i = 0;\nwhile (i < 3)\n  i = i + 1;\nprint i;";
+        assert_eq!(
+            stops(unbraced, &[Continue], &[3]),
+            vec![
+                (1, Entry, 0),
+                (3, Breakpoint, 0),
+                (3, Breakpoint, 0),
+                (3, Breakpoint, 0)
+            ]
+        );
+        // but only once a pass when the line has more than one statement
+        let one_line = "var i = 0;\nwhile (i < 2) { i = i + 1; print i; }";
+        assert_eq!(
+            stops(one_line, &[Continue], &[2]),
+            vec![(1, Entry, 0), (2, Breakpoint, 0), (2, Breakpoint, 0)]
+        );
+    }
+
+    #[test]
+    fn inspects_variables_and_quits() {
+        let script = Script {
+            commands: [Command::Continue, Command::Quit].into(),
+            breakpoints: vec![3],
+            ..Script::default()
+        };
+        let (debugger, finished) = debug(SOURCE, script);
+        assert!(!finished);
+        let debugger = debugger.borrow();
+        assert_eq!(debugger.frontend().variables, vec!["", "a=1 b=2 sum=3"]);
+    }
+
+    #[test]
+    fn runs_console_commands() {
+        let input = "b 3\nc\nbt\nv\np a\nf 1\np x\nl\nstep\nwhat\nq\n";
+        let console = Console::new(SOURCE, Cursor::new(input), vec![]);
+        let (debugger, finished) = debug(SOURCE, console);
+        assert!(!finished);
+        let debugger = debugger.borrow();
+        let output = String::from_utf8_lossy(debugger.frontend().output());
+        assert_eq!(
+            output,
+            "\
+Stopped at line 1: fun add(a, b) {
+(debug) Breakpoint at line 3.
+(debug) Breakpoint at line 3: return sum;
+(debug) * #0 at add (<stdin>:3)
+  #1 at <script> (<stdin>:5)
+(debug) scope 0:
+  a = 1
+  b = 2
+  sum = 3
+globals:
+  add = <fn add>
+(debug) a = 1
+(debug) #1 at <script> (<stdin>:5)
+(debug) No variable 'x' in frame 1.
+(debug)       1  fun add(a, b) {
+      2    var sum = a + b;
+->    3    return sum;
+      4  }
+      5  var x = add(1, 2);
+      6  print x;
+(debug) Stopped at line 6: print x;
+(debug) Unknown command 'what', try 'help'.
+(debug) "
+        );
+    }
+}
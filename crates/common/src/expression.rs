@@ -1,49 +1,69 @@
-use std::{
-    fmt::{self, Display},
-    hash::{Hash, Hasher},
-};
+use std::{fmt, ops::Index, rc::Rc};
 
-use uuid::Uuid;
-
-use crate::{
-    token::{Token, TokenType},
-    value::Value,
-};
+use crate::{token::Token, value::Value};
 
 // copying from here https://github.com/mchlrhw/loxide/blob/main/treewalk/src/ast.rs
 // also found an interesting implementation using macros here https://github.com/abesto/jlox-rs/blob/main/src/ast.rs
 
+/// Index of an expression in its `ExprArena`. Ids are handed out sequentially while parsing,
+/// so the same source always produces the same ids and side tables can be plain `Vec`s.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ExprId(pub u32);
+
+impl ExprId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
 #[derive(Clone, Debug)]
 pub enum ExprKind {
     Assign {
         name: Token,
-        value: Box<Expr>,
+        value: ExprId,
     },
     Binary {
-        left: Box<Expr>,
+        left: ExprId,
         operator: Token,
-        right: Box<Expr>,
+        right: ExprId,
     },
     Call {
-        callee: Box<Expr>,
+        callee: ExprId,
         paren: Token,
-        arguments: Vec<Expr>,
+        arguments: Vec<ExprId>,
     },
     Get {
-        object: Box<Expr>,
+        object: ExprId,
         name: Token,
     },
-    Grouping(Box<Expr>),
+    Grouping(ExprId),
+    List(Vec<ExprId>),
     Literal(Option<Value>),
     Logical {
-        left: Box<Expr>,
+        left: ExprId,
         operator: Token,
-        right: Box<Expr>,
+        right: ExprId,
+    },
+    // key and value pairs in source order
+    Map {
+        brace: Token,
+        entries: Vec<(ExprId, ExprId)>,
     },
     Set {
-        object: Box<Expr>,
+        object: ExprId,
         name: Token,
-        value: Box<Expr>,
+        value: ExprId,
+    },
+    Subscript {
+        object: ExprId,
+        bracket: Token,
+        index: ExprId,
+    },
+    SubscriptSet {
+        object: ExprId,
+        bracket: Token,
+        index: ExprId,
+        value: ExprId,
     },
     Super {
         keyword: Token,
@@ -52,109 +72,194 @@ pub enum ExprKind {
     This(Token),
     Unary {
         operator: Token,
-        right: Box<Expr>,
+        right: ExprId,
     },
     Variable(Token),
 }
 
 #[derive(Clone, Debug)]
-pub struct Expr {
-    pub id: Uuid,
+pub struct ExprNode {
+    pub id: ExprId,
     pub kind: ExprKind,
+    /// The first line the expression is on, 0 when it isn't known
+    pub line: usize,
+}
+
+/// Owns every expression of a program; children are referenced by `ExprId`
+#[derive(Clone, Debug, Default)]
+pub struct ExprArena {
+    nodes: Vec<ExprNode>,
+}
+
+impl ExprArena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alloc(&mut self, kind: ExprKind) -> ExprId {
+        self.alloc_at(kind, 0)
+    }
+
+    /// Allocates an expression whose first token is on `line`
+    pub fn alloc_at(&mut self, kind: ExprKind, line: usize) -> ExprId {
+        let id = ExprId(self.nodes.len() as u32);
+        self.nodes.push(ExprNode { id, kind, line });
+        id
+    }
+
+    pub fn get(&self, id: ExprId) -> &ExprNode {
+        &self.nodes[id.index()]
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ExprNode> {
+        self.nodes.iter()
+    }
+
+    pub fn display(&self, id: ExprId) -> DisplayExpr<'_> {
+        DisplayExpr { arena: self, id }
+    }
+
+    pub fn display_stmt<'a>(&'a self, stmt: &'a Stmt) -> DisplayStmt<'a> {
+        DisplayStmt { arena: self, stmt }
+    }
 }
 
-impl PartialEq for Expr {
-    fn eq(&self, other: &Expr) -> bool {
-        self.id == other.id
+impl Index<ExprId> for ExprArena {
+    type Output = ExprNode;
+
+    fn index(&self, id: ExprId) -> &ExprNode {
+        self.get(id)
     }
 }
 
-impl Eq for Expr {}
+/**
+ * The printers below write the AST as S-expressions, e.g. `(+ (1) (* (2) (3)))`.
+ * Every variant has its own form so the output can be read back by `reader`:
+ *
+ *  literal     → "(" NUMBER | "\"" STRING "\"" | "true" | "false" | "nil" ")" | "nil"
+ *  variable    → IDENTIFIER | "this"
+ *  assign      → "(= " IDENTIFIER expr ")"
+ *  binary      → "(" OPERATOR expr expr ")"   (also "and"/"or" for logical)
+ *  unary       → "(" ( "-" | "!" | "${" ) expr ")"   (`${` stringifies an interpolated value)
+ *  call        → "(call " expr expr* ")"
+ *  get / set   → "(. " expr IDENTIFIER ")" | "(.= " expr IDENTIFIER expr ")"
+ *  list        → "(list" expr* ")"
+ *  map         → "(map" ( expr expr )* ")"
+ *  subscript   → "([] " expr expr ")" | "([]= " expr expr expr ")"
+ *  super       → "(super " IDENTIFIER ")"
+ */
+pub struct DisplayExpr<'a> {
+    arena: &'a ExprArena,
+    id: ExprId,
+}
 
-fn parenthesize(name: &str, exprs: &[Box<Expr>]) -> String {
-    let mut result = String::new();
-    result.push('(');
-    result.push_str(name);
+fn parenthesize(
+    f: &mut fmt::Formatter<'_>,
+    arena: &ExprArena,
+    name: &str,
+    exprs: &[ExprId],
+) -> fmt::Result {
+    write!(f, "({name}")?;
     for expr in exprs {
-        result.push(' ');
-        result.push_str(&expr.clone().to_string());
+        write!(f, " {}", arena.display(*expr))?;
     }
-    result.push(')');
-    result
+    write!(f, ")")
 }
 
-impl Expr {
-    pub fn new(kind: ExprKind) -> Self {
-        let id = Uuid::new_v4();
-        Self { id, kind }
+// Strings are quoted and escaped so that spaces and parens inside them survive a round trip
+fn write_quoted(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            _ => write!(f, "{c}")?,
+        }
     }
+    write!(f, "\"")
+}
 
-    pub fn to_string(&self) -> String {
-        match self.kind.clone() {
+impl fmt::Display for DisplayExpr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arena = self.arena;
+        match &arena[self.id].kind {
+            ExprKind::Assign { name, value } => {
+                write!(f, "(= {} {})", name.lexeme(), arena.display(*value))
+            }
             ExprKind::Binary {
                 left,
                 operator,
                 right,
-            } => parenthesize(&operator.to_lexme(), &[left, right]),
-            ExprKind::Grouping(expression) => parenthesize("group", &[expression]),
-            ExprKind::Literal(literal) => {
-                if literal.is_none() {
-                    return "nil".to_string();
-                }
-                let mut result = String::new();
-                result.push('(');
-                result.push_str(literal.unwrap().to_string().as_str());
-                result.push(')');
-                result
             }
-            ExprKind::Unary { operator, right } => parenthesize(&operator.to_lexme(), &[right]),
-            ExprKind::Assign { name, value } => {
-                let mut result = String::new();
-                result.push('(');
-                result.push_str("=");
-                result.push(' ');
-                result.push_str(&name.to_lexme());
-                result.push(' ');
-                result.push_str(&value.to_string());
-                result.push(')');
-                result
-            }
-
-            ExprKind::Call {
-                callee,
-                paren,
-                arguments,
-            } => todo!(),
-            ExprKind::Get { object, name } => todo!(),
-            ExprKind::Logical {
+            | ExprKind::Logical {
                 left,
                 operator,
                 right,
-            } => todo!(),
+            } => parenthesize(f, arena, operator.lexeme(), &[*left, *right]),
+            ExprKind::Call {
+                callee, arguments, ..
+            } => {
+                let mut exprs = vec![*callee];
+                exprs.extend(arguments);
+                parenthesize(f, arena, "call", &exprs)
+            }
+            ExprKind::Get { object, name } => {
+                write!(f, "(. {} {})", arena.display(*object), name.lexeme())
+            }
+            ExprKind::Grouping(expression) => parenthesize(f, arena, "group", &[*expression]),
+            ExprKind::List(elements) => parenthesize(f, arena, "list", elements),
+            ExprKind::Literal(None) => write!(f, "nil"),
+            ExprKind::Literal(Some(Value::String(s))) => {
+                write!(f, "(")?;
+                write_quoted(f, s)?;
+                write!(f, ")")
+            }
+            ExprKind::Literal(Some(literal)) => write!(f, "({literal})"),
+            ExprKind::Map { entries, .. } => {
+                let exprs: Vec<ExprId> = entries.iter().flat_map(|(k, v)| [*k, *v]).collect();
+                parenthesize(f, arena, "map", &exprs)
+            }
             ExprKind::Set {
                 object,
                 name,
                 value,
-            } => todo!(),
-            ExprKind::Super { keyword, method } => todo!(),
-            ExprKind::This(_) => todo!(),
-            ExprKind::Variable(token) => return token.to_lexme(),
+            } => write!(
+                f,
+                "(.= {} {} {})",
+                arena.display(*object),
+                name.lexeme(),
+                arena.display(*value)
+            ),
+            ExprKind::Subscript { object, index, .. } => {
+                parenthesize(f, arena, "[]", &[*object, *index])
+            }
+            ExprKind::SubscriptSet {
+                object,
+                index,
+                value,
+                ..
+            } => parenthesize(f, arena, "[]=", &[*object, *index, *value]),
+            ExprKind::Super { method, .. } => write!(f, "(super {})", method.lexeme()),
+            ExprKind::This(_) => write!(f, "this"),
+            ExprKind::Unary { operator, right } => {
+                parenthesize(f, arena, operator.lexeme(), &[*right])
+            }
+            ExprKind::Variable(token) => write!(f, "{}", token.lexeme()),
         }
     }
 }
 
-impl Hash for Expr {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.id.hash(state);
-    }
-}
-
-// impl fmt::Display for Expr {
-//     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-//         write!(f, "{}", self.to_string())
-//     }
-// }
-
 /**
  * program -> statement* EOF;
  * statement -> exprStmt
@@ -162,63 +267,292 @@ impl Hash for Expr {
  * exprStmt -> expression ";";
  * printStmt -> "print" expression ";";
  */
+/// The statements between a pair of braces
+#[derive(Clone, Debug)]
+pub struct Block {
+    /// The `{`, or the `for` keyword of the blocks a for loop is desugared into
+    pub brace: Token,
+    pub stmts: Vec<Stmt>,
+}
+
 #[derive(Clone, Debug)]
 pub enum Stmt {
-    Block(Vec<Stmt>),
+    Block(Block),
     Class {
         name: Token,
-        superclass: Option<Expr>,
+        superclass: Option<ExprId>,
         methods: Vec<Stmt>,
     },
-    Expression(Expr),
+    Expression(ExprId),
+    // `for (var name in iterable) body`, the C-style for loop is desugared into `While`
+    ForIn {
+        name: Token,
+        iterable: ExprId,
+        body: Box<Stmt>,
+    },
     Function {
         name: Token,
         params: Vec<Token>,
-        body: Vec<Stmt>,
+        // shared with the functions created by executing the declaration
+        body: Rc<[Stmt]>,
     },
     If {
-        condition: Expr,
+        condition: ExprId,
         then_branch: Box<Stmt>,
         else_branch: Option<Box<Stmt>>,
     },
-    Print(Expr),
+    Print {
+        keyword: Token,
+        value: ExprId,
+    },
     Return {
         keyword: Token,
-        value: Option<Expr>,
+        value: Option<ExprId>,
+    },
+    Throw {
+        keyword: Token,
+        value: ExprId,
+    },
+    // at least one of `catch` and `finally` is present, `catch` binds the caught value to a name
+    Try {
+        body: Box<Block>,
+        catch: Option<(Token, Block)>,
+        finally: Option<Box<Block>>,
     },
     Var {
         name: Token,
-        initializer: Option<Expr>,
+        initializer: Option<ExprId>,
     },
     While {
-        condition: Expr,
+        condition: ExprId,
         body: Box<Stmt>,
     },
 }
 
+impl Stmt {
+    /// The line the statement starts on, for `if` and `while` the line of their condition. Blocks
+    /// and `try` statements have no line of their own, the statements in them have theirs.
+    pub fn line(&self, arena: &ExprArena) -> Option<usize> {
+        let line = match self {
+            Stmt::Block(_) | Stmt::Try { .. } => return None,
+            Stmt::Class { name, .. }
+            | Stmt::ForIn { name, .. }
+            | Stmt::Function { name, .. }
+            | Stmt::Var { name, .. } => name.line,
+            Stmt::Print { keyword, .. }
+            | Stmt::Return { keyword, .. }
+            | Stmt::Throw { keyword, .. } => keyword.line,
+            // the line of an expression is that of its first token
+            Stmt::Expression(expr)
+            | Stmt::If {
+                condition: expr, ..
+            }
+            | Stmt::While {
+                condition: expr, ..
+            } => arena[*expr].line,
+        };
+        Some(line).filter(|line| *line > 0)
+    }
+
+    /// The `///` doc comment of a class, function or variable declaration
+    pub fn doc(&self) -> Option<&str> {
+        match self {
+            Stmt::Class { name, .. } | Stmt::Function { name, .. } | Stmt::Var { name, .. } => {
+                name.doc.as_deref()
+            }
+            _ => None,
+        }
+    }
+}
+
+/**
+ * Statements use the same notation as expressions:
+ *
+ *  block       → "(block " stmt* ")"
+ *  class       → "(class " IDENTIFIER ( "(< " IDENTIFIER ")" )? function* ")"
+ *  expression  → "(; " expr ")"
+ *  for in      → "(for " IDENTIFIER expr stmt ")"
+ *  function    → "(fun " IDENTIFIER "(" IDENTIFIER* ")" stmt* ")"
+ *  if          → "(if " expr stmt stmt? ")"
+ *  print       → "(print " expr ")"
+ *  return      → "(return" expr? ")"
+ *  throw       → "(throw " expr ")"
+ *  try         → "(try " block ( "(catch " IDENTIFIER block ")" )? ( "(finally " block ")" )? ")"
+ *  var         → "(var " IDENTIFIER expr? ")"
+ *  while       → "(while " expr stmt ")"
+ */
+pub struct DisplayStmt<'a> {
+    arena: &'a ExprArena,
+    stmt: &'a Stmt,
+}
+
+fn write_block(f: &mut fmt::Formatter<'_>, arena: &ExprArena, stmts: &[Stmt]) -> fmt::Result {
+    write!(f, "(block")?;
+    for stmt in stmts {
+        write!(f, " {}", arena.display_stmt(stmt))?;
+    }
+    write!(f, ")")
+}
+
+impl fmt::Display for DisplayStmt<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arena = self.arena;
+        match self.stmt {
+            Stmt::Block(block) => write_block(f, arena, &block.stmts),
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                write!(f, "(class {}", name.lexeme())?;
+                if let Some(superclass) = superclass {
+                    write!(f, " (< {})", arena.display(*superclass))?;
+                }
+                for method in methods {
+                    write!(f, " {}", arena.display_stmt(method))?;
+                }
+                write!(f, ")")
+            }
+            Stmt::Expression(expr) => write!(f, "(; {})", arena.display(*expr)),
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => write!(
+                f,
+                "(for {} {} {})",
+                name.lexeme(),
+                arena.display(*iterable),
+                arena.display_stmt(body)
+            ),
+            Stmt::Function { name, params, body } => {
+                let params: Vec<&str> = params.iter().map(|p| p.lexeme()).collect();
+                write!(f, "(fun {} ({})", name.lexeme(), params.join(" "))?;
+                for stmt in body.iter() {
+                    write!(f, " {}", arena.display_stmt(stmt))?;
+                }
+                write!(f, ")")
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                write!(
+                    f,
+                    "(if {} {}",
+                    arena.display(*condition),
+                    arena.display_stmt(then_branch)
+                )?;
+                if let Some(else_branch) = else_branch {
+                    write!(f, " {}", arena.display_stmt(else_branch))?;
+                }
+                write!(f, ")")
+            }
+            Stmt::Print { value, .. } => write!(f, "(print {})", arena.display(*value)),
+            Stmt::Return { value, .. } => match value {
+                Some(value) => write!(f, "(return {})", arena.display(*value)),
+                None => write!(f, "(return)"),
+            },
+            Stmt::Throw { value, .. } => write!(f, "(throw {})", arena.display(*value)),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                write!(f, "(try ")?;
+                write_block(f, arena, &body.stmts)?;
+                if let Some((name, handler)) = catch {
+                    write!(f, " (catch {} ", name.lexeme())?;
+                    write_block(f, arena, &handler.stmts)?;
+                    write!(f, ")")?;
+                }
+                if let Some(finally) = finally {
+                    write!(f, " (finally ")?;
+                    write_block(f, arena, &finally.stmts)?;
+                    write!(f, ")")?;
+                }
+                write!(f, ")")
+            }
+            Stmt::Var { name, initializer } => match initializer {
+                Some(initializer) => {
+                    write!(f, "(var {} {})", name.lexeme(), arena.display(*initializer))
+                }
+                None => write!(f, "(var {})", name.lexeme()),
+            },
+            Stmt::While { condition, body } => write!(
+                f,
+                "(while {} {})",
+                arena.display(*condition),
+                arena.display_stmt(body)
+            ),
+        }
+    }
+}
+
+/// A parsed program: its top level statements and the arena holding their expressions
+#[derive(Clone, Debug, Default)]
+pub struct Program {
+    pub arena: ExprArena,
+    pub stmts: Vec<Stmt>,
+}
+
+/// Prints one statement per line
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, stmt) in self.stmts.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", self.arena.display_stmt(stmt))?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::value::Value;
+    use super::{ExprArena, ExprId, ExprKind, Stmt};
+    use crate::{
+        parser::Parser,
+        scanner::Scanner,
+        token::{Token, TokenType},
+        value::Value,
+    };
+
+    fn number(arena: &mut ExprArena, n: f64) -> ExprId {
+        arena.alloc(ExprKind::Literal(Some(Value::Number(n))))
+    }
+
+    fn binary(
+        arena: &mut ExprArena,
+        left: ExprId,
+        op: TokenType,
+        lexeme: &str,
+        right: ExprId,
+    ) -> ExprId {
+        arena.alloc(ExprKind::Binary {
+            left,
+            operator: Token::new(op, lexeme.to_owned(), Option::None, 1),
+            right,
+        })
+    }
 
     #[test]
     fn prints_basic_ast() {
-        let expr = super::Expr::new(super::ExprKind::Literal(Some(Value::Number(1.))));
-        assert_eq!(expr.to_string(), "(1)");
+        let mut arena = ExprArena::new();
+        let expr = number(&mut arena, 1.);
+        assert_eq!(arena.display(expr).to_string(), "(1)");
     }
 
     // an AST with a binary expression
     #[test]
     fn prints_ast_with_binary_expression() {
-        let expr = super::Expr::new(super::ExprKind::Binary {
-            left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                Value::Number(1.0),
-            )))),
-            operator: super::Token::new(super::TokenType::PLUS, "+".to_owned(), Option::None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                Value::Number(2.0),
-            )))),
-        });
-        assert_eq!(expr.to_string(), "(+ (1) (2))");
+        let mut arena = ExprArena::new();
+        let left = number(&mut arena, 1.0);
+        let right = number(&mut arena, 2.0);
+        let expr = binary(&mut arena, left, TokenType::PLUS, "+", right);
+        assert_eq!(arena.display(expr).to_string(), "(+ (1) (2))");
     }
 
     /*
@@ -234,24 +568,31 @@ mod tests {
     */
     #[test]
     fn prints_ast_with_nested_binary_expression() {
-        let expr = super::Expr::new(super::ExprKind::Binary {
-            left: Box::new(super::Expr::new(super::ExprKind::Unary {
-                operator: super::Token::new(
-                    super::TokenType::MINUS,
-                    "-".to_owned(),
-                    Option::None,
-                    1,
-                ),
-                right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                    Value::Number(123.),
-                )))),
-            })),
-            operator: super::Token::new(super::TokenType::STAR, "*".to_owned(), Option::None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Grouping(Box::new(
-                super::Expr::new(super::ExprKind::Literal(Some(Value::Number(45.67)))),
-            )))),
+        let mut arena = ExprArena::new();
+        let literal = number(&mut arena, 123.);
+        let unary = arena.alloc(ExprKind::Unary {
+            operator: Token::new(TokenType::MINUS, "-".to_owned(), Option::None, 1),
+            right: literal,
         });
-        assert_eq!(expr.to_string(), "(* (- (123)) (group (45.67)))");
+        let literal = number(&mut arena, 45.67);
+        let grouping = arena.alloc(ExprKind::Grouping(literal));
+        let expr = binary(&mut arena, unary, TokenType::STAR, "*", grouping);
+        assert_eq!(
+            arena.display(expr).to_string(),
+            "(* (- (123)) (group (45.67)))"
+        );
+    }
+
+    // builds the expression 25 + 10 * (2/4)
+    fn nested_binary_expression(arena: &mut ExprArena) -> ExprId {
+        let two = number(arena, 2.);
+        let four = number(arena, 4.);
+        let division = binary(arena, two, TokenType::SLASH, "/", four);
+        let grouping = arena.alloc(ExprKind::Grouping(division));
+        let ten = number(arena, 10.);
+        let product = binary(arena, ten, TokenType::STAR, "*", grouping);
+        let twenty_five = number(arena, 25.);
+        binary(arena, twenty_five, TokenType::PLUS, "+", product)
     }
 
     /*
@@ -259,40 +600,12 @@ mod tests {
     */
     #[test]
     fn prints_ast_with_nested_binary_expression_2() {
-        let expr = super::Expr::new(super::ExprKind::Binary {
-            left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                Value::Number(25.),
-            )))),
-            operator: super::Token::new(super::TokenType::PLUS, "+".to_owned(), Option::None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Binary {
-                left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                    Value::Number(10.),
-                )))),
-                operator: super::Token::new(
-                    super::TokenType::STAR,
-                    "*".to_owned(),
-                    Option::None,
-                    1,
-                ),
-                right: Box::new(super::Expr::new(super::ExprKind::Grouping(Box::new(
-                    super::Expr::new(super::ExprKind::Binary {
-                        left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                            Value::Number(2.),
-                        )))),
-                        operator: super::Token::new(
-                            super::TokenType::SLASH,
-                            "/".to_owned(),
-                            Option::None,
-                            1,
-                        ),
-                        right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                            Value::Number(4.),
-                        )))),
-                    }),
-                )))),
-            })),
-        });
-        assert_eq!(expr.to_string(), "(+ (25) (* (10) (group (/ (2) (4)))))");
+        let mut arena = ExprArena::new();
+        let expr = nested_binary_expression(&mut arena);
+        assert_eq!(
+            arena.display(expr).to_string(),
+            "(+ (25) (* (10) (group (/ (2) (4)))))"
+        );
     }
 
     /*
@@ -301,18 +614,13 @@ mod tests {
     */
     #[test]
     fn prints_ast_with_assignment() {
-        let expr = super::Expr::new(super::ExprKind::Assign {
-            name: super::Token::new(
-                super::TokenType::IDENTIFIER,
-                "a".to_owned(),
-                Option::None,
-                1,
-            ),
-            value: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                Value::Number(1.),
-            )))),
+        let mut arena = ExprArena::new();
+        let value = number(&mut arena, 1.);
+        let expr = arena.alloc(ExprKind::Assign {
+            name: Token::new(TokenType::IDENTIFIER, "a".to_owned(), Option::None, 1),
+            value,
         });
-        assert_eq!(expr.to_string(), "(= a (1))");
+        assert_eq!(arena.display(expr).to_string(), "(= a (1))");
     }
 
     /*
@@ -320,55 +628,62 @@ mod tests {
     */
     #[test]
     fn prints_ast_with_assignment_and_expression() {
-        let expr = super::Expr::new(super::ExprKind::Assign {
-            name: super::Token::new(
-                super::TokenType::IDENTIFIER,
-                "a".to_owned(),
-                Option::None,
-                1,
-            ),
-            value: Box::new(super::Expr::new(super::ExprKind::Binary {
-                left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                    Value::Number(25.),
-                )))),
-                operator: super::Token::new(
-                    super::TokenType::PLUS,
-                    "+".to_owned(),
-                    Option::None,
-                    1,
-                ),
-                right: Box::new(super::Expr::new(super::ExprKind::Binary {
-                    left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                        Value::Number(10.),
-                    )))),
-                    operator: super::Token::new(
-                        super::TokenType::STAR,
-                        "*".to_owned(),
-                        Option::None,
-                        1,
-                    ),
-                    right: Box::new(super::Expr::new(super::ExprKind::Grouping(Box::new(
-                        super::Expr::new(super::ExprKind::Binary {
-                            left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                                Value::Number(2.),
-                            )))),
-                            operator: super::Token::new(
-                                super::TokenType::SLASH,
-                                "/".to_owned(),
-                                Option::None,
-                                1,
-                            ),
-                            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                                Value::Number(4.),
-                            )))),
-                        }),
-                    )))),
-                })),
-            })),
+        let mut arena = ExprArena::new();
+        let value = nested_binary_expression(&mut arena);
+        let expr = arena.alloc(ExprKind::Assign {
+            name: Token::new(TokenType::IDENTIFIER, "a".to_owned(), Option::None, 1),
+            value,
         });
         assert_eq!(
-            expr.to_string(),
+            arena.display(expr).to_string(),
             "(= a (+ (25) (* (10) (group (/ (2) (4))))))"
         );
     }
+
+    #[test]
+    fn allocates_sequential_ids() {
+        let mut arena = ExprArena::new();
+        let expr = nested_binary_expression(&mut arena);
+        assert_eq!(arena.len(), 8);
+        assert_eq!(expr, ExprId(7));
+        assert!(arena
+            .iter()
+            .enumerate()
+            .all(|(i, node)| node.id.index() == i));
+    }
+
+    #[test]
+    fn finds_the_first_line_of_expressions_and_statements() {
+        let mut scanner = Scanner::new(
+            "var a =\n  1;\nprint\n  a +\n  f(\n2);\n{ -a; }\n(\n  a) + [\n  1][0];".to_string(),
+        );
+        scanner.scan_tokens();
+        let program = Parser::new(&scanner.tokens).parse().unwrap();
+        let lines: Vec<_> = program
+            .stmts
+            .iter()
+            .map(|stmt| stmt.line(&program.arena))
+            .collect();
+        // a statement is on the line of its keyword, or of the first token of its expression
+        assert_eq!(lines, vec![Some(1), Some(3), None, Some(8)]);
+        let Stmt::Block(block) = &program.stmts[2] else {
+            panic!("expected a block");
+        };
+        assert_eq!(block.stmts[0].line(&program.arena), Some(7));
+        let lines_of = |matches: fn(&ExprKind) -> bool| -> Vec<_> {
+            let nodes = program.arena.iter();
+            nodes
+                .filter(|node| matches(&node.kind))
+                .map(|node| node.line)
+                .collect()
+        };
+        assert_eq!(
+            lines_of(|kind| matches!(kind, ExprKind::Literal(_))),
+            vec![2, 6, 10, 10]
+        );
+        assert_eq!(
+            lines_of(|kind| matches!(kind, ExprKind::Grouping(_) | ExprKind::List(_))),
+            vec![8, 9]
+        );
+    }
 }
@@ -0,0 +1,463 @@
+/**
+* An opinionated formatter for Lox, built on the concrete syntax tree so comments are kept.
+*
+* - statements go on their own lines, blocks are indented by two spaces
+* - binary operators, `=`, `else` and `in` have a space on both sides, `..` and unary operators
+*   have none, keywords, commas and colons are followed by a space
+* - argument lists that don't fit in 80 columns get one argument per line
+* - comments stay where they are, on their own line or after the code they follow, and at most
+*   one blank line is kept between statements
+*
+* Only source that scans and parses without errors is formatted, and formatting is idempotent:
+* formatting the output again doesn't change it.
+*/
+use thiserror::Error;
+
+use crate::{
+    parser::{self, Parser},
+    scanner::{self, Scanner},
+    syntax::{SyntaxElement, SyntaxKind, SyntaxNode},
+    token::{Token, TokenType, Trivia, TriviaKind},
+};
+
+const INDENT: &str = "  ";
+const MAX_WIDTH: usize = 80;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0}")]
+    Scan(#[from] scanner::Error),
+    #[error("{0}")]
+    Parse(#[from] parser::Error),
+}
+
+/// Formats a whole program, or returns every error that stopped it from being parsed
+pub fn format(source: &str) -> Result<String, Vec<Error>> {
+    let mut scanner = Scanner::lossless(source.to_string());
+    scanner.scan_tokens();
+    let parser = Parser::new(&scanner.tokens);
+    let (result, tree) = parser.parse_lossless();
+
+    let mut errors: Vec<Error> = scanner.errors.into_iter().map(Error::from).collect();
+    if result.is_err() {
+        errors.extend(parser.errors.take().into_iter().map(Error::from));
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut formatter = Formatter::new(false);
+    formatter.program(&tree);
+    Ok(formatter.out)
+}
+
+struct Formatter {
+    out: String,
+    indent: usize,
+    // nothing is written on the current line yet, the indentation comes with the first token
+    line_start: bool,
+    // a space goes before whatever is written next on this line
+    space: bool,
+    // a blank line goes before the next line, unless it starts or ends a block
+    blank_line: bool,
+    // a line comment was written, so the next token has to go on a new line
+    after_line_comment: bool,
+    // argument lists are never broken, used to measure how wide they are on one line
+    flat: bool,
+}
+
+impl Formatter {
+    fn new(flat: bool) -> Self {
+        Formatter {
+            out: String::new(),
+            indent: 0,
+            line_start: true,
+            space: false,
+            blank_line: false,
+            after_line_comment: false,
+            flat,
+        }
+    }
+
+    fn program(&mut self, program: &SyntaxNode) {
+        for child in &program.children {
+            match child {
+                SyntaxElement::Node(statement) => {
+                    self.newline();
+                    self.node(statement);
+                }
+                // the EOF token, with the comments at the end of the file
+                SyntaxElement::Token(eof) => {
+                    self.newline();
+                    self.leading(&eof.leading);
+                }
+            }
+        }
+        self.newline();
+    }
+
+    fn node(&mut self, node: &SyntaxNode) {
+        match node.kind {
+            SyntaxKind::Block => self.block(node),
+            SyntaxKind::ArgList if !self.flat => self.arguments(node),
+            _ => self.children(node),
+        }
+    }
+
+    fn children(&mut self, node: &SyntaxNode) {
+        for child in &node.children {
+            match child {
+                SyntaxElement::Node(child) => {
+                    // the body of an `if`, a loop or a function, and the clauses of a `try`
+                    if is_statement(child.kind) {
+                        self.space = true;
+                    }
+                    self.node(child);
+                }
+                SyntaxElement::Token(token) => self.token_in(node.kind, token),
+            }
+        }
+    }
+
+    fn block(&mut self, block: &SyntaxNode) {
+        let (Some(SyntaxElement::Token(open)), Some(SyntaxElement::Token(close))) =
+            (block.children.first(), block.children.last())
+        else {
+            unreachable!("a block is enclosed in braces");
+        };
+        self.token_in(SyntaxKind::Block, open);
+        let statements: Vec<&SyntaxNode> = block.nodes().collect();
+        if statements.is_empty() && !has_comments(&open.trailing) && !has_comments(&close.leading) {
+            self.token(close);
+            return;
+        }
+
+        self.indent += 1;
+        for statement in statements {
+            self.newline();
+            self.node(statement);
+        }
+        // comments after the last statement belong inside the block
+        self.newline();
+        self.leading(&close.leading);
+        self.indent -= 1;
+        self.blank_line = false;
+        self.newline();
+        self.write(close.lexeme());
+        self.trailing(&close.trailing);
+    }
+
+    // `(` and `)` with the arguments between them on one line, or each on its own line
+    fn arguments(&mut self, arguments: &SyntaxNode) {
+        let mut flat = Formatter::new(true);
+        flat.children(arguments);
+        let width = flat.out.chars().count();
+        // one more column for the `;` or `)` that usually follows
+        if !flat.out.contains('\n') && self.column() + width < MAX_WIDTH {
+            return self.children(arguments);
+        }
+
+        for child in &arguments.children {
+            match child {
+                SyntaxElement::Token(paren) if paren.token_type == TokenType::LeftParen => {
+                    self.token(paren);
+                    self.indent += 1;
+                }
+                SyntaxElement::Token(paren) if paren.token_type == TokenType::RightParen => {
+                    self.indent -= 1;
+                    self.newline();
+                    self.token(paren);
+                }
+                SyntaxElement::Token(comma) => self.token(comma),
+                SyntaxElement::Node(argument) => {
+                    self.newline();
+                    self.node(argument);
+                }
+            }
+        }
+    }
+
+    // a token with the spaces its place in the tree calls for
+    fn token_in(&mut self, parent: SyntaxKind, token: &Token) {
+        let operator = match parent {
+            SyntaxKind::Binary | SyntaxKind::Logical | SyntaxKind::Assign => {
+                token.token_type != TokenType::DotDot
+            }
+            SyntaxKind::VarDecl => token.token_type == TokenType::EQUAL,
+            _ => false,
+        };
+        if operator
+            || matches!(
+                token.token_type,
+                TokenType::LeftBrace | TokenType::ELSE | TokenType::IN
+            )
+        {
+            self.space = true;
+        }
+        self.token(token);
+        if operator || spaced_after(token.token_type) {
+            self.space = true;
+        }
+    }
+
+    fn token(&mut self, token: &Token) {
+        self.leading(&token.leading);
+        self.write(token.lexeme());
+        self.trailing(&token.trailing);
+    }
+
+    // comments before a token, and whether there is a blank line before it
+    fn leading(&mut self, trivia: &[Trivia]) {
+        let mut newlines = 0;
+        let mut after_comment = false;
+        for piece in trivia {
+            match piece.kind {
+                TriviaKind::Newline => newlines += 1,
+                TriviaKind::Comment => {
+                    if newlines > 0 || self.line_start {
+                        self.newline();
+                        if newlines > 1 {
+                            self.blank_line = true;
+                        }
+                    } else {
+                        self.space = true;
+                    }
+                    self.comment(&piece.text);
+                    newlines = 0;
+                    after_comment = true;
+                }
+                TriviaKind::Whitespace | TriviaKind::Skipped => {}
+            }
+        }
+        // a comment on the lines before the token keeps the token on a line of its own
+        if newlines > 0 && after_comment {
+            self.newline();
+        }
+        if newlines > 1 && self.line_start {
+            self.blank_line = true;
+        }
+    }
+
+    // comments after a token on the same line
+    fn trailing(&mut self, trivia: &[Trivia]) {
+        for piece in trivia {
+            if piece.kind == TriviaKind::Comment {
+                self.space = true;
+                self.comment(&piece.text);
+            }
+        }
+    }
+
+    fn comment(&mut self, text: &str) {
+        if text.starts_with("//") {
+            self.write(text.trim_end());
+            self.after_line_comment = true;
+        } else {
+            self.write(text);
+            self.space = true;
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        if self.after_line_comment {
+            self.newline();
+        }
+        if self.line_start {
+            if self.blank_line && !self.out.is_empty() && !self.out.ends_with("{\n") {
+                self.out.push('\n');
+            }
+            self.out.push_str(&INDENT.repeat(self.indent));
+            self.line_start = false;
+        } else if self.space
+            && !matches!(text, ";" | "," | ")" | "]")
+            && !self.out.ends_with(['(', '['])
+            && !self.out.ends_with("${")
+        {
+            self.out.push(' ');
+        }
+        self.space = false;
+        self.blank_line = false;
+        self.out.push_str(text);
+    }
+
+    // ends the current line, unless nothing has been written on it
+    fn newline(&mut self) {
+        self.space = false;
+        self.after_line_comment = false;
+        if !self.line_start {
+            self.out.push('\n');
+            self.line_start = true;
+        }
+    }
+
+    // where the next token would be written
+    fn column(&self) -> usize {
+        if self.line_start {
+            return self.indent * INDENT.len();
+        }
+        let line = self.out.rsplit('\n').next().unwrap_or_default();
+        line.chars().count() + usize::from(self.space)
+    }
+}
+
+fn is_statement(kind: SyntaxKind) -> bool {
+    matches!(
+        kind,
+        SyntaxKind::Block
+            | SyntaxKind::ExprStmt
+            | SyntaxKind::ForInStmt
+            | SyntaxKind::ForStmt
+            | SyntaxKind::FunDecl
+            | SyntaxKind::IfStmt
+            | SyntaxKind::PrintStmt
+            | SyntaxKind::ReturnStmt
+            | SyntaxKind::ThrowStmt
+            | SyntaxKind::TryStmt
+            | SyntaxKind::VarDecl
+            | SyntaxKind::WhileStmt
+            | SyntaxKind::CatchClause
+            | SyntaxKind::FinallyClause
+    )
+}
+
+fn spaced_after(token_type: TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::COMMA
+            | TokenType::COLON
+            | TokenType::SEMICOLON
+            | TokenType::CATCH
+            | TokenType::ELSE
+            | TokenType::FINALLY
+            | TokenType::FOR
+            | TokenType::FUN
+            | TokenType::IF
+            | TokenType::IN
+            | TokenType::PRINT
+            | TokenType::RETURN
+            | TokenType::THROW
+            | TokenType::TRY
+            | TokenType::VAR
+            | TokenType::WHILE
+    )
+}
+
+fn has_comments(trivia: &[Trivia]) -> bool {
+    trivia.iter().any(|piece| piece.kind == TriviaKind::Comment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+
+    fn assert_formats(source: &str, expected: &str) {
+        let formatted = format(source).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format(&formatted).unwrap(), formatted, "not idempotent");
+    }
+
+    #[test]
+    fn normalizes_spacing() {
+        assert_formats(
+            "var  a=1+2*-b;print(a)..3 ;a=!a  or  a and[1,2][0]==a;",
+            "var a = 1 + 2 * -b;\nprint (a)..3;\na = !a or a and [1, 2][0] == a;\n",
+        );
+        assert_formats(
+            "print {\"a\":1,\"b\":f( x,y )};print \"x${ {1:2}[1] }y\";",
+            "print {\"a\": 1, \"b\": f(x, y)};\nprint \"x${{1: 2}[1]}y\";\n",
+        );
+    }
+
+    #[test]
+    fn indents_blocks() {
+        assert_formats(
+            "fun f(a,b){if(a)return b;else{while(b)b=b-1;}for(var i=0;i<2;i=i+1){}for(;;)return;}",
+            "\
+fun f(a, b) {
+  if (a) return b; else {
+    while (b) b = b - 1;
+  }
+  for (var i = 0; i < 2; i = i + 1) {}
+  for (;;) return;
+}
+",
+        );
+        assert_formats(
+            "try{throw 1;}catch(e){print e;}finally{}\nfor(var x in [1]){print x;}",
+            "\
+try {
+  throw 1;
+} catch (e) {
+  print e;
+} finally {}
+for (var x in [1]) {
+  print x;
+}
+",
+        );
+    }
+
+    #[test]
+    fn keeps_comments_and_one_blank_line() {
+        assert_formats(
+            "\
+// header
+
+
+/// Adds.
+fun add(a, b) {   // trailing
+  /* inline */ return a + /* b */ b;
+
+
+  // last
+}
+print 1; // done
+// end
+",
+            "\
+// header
+
+/// Adds.
+fun add(a, b) { // trailing
+  /* inline */ return a + /* b */ b;
+
+  // last
+}
+print 1; // done
+// end
+",
+        );
+        assert_formats("{\n  // only a comment\n}", "{\n  // only a comment\n}\n");
+        assert_formats("print 1 + // why\n  2;", "print 1 + // why\n2;\n");
+    }
+
+    #[test]
+    fn wraps_long_argument_lists() {
+        assert_formats(
+            "print someFunction(firstArgument, secondArgument, thirdArgument, fourthArgument(1, 2));",
+            "\
+print someFunction(
+  firstArgument,
+  secondArgument,
+  thirdArgument,
+  fourthArgument(1, 2)
+);
+",
+        );
+        assert_formats("f(a, // first\nb);", "f(\n  a, // first\n  b\n);\n");
+    }
+
+    #[test]
+    fn refuses_invalid_source() {
+        let errors = format("print 1 +;\n#").unwrap_err();
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            vec![
+                "[line 2] Error: Unexpected character.",
+                "[line 1] Error at ';': Expect expression."
+            ]
+        );
+        assert_eq!(format("").unwrap(), "");
+    }
+}
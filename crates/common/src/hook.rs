@@ -0,0 +1,58 @@
+/**
+* Hooks into a running program, for tools like debuggers and tracers.
+*
+* An `Interpreter` with a hook set calls it before every statement that has a line of its own,
+* see `Stmt::line`, so blocks and `try` statements are skipped but the statements in them aren't.
+* It is also told about every call and what it returned, and every variable that is defined or
+* assigned. The hook is handed the interpreter to look at the program's state with:
+*
+*  Interpreter::call_stack   the functions being called and the lines they are at
+*  Interpreter::depth        how many functions are being called
+*  Interpreter::scopes       the local variables of a frame of the call stack
+*  Interpreter::globals      the global variables
+*/
+use crate::{
+    callable::Callable,
+    expression::Stmt,
+    interpreter::{Error, Interpreter},
+    token::Token,
+    value::Value,
+};
+
+pub trait Hook {
+    /// Called before a statement on `line` runs. An error is raised in place of the statement,
+    /// `Error::Abort` stops the program.
+    fn before_statement(
+        &mut self,
+        _interpreter: &Interpreter,
+        _stmt: &Stmt,
+        _line: usize,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called before `callable` is called from `line`, at the depth of the caller
+    fn call(
+        &mut self,
+        _interpreter: &Interpreter,
+        _callable: &Callable,
+        _arguments: &[Value],
+        _line: usize,
+    ) {
+    }
+
+    /// Called once a call returned a value or raised an error, back at the depth of the caller
+    fn returned(
+        &mut self,
+        _interpreter: &Interpreter,
+        _callable: &Callable,
+        _result: Result<&Value, &Error>,
+    ) {
+    }
+
+    /// Called before a variable is defined, parameters are passed to `call` instead
+    fn define(&mut self, _interpreter: &Interpreter, _name: &Token, _value: &Value) {}
+
+    /// Called after a variable is assigned
+    fn assign(&mut self, _interpreter: &Interpreter, _name: &Token, _value: &Value) {}
+}
@@ -1,84 +1,284 @@
 use crate::{
-    expression::{Expr, ExprKind, Stmt},
-    token::{self, Token, TokenType},
-    value::{self, Value},
-};
-use std::{
-    cell::{Ref, RefCell},
-    collections::HashMap,
-    rc::Rc,
-    result,
+    callable::{Callable, Function},
+    expression::{ExprArena, ExprId, ExprKind, Program, Stmt},
+    hook::Hook,
+    natives,
+    resolver::{Locals, Resolver, Slot},
+    suggest,
+    token::{Token, TokenType},
+    value::{self, MapKey, Value},
 };
+use indexmap::IndexMap;
+use std::{cell::RefCell, collections::HashMap, fmt, mem, rc::Rc, slice};
+
+// 2^53, every whole number up to it is exactly a double
+const MAX_EXACT: f64 = 9007199254740992.0;
+
+const NOT_SUBSCRIPTABLE: &str = "Only lists and maps can be subscripted.";
+
+// name of the frame for code outside of any function
+const SCRIPT_FRAME: &str = "<script>";
+
+/// How deep calls can nest before a "Stack overflow." runtime error is raised in place of the
+/// next one. The Rust stack has to hold that many calls, see `Interpreter`.
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+/// A stack size that fits `MAX_CALL_DEPTH` calls in a debug build with room to spare, for the
+/// thread programs run on. Only the pages in use are allocated.
+pub const STACK_SIZE: usize = MAX_CALL_DEPTH * 64 * 1024;
+
+/// One line of a stack trace: the function that was executing and where it was in its file
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub function: Rc<str>,
+    pub file: Rc<str>,
+    pub line: usize,
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at {} ({}:{})", self.function, self.file, self.line)
+    }
+}
+
+/// The frames active when an error was raised, innermost first. Empty until the error has
+/// propagated out of the function it was raised in, or out of `interpret`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StackTrace(pub Vec<Frame>);
+
+impl StackTrace {
+    pub fn frames(&self) -> &[Frame] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // the trace when there is one, otherwise just the line the error was raised on
+    fn display(&self, line: usize) -> String {
+        if self.is_empty() {
+            return format!("[line {line}]");
+        }
+        self.0
+            .iter()
+            .map(|frame| format!("  {frame}"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
-    #[error("{message}\n[line {line}]")]
-    Runtime { message: String, line: usize },
+    #[error("Error: {message}\n{}", trace.display(*line))]
+    Runtime {
+        message: String,
+        line: usize,
+        trace: StackTrace,
+    },
 
     #[error("Returning {value:?}")]
     Return { value: Value },
+
+    /// A value thrown by a `throw` statement that no `catch` has handled yet
+    #[error("Error: Uncaught {value}\n{}", trace.display(*line))]
+    Throw {
+        value: Value,
+        line: usize,
+        trace: StackTrace,
+    },
+
+    /// Raised by a `Hook` to stop the program, e.g. when the user quits the debugger.
+    /// It can't be caught, but `finally` blocks still run.
+    #[error("Aborted")]
+    Abort,
 }
+
+impl Error {
+    pub fn runtime(message: impl Into<String>, line: usize) -> Self {
+        Error::Runtime {
+            message: message.into(),
+            line,
+            trace: StackTrace::default(),
+        }
+    }
+
+    /// The call stack at the point the error was raised, empty for `Return` and `Abort`
+    pub fn trace(&self) -> &[Frame] {
+        match self {
+            Error::Runtime { trace, .. } | Error::Throw { trace, .. } => trace.frames(),
+            Error::Return { .. } | Error::Abort => &[],
+        }
+    }
+
+    fn trace_mut(&mut self) -> Option<(usize, &mut StackTrace)> {
+        match self {
+            Error::Runtime { line, trace, .. } | Error::Throw { line, trace, .. } => {
+                Some((*line, trace))
+            }
+            Error::Return { .. } | Error::Abort => None,
+        }
+    }
+
+    // what a `catch` clause binds, `None` for control flow that isn't an exception
+    fn caught_value(&self) -> Option<Value> {
+        match self {
+            Error::Runtime { message, line, .. } => {
+                let mut error = IndexMap::new();
+                error.insert(
+                    MapKey::new(Value::String(value::intern("message"))).unwrap(),
+                    // runtime strings aren't interned, a loop of caught errors would fill the table
+                    Value::String(Rc::from(message.as_str())),
+                );
+                error.insert(
+                    MapKey::new(Value::String(value::intern("line"))).unwrap(),
+                    Value::Number(*line as f64),
+                );
+                Some(Value::map(error))
+            }
+            Error::Throw { value, .. } => Some(value.clone()),
+            Error::Return { .. } | Error::Abort => None,
+        }
+    }
+}
+/// Global variables, looked up by name. Locals live in `Scope`s and are found by their resolved slot.
 #[derive(Clone, Default, Debug)]
 pub struct Environment {
     values: HashMap<String, Value>,
-    enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
 impl Environment {
-    pub fn wrap(enclosing: Rc<RefCell<Environment>>) -> Rc<RefCell<Self>> {
-        Rc::new(RefCell::new(Self {
-            enclosing: Some(enclosing),
-            values: HashMap::new(),
-        }))
-    }
     pub fn define(&mut self, name: &str, value: Value) {
-        self.values.insert(name.to_string(), value.clone());
+        self.values.insert(name.to_string(), value);
     }
 
-    fn assign(&mut self, name: &Token, value: Value) -> Result<(), Error> {
-        let lexeme = name.lexeme();
+    // `hint` is a local in scope where the name was used, suggested along with the globals
+    fn assign(&mut self, name: &Token, value: Value, hint: Option<&str>) -> Result<(), Error> {
+        match self.values.get_mut(name.lexeme()) {
+            Some(slot) => {
+                *slot = value;
+                Ok(())
+            }
+            None => Err(self.undefined(name, hint)),
+        }
+    }
 
-        if self.values.contains_key(lexeme) {
-            self.values.insert(lexeme.to_string(), value.clone());
+    pub fn get(&self, token: &Token) -> Result<Value, Error> {
+        self.lookup(token, None)
+    }
 
-            return Ok(());
+    fn lookup(&self, token: &Token, hint: Option<&str>) -> Result<Value, Error> {
+        match self.values.get(token.lexeme()) {
+            Some(value) => Ok(value.clone()),
+            None => Err(self.undefined(token, hint)),
         }
+    }
 
-        if let Some(enclosing) = &self.enclosing {
-            enclosing.borrow_mut().assign(name, value)
+    fn undefined(&self, name: &Token, hint: Option<&str>) -> Error {
+        let lexeme = name.lexeme();
+        let mut message = format!("Undefined variable '{lexeme}'.");
+        let candidates = self.values.keys().map(String::as_str).chain(hint);
+        if let Some(suggestion) = suggest::closest(lexeme, candidates) {
+            message.push('\n');
+            message.push_str(&suggest::help(suggestion));
+        }
+        Error::runtime(message, name.line())
+    }
+}
+
+/// The locals of a block or function call, in the order the `Resolver` numbered them
+#[derive(Clone, Default, Debug)]
+pub struct Scope {
+    slots: Vec<Value>,
+    // the names of the slots, only kept while a hook is set
+    names: Vec<Rc<str>>,
+    enclosing: Option<Rc<RefCell<Scope>>>,
+}
+
+impl Scope {
+    pub fn wrap(enclosing: Option<Rc<RefCell<Scope>>>) -> Rc<RefCell<Self>> {
+        Rc::new(RefCell::new(Self {
+            slots: vec![],
+            names: vec![],
+            enclosing,
+        }))
+    }
+
+    // declarations execute in the order they were resolved, so the next slot is always at the end
+    pub fn define(&mut self, value: Value) {
+        self.slots.push(value);
+    }
+
+    pub fn get_at(&self, slot: Slot) -> Value {
+        if slot.depth == 0 {
+            self.slots[slot.index].clone()
         } else {
-            Err(Error::Runtime {
-                message: format!("Undefined variable '{lexeme}'."),
-                line: name.line(),
-            })
+            self.ancestor(slot.depth).borrow().slots[slot.index].clone()
         }
     }
 
-    pub fn get(&self, token: &Token) -> Result<Value, Error> {
-        let lexeme = token.lexeme();
-        if let Some(value) = self.values.get(lexeme) {
-            return Ok(value.clone());
-        } else if let Some(enc) = &self.enclosing {
-            enc.borrow().get(token)
+    pub fn assign_at(&mut self, slot: Slot, value: Value) {
+        if slot.depth == 0 {
+            self.slots[slot.index] = value;
         } else {
-            Err(Error::Runtime {
-                message: format!("Undefined varliable {lexeme}"),
-                line: token.line,
-            })
+            self.ancestor(slot.depth).borrow_mut().slots[slot.index] = value;
+        }
+    }
+
+    fn ancestor(&self, depth: usize) -> Rc<RefCell<Scope>> {
+        let mut scope = self
+            .enclosing
+            .clone()
+            .expect("resolved slot is deeper than the scope chain");
+        for _ in 1..depth {
+            let enclosing = scope.borrow().enclosing.clone();
+            scope = enclosing.expect("resolved slot is deeper than the scope chain");
         }
+        scope
     }
 }
 
+/**
+ * Runs programs, recursing on the Rust stack for every Lox call. A call takes about 3KB of stack
+ * in a release build and about 30KB in a debug build, so `MAX_CALL_DEPTH` calls fit in the 8MiB
+ * of a main thread only in a release build. Spawned threads get 2MiB by default, too little in
+ * either. Running an `Interpreter` on a thread with a stack of `STACK_SIZE` makes deep recursion
+ * a "Stack overflow." runtime error rather than a crash.
+ */
 pub struct Interpreter {
-    expressions: Vec<Expr>,
-    enviorment: Rc<RefCell<Environment>>,
+    globals: Environment,
+    // innermost local scope, `None` while executing top level statements
+    scope: Option<Rc<RefCell<Scope>>>,
+    // the arena the statements currently being executed point into, and its resolved locals
+    arena: Rc<ExprArena>,
+    locals: Rc<Locals>,
+    // the file the statements currently being executed were read from
+    file: Rc<str>,
+    // functions being called, outermost first
+    frames: Vec<CallFrame>,
+    hook: Option<Rc<RefCell<dyn Hook>>>,
+}
+
+struct CallFrame {
+    function: Rc<Function>,
+    // where the function was called from
+    caller_file: Rc<str>,
+    call_line: usize,
+    caller_scope: Option<Rc<RefCell<Scope>>>,
 }
 
 impl Default for Interpreter {
     fn default() -> Self {
+        let mut globals = Environment::default();
+        natives::define_natives(&mut globals);
         Self {
-            enviorment: Rc::new(RefCell::new(Environment::default())),
-            expressions: vec![],
+            globals,
+            scope: None,
+            arena: Rc::new(ExprArena::new()),
+            locals: Rc::new(Locals::default()),
+            file: Rc::from("<stdin>"),
+            frames: vec![],
+            hook: None,
         }
     }
 }
@@ -88,62 +288,297 @@ impl Interpreter {
         Self::default()
     }
 
-    pub fn interpret(&mut self, statments: Vec<Stmt>) {
-        for statement in statments {
-            if let Err(error) = self.execute(statement) {
-                println!("[Error]: {error}");
+    pub fn with_arena(arena: ExprArena) -> Self {
+        Self {
+            arena: Rc::new(arena),
+            ..Self::default()
+        }
+    }
+
+    /// An interpreter whose stack traces name `file` as the source of the programs it runs
+    pub fn with_file(file: &str) -> Self {
+        Self {
+            file: Rc::from(file),
+            ..Self::default()
+        }
+    }
+
+    /// Calls `hook` before every statement, around every call and on every definition and
+    /// assignment from now on. Set it before running anything, the names
+    /// of local variables are only kept while there is a hook.
+    pub fn set_hook(&mut self, hook: Rc<RefCell<dyn Hook>>) {
+        self.hook = Some(hook);
+    }
+
+    /// Runs a program, stopping at the first uncaught error. The error carries its stack trace.
+    pub fn interpret(&mut self, program: Program) -> Result<(), Error> {
+        self.locals = Rc::new(Resolver::new(&program.arena).resolve(&program.stmts));
+        self.arena = Rc::new(program.arena);
+        for statement in &program.stmts {
+            if let Err(mut error) = self.execute(statement) {
+                // errors raised outside of any function have no trace yet
+                self.capture_trace(&mut error);
+                return Err(error);
             }
         }
+        Ok(())
+    }
+
+    // fills in the trace of an error raised in the innermost frame, unless it already has one
+    fn capture_trace(&self, error: &mut Error) {
+        let Some((line, trace)) = error.trace_mut() else {
+            return;
+        };
+        if trace.is_empty() {
+            trace.0 = self.call_stack(line);
+        }
+    }
+
+    /// The functions being called, innermost first, when the innermost one is at `line`
+    pub fn call_stack(&self, mut line: usize) -> Vec<Frame> {
+        let mut frames = vec![];
+        let mut file = Rc::clone(&self.file);
+        for frame in self.frames.iter().rev() {
+            frames.push(Frame {
+                function: Rc::from(frame.function.name.lexeme()),
+                file,
+                line,
+            });
+            file = Rc::clone(&frame.caller_file);
+            line = frame.call_line;
+        }
+        frames.push(Frame {
+            function: Rc::from(SCRIPT_FRAME),
+            file,
+            line,
+        });
+        frames
+    }
+
+    /// How many functions are being called
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The local variables of a frame of the `call_stack`, by scope from the innermost out, in
+    /// the order they were defined. Variables defined without a hook set have no name.
+    pub fn scopes(&self, frame: usize) -> Vec<Vec<(Option<Rc<str>>, Value)>> {
+        let scope = match frame {
+            0 => self.scope.clone(),
+            _ => match self.frames.len().checked_sub(frame) {
+                Some(index) => self.frames[index].caller_scope.clone(),
+                None => None,
+            },
+        };
+        let mut scopes = vec![];
+        let mut next = scope;
+        while let Some(scope) = next {
+            let scope = scope.borrow();
+            let names = scope
+                .names
+                .iter()
+                .cloned()
+                .map(Some)
+                .chain(std::iter::repeat(None));
+            scopes.push(names.zip(scope.slots.iter().cloned()).collect());
+            next = scope.enclosing.clone();
+        }
+        scopes
     }
 
-    pub fn execute(&mut self, stmt: Stmt) -> Result<(), Error> {
+    /// The global variables sorted by name, without the natives
+    pub fn globals(&self) -> Vec<(&str, Value)> {
+        let mut globals: Vec<_> = self
+            .globals
+            .values
+            .iter()
+            .filter(|(_, value)| !matches!(value, Value::Callable(Callable::Native(_))))
+            .map(|(name, value)| (name.as_str(), value.clone()))
+            .collect();
+        globals.sort_by(|a, b| a.0.cmp(b.0));
+        globals
+    }
+
+    pub fn execute(&mut self, stmt: &Stmt) -> Result<(), Error> {
+        if let Some(hook) = self.hook.clone() {
+            if let Some(line) = stmt.line(&self.arena) {
+                hook.borrow_mut().before_statement(self, stmt, line)?;
+            }
+        }
         match stmt {
             Stmt::Expression(expression) => {
-                self.evaluate(expression)?;
+                self.evaluate(*expression)?;
             }
-            Stmt::Print(expession) => {
-                let value = self.evaluate(expession)?;
+            Stmt::Print { value, .. } => {
+                let value = self.evaluate(*value)?;
                 println!("{}", value);
             }
             Stmt::Var { name, initializer } => {
                 let value = if let Some(initializer) = initializer {
-                    self.evaluate(initializer)?
+                    self.evaluate(*initializer)?
                 } else {
                     Value::Nil
                 };
 
-                self.enviorment.borrow_mut().define(name.lexeme(), value);
+                self.define(name, value);
             }
-            Stmt::Block(stmts) => {
-                self.execute_block(stmts, Environment::wrap(self.enviorment.clone()))?
+            Stmt::Block(block) => {
+                self.execute_block(&block.stmts, Scope::wrap(self.scope.clone()))?
+            }
+            Stmt::Class { .. } => todo!(),
+            Stmt::Function { name, params, body } => {
+                let function = Function {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: Rc::clone(body),
+                    arena: Rc::clone(&self.arena),
+                    locals: Rc::clone(&self.locals),
+                    file: Rc::clone(&self.file),
+                    closure: self.scope.clone(),
+                };
+                let value = Value::Callable(Callable::Function(Rc::new(function)));
+                self.define(name, value);
             }
-            Stmt::Class {
-                name,
-                superclass,
-                methods,
-            } => todo!(),
-            Stmt::Function { name, params, body } => todo!(),
             Stmt::If {
                 condition,
                 then_branch,
                 else_branch,
-            } => todo!(),
-            Stmt::Return { keyword, value } => todo!(),
-            Stmt::While { condition, body } => todo!(),
+            } => {
+                if self.evaluate(*condition)?.is_truthy() {
+                    self.execute(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?;
+                }
+            }
+            Stmt::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => self.evaluate(*value)?,
+                    None => Value::Nil,
+                };
+                return Err(Error::Return { value });
+            }
+            Stmt::Throw { keyword, value } => {
+                let value = self.evaluate(*value)?;
+                return Err(Error::Throw {
+                    value,
+                    line: keyword.line,
+                    trace: StackTrace::default(),
+                });
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                let mut result = self.execute_block(&body.stmts, Scope::wrap(self.scope.clone()));
+                if let (Err(error), Some((name, handler))) = (&result, catch) {
+                    if let Some(value) = error.caught_value() {
+                        let scope = Scope::wrap(self.scope.clone());
+                        self.bind(&scope, name, value);
+                        result = self.execute_block(&handler.stmts, scope);
+                    }
+                }
+                // finally always runs, and an error it raises replaces the pending one
+                if let Some(finally) = finally {
+                    self.execute_block(&finally.stmts, Scope::wrap(self.scope.clone()))?;
+                }
+                result?;
+            }
+            Stmt::While { condition, body } => {
+                while self.evaluate(*condition)?.is_truthy() {
+                    self.execute(body)?;
+                }
+            }
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => match self.evaluate(*iterable)? {
+                // by index, so elements pushed by the body are visited too
+                Value::List(list) => {
+                    let mut index = 0;
+                    loop {
+                        // the borrow must end before the body runs, it may push to the list
+                        let Some(element) = list.borrow().get(index).cloned() else {
+                            break;
+                        };
+                        self.execute_iteration(name, body, element)?;
+                        index += 1;
+                    }
+                }
+                Value::Map(map) => {
+                    let keys: Vec<Value> = map.borrow().keys().map(|k| k.value().clone()).collect();
+                    for key in keys {
+                        self.execute_iteration(name, body, key)?;
+                    }
+                }
+                Value::Range(start, end) => {
+                    // past 2^53 adding 1 can round back down, so the steps are counted instead
+                    if !(-MAX_EXACT..=MAX_EXACT).contains(&start)
+                        || !(-MAX_EXACT..=MAX_EXACT).contains(&end)
+                    {
+                        return Err(Error::runtime(
+                            format!("Range bounds must be between -{MAX_EXACT} and {MAX_EXACT}."),
+                            name.line,
+                        ));
+                    }
+                    let steps = (end - start).ceil().max(0.0) as u64;
+                    for step in 0..steps {
+                        let n = start + step as f64;
+                        self.execute_iteration(name, body, Value::Number(n))?;
+                    }
+                }
+                Value::String(s) => {
+                    for c in s.chars() {
+                        let c = value::intern(c.encode_utf8(&mut [0; 4]));
+                        self.execute_iteration(name, body, Value::String(c))?;
+                    }
+                }
+                _ => {
+                    return Err(Error::runtime(
+                        "Can only iterate over lists, maps, strings and ranges.",
+                        name.line,
+                    ))
+                }
+            },
         }
         Ok(())
     }
 
-    pub fn evaluate(&self, expr: Expr) -> Result<Value, Error> {
-        match expr.kind {
-            ExprKind::Literal(value) => Ok(value.unwrap()),
-            ExprKind::Assign {
-                ref name,
-                ref value,
-            } => {
-                let value = self.evaluate(*value.clone())?;
+    // runs one iteration of a for in loop, with the loop variable in a scope of its own
+    fn execute_iteration(&mut self, name: &Token, body: &Stmt, value: Value) -> Result<(), Error> {
+        let scope = Scope::wrap(self.scope.clone());
+        self.bind(&scope, name, value);
+        self.execute_block(slice::from_ref(body), scope)
+    }
 
-                let _ = self.enviorment.borrow_mut().assign(name, value.to_owned());
+    pub fn evaluate(&mut self, expr: ExprId) -> Result<Value, Error> {
+        // calls swap `self.arena` out, so hold on to the one `expr` belongs to
+        let arena = Rc::clone(&self.arena);
+        match &arena[expr].kind {
+            ExprKind::Literal(value) => Ok(value.clone().unwrap_or(Value::Nil)),
+            ExprKind::Map { brace, entries } => {
+                let mut map = IndexMap::with_capacity(entries.len());
+                for (key, value) in entries {
+                    let key = self.evaluate(*key)?;
+                    let key =
+                        MapKey::new(key).map_err(|message| Error::runtime(message, brace.line))?;
+                    map.insert(key, self.evaluate(*value)?);
+                }
+                Ok(Value::map(map))
+            }
+            ExprKind::Assign { name, value } => {
+                let value = self.evaluate(*value)?;
+
+                match (self.locals.get(expr), &self.scope) {
+                    (Some(slot), Some(scope)) => scope.borrow_mut().assign_at(slot, value.clone()),
+                    _ => self
+                        .globals
+                        .assign(name, value.clone(), self.locals.hint(expr))?,
+                }
+                if let Some(hook) = &self.hook {
+                    hook.borrow_mut().assign(self, name, &value);
+                }
                 Ok(value)
             }
             ExprKind::Binary {
@@ -156,67 +591,51 @@ impl Interpreter {
                 match operator.token_type {
                     TokenType::MINUS => match (left_result, right_result) {
                         (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l - r)),
-                        _ => Err(Error::Runtime {
-                            message: "Operands must be numbers".to_string(),
-                            line: operator.line,
-                        }),
+                        _ => Err(Error::runtime("Operands must be numbers.", operator.line)),
                     },
                     TokenType::SLASH => match (left_result, right_result) {
                         (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l / r)),
-                        _ => Err(Error::Runtime {
-                            message: "Operands must be numbers".to_string(),
-                            line: operator.line,
-                        }),
+                        _ => Err(Error::runtime("Operands must be numbers.", operator.line)),
                     },
                     TokenType::STAR => match (left_result, right_result) {
                         (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l * r)),
-                        _ => Err(Error::Runtime {
-                            message: "Operands must be numbers".to_string(),
-                            line: operator.line,
-                        }),
+                        _ => Err(Error::runtime("Operands must be numbers.", operator.line)),
                     },
                     TokenType::PLUS => match (left_result, right_result) {
                         (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
-                        (Value::String(l), Value::String(r)) => Ok(Value::String(l + &r)),
-                        _ => Err(Error::Runtime {
-                            message: "Operands must be two numbers or two strings".to_string(),
-                            line: operator.line,
-                        }),
+                        (Value::String(l), Value::String(r)) => {
+                            Ok(Value::String(value::concat(&l, &r)))
+                        }
+                        _ => Err(Error::runtime(
+                            "Operands must be two numbers or two strings.",
+                            operator.line,
+                        )),
                     },
                     TokenType::GREATER => match (left_result, right_result) {
                         (Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l > r)),
-                        _ => Err(Error::Runtime {
-                            message: "Operands must be numbers".to_string(),
-                            line: operator.line,
-                        }),
+                        _ => Err(Error::runtime("Operands must be numbers.", operator.line)),
                     },
                     TokenType::GreaterEqual => match (left_result, right_result) {
                         (Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l >= r)),
-                        _ => Err(Error::Runtime {
-                            message: "Operands must be numbers".to_string(),
-                            line: operator.line,
-                        }),
+                        _ => Err(Error::runtime("Operands must be numbers.", operator.line)),
                     },
                     TokenType::LESS => match (left_result, right_result) {
                         (Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l < r)),
-                        _ => Err(Error::Runtime {
-                            message: "Operands must be numbers".to_string(),
-                            line: operator.line,
-                        }),
+                        _ => Err(Error::runtime("Operands must be numbers.", operator.line)),
                     },
                     TokenType::LessEqual => match (left_result, right_result) {
                         (Value::Number(l), Value::Number(r)) => Ok(Value::Boolean(l <= r)),
-                        _ => Err(Error::Runtime {
-                            message: "Operands must be numbers".to_string(),
-                            line: operator.line,
-                        }),
-                    },
-                    TokenType::BangEqual => match (left_result, right_result) {
-                        (l, r) => Ok(Value::Boolean(l != r)),
+                        _ => Err(Error::runtime("Operands must be numbers.", operator.line)),
                     },
-                    TokenType::EqualEqual => match (left_result, right_result) {
-                        (l, r) => Ok(Value::Boolean(l == r)),
+                    TokenType::DotDot => match (left_result, right_result) {
+                        (Value::Number(l), Value::Number(r)) => Ok(Value::Range(l, r)),
+                        _ => Err(Error::runtime(
+                            "Range bounds must be numbers.",
+                            operator.line,
+                        )),
                     },
+                    TokenType::BangEqual => Ok(Value::Boolean(left_result != right_result)),
+                    TokenType::EqualEqual => Ok(Value::Boolean(left_result == right_result)),
                     _ => unreachable!(),
                 }
             }
@@ -224,97 +643,323 @@ impl Interpreter {
                 callee,
                 paren,
                 arguments,
-            } => todo!(),
-            ExprKind::Get { object, name } => todo!(),
+            } => {
+                let callee = self.evaluate(*callee)?;
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.evaluate(*argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let Value::Callable(callable) = callee else {
+                    return Err(Error::runtime(
+                        "Can only call functions and classes.",
+                        paren.line,
+                    ));
+                };
+                if arguments.len() != callable.arity() {
+                    return Err(Error::runtime(
+                        format!(
+                            "Expected {} arguments but got {}.",
+                            callable.arity(),
+                            arguments.len()
+                        ),
+                        paren.line,
+                    ));
+                }
+                self.call(&callable, arguments, paren)
+            }
+            ExprKind::Get { .. } => todo!(),
             ExprKind::Grouping(inner) => self.evaluate(*inner),
+            ExprKind::List(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.evaluate(*element))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Value::list(elements))
+            }
             ExprKind::Logical {
                 left,
                 operator,
                 right,
-            } => todo!(),
-            ExprKind::Set {
+            } => {
+                let left = self.evaluate(*left)?;
+                let short_circuits = match operator.token_type {
+                    TokenType::OR => left.is_truthy(),
+                    TokenType::AND => !left.is_truthy(),
+                    _ => unreachable!(),
+                };
+                if short_circuits {
+                    Ok(left)
+                } else {
+                    self.evaluate(*right)
+                }
+            }
+            ExprKind::Set { .. } => todo!(),
+            ExprKind::Subscript {
                 object,
-                name,
+                bracket,
+                index,
+            } => {
+                let object = self.evaluate(*object)?;
+                let index = self.evaluate(*index)?;
+                let error = |message| Error::runtime(message, bracket.line);
+                match object {
+                    Value::List(list) => {
+                        let list = list.borrow();
+                        let index = value::list_index(&index, list.len()).map_err(error)?;
+                        Ok(list[index].clone())
+                    }
+                    Value::Map(map) => {
+                        let key = MapKey::new(index).map_err(error)?;
+                        let map = map.borrow();
+                        map.get(&key)
+                            .cloned()
+                            .ok_or_else(|| error(format!("Key {} is not in the map.", key.value())))
+                    }
+                    _ => Err(error(NOT_SUBSCRIPTABLE.to_string())),
+                }
+            }
+            ExprKind::SubscriptSet {
+                object,
+                bracket,
+                index,
                 value,
-            } => todo!(),
-            ExprKind::Super { keyword, method } => todo!(),
+            } => {
+                let object = self.evaluate(*object)?;
+                let index = self.evaluate(*index)?;
+                let value = self.evaluate(*value)?;
+                let error = |message| Error::runtime(message, bracket.line);
+                match object {
+                    Value::List(list) => {
+                        // the error shows the index, which can be the list being assigned to
+                        let len = list.borrow().len();
+                        let index = value::list_index(&index, len).map_err(error)?;
+                        list.borrow_mut()[index] = value.clone();
+                    }
+                    Value::Map(map) => {
+                        let key = MapKey::new(index).map_err(error)?;
+                        map.borrow_mut().insert(key, value.clone());
+                    }
+                    _ => return Err(error(NOT_SUBSCRIPTABLE.to_string())),
+                }
+                Ok(value)
+            }
+            ExprKind::Super { .. } => todo!(),
             ExprKind::This(_) => todo!(),
             ExprKind::Unary { operator, right } => {
                 let result = self.evaluate(*right)?;
                 match operator.token_type {
                     TokenType::MINUS => match result {
                         Value::Number(n) => Ok(Value::Number(-n)),
-                        _ => Err(Error::Runtime {
-                            message: "Operand must be a number".to_string(),
-                            line: operator.line,
-                        }),
+                        _ => Err(Error::runtime("Operand must be a number.", operator.line)),
                     },
-                    TokenType::BANG => match result {
-                        Value::Boolean(b) => Ok(Value::Boolean(!b)),
-                        Value::Nil => Ok(Value::Boolean(true)),
-                        _ => Err(Error::Runtime {
-                            message: "Operand must be a boolean".to_string(),
-                            line: operator.line,
-                        }),
+                    TokenType::BANG => Ok(Value::Boolean(!result.is_truthy())),
+                    // the parts of an interpolated string are converted the way print does
+                    TokenType::Interpolation => match result {
+                        Value::String(_) => Ok(result),
+                        _ => Ok(Value::String(Rc::from(result.to_string()))),
                     },
                     _ => unreachable!(),
                 }
             }
-            ExprKind::Variable(ref name) => self.lookup_variable(name, &expr),
+            ExprKind::Variable(name) => self.lookup_variable(name, expr),
         }
     }
 
-    fn lookup_variable(&self, name: &Token, expr: &Expr) -> Result<Value, Error> {
-        self.enviorment.borrow().get(name)
+    fn lookup_variable(&self, name: &Token, expr: ExprId) -> Result<Value, Error> {
+        match (self.locals.get(expr), &self.scope) {
+            (Some(slot), Some(scope)) => Ok(scope.borrow().get_at(slot)),
+            _ => self.globals.lookup(name, self.locals.hint(expr)),
+        }
     }
 
-    fn execute_block(
+    fn define(&mut self, name: &Token, value: Value) {
+        match &self.scope {
+            Some(scope) => self.bind(scope, name, value),
+            None => {
+                if let Some(hook) = &self.hook {
+                    hook.borrow_mut().define(self, name, &value);
+                }
+                self.globals.define(name.lexeme(), value)
+            }
+        }
+    }
+
+    // defines a local, named for the hook if there is one
+    fn bind(&self, scope: &RefCell<Scope>, name: &Token, value: Value) {
+        if let Some(hook) = &self.hook {
+            hook.borrow_mut().define(self, name, &value);
+            scope.borrow_mut().names.push(value::intern(name.lexeme()));
+        }
+        scope.borrow_mut().define(value);
+    }
+
+    // tells the hook about the call and what it returned, if there is one
+    fn call(
         &mut self,
-        stmts: Vec<Stmt>,
-        env: Rc<RefCell<Environment>>,
-    ) -> Result<(), Error> {
-        let prev_env = self.enviorment.clone();
-        self.enviorment = env;
-
-        for stmt in stmts {
-            self.execute(stmt);
+        callable: &Callable,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> Result<Value, Error> {
+        let Some(hook) = self.hook.clone() else {
+            return self.invoke(callable, arguments, paren);
+        };
+        hook.borrow_mut()
+            .call(self, callable, &arguments, paren.line);
+        let result = self.invoke(callable, arguments, paren);
+        hook.borrow_mut().returned(self, callable, result.as_ref());
+        result
+    }
+
+    fn invoke(
+        &mut self,
+        callable: &Callable,
+        arguments: Vec<Value>,
+        paren: &Token,
+    ) -> Result<Value, Error> {
+        match callable {
+            Callable::Native(native) => {
+                (native.function)(&arguments).map_err(|message| Error::runtime(message, paren.line))
+            }
+            Callable::Function(function) => {
+                // raised before the Rust stack runs out, so it can be caught and has a trace
+                if self.frames.len() >= MAX_CALL_DEPTH {
+                    return Err(Error::runtime("Stack overflow.", paren.line));
+                }
+                let scope = Scope::wrap(function.closure.clone());
+                scope.borrow_mut().slots = arguments;
+                if self.hook.is_some() {
+                    let params = function.params.iter();
+                    scope.borrow_mut().names = params.map(|p| value::intern(p.lexeme())).collect();
+                }
+
+                // the body may come from another program, e.g. an earlier line in the REPL
+                let prev_arena = mem::replace(&mut self.arena, Rc::clone(&function.arena));
+                let prev_locals = mem::replace(&mut self.locals, Rc::clone(&function.locals));
+                let caller_file = mem::replace(&mut self.file, Rc::clone(&function.file));
+                self.frames.push(CallFrame {
+                    function: Rc::clone(function),
+                    caller_file,
+                    call_line: paren.line,
+                    caller_scope: self.scope.clone(),
+                });
+                let result = self.execute_block(&function.body, scope);
+
+                let result = match result {
+                    Ok(()) => Ok(Value::Nil),
+                    Err(Error::Return { value }) => Ok(value),
+                    Err(mut error) => {
+                        self.capture_trace(&mut error);
+                        Err(error)
+                    }
+                };
+                let frame = self.frames.pop().expect("call frame pushed above");
+                self.file = frame.caller_file;
+                self.arena = prev_arena;
+                self.locals = prev_locals;
+                result
+            }
         }
+    }
 
-        self.enviorment = prev_env;
-        Ok(())
+    fn execute_block(&mut self, stmts: &[Stmt], scope: Rc<RefCell<Scope>>) -> Result<(), Error> {
+        let prev_scope = self.scope.replace(scope);
+
+        let result = stmts.iter().try_for_each(|stmt| self.execute(stmt));
+
+        self.scope = prev_scope;
+        result
     }
 }
 
 // add tests for this module
 #[cfg(test)]
 mod test {
-    use std::{cell::RefCell, rc::Rc};
+    use std::rc::Rc;
 
     use crate::{
-        expression::{Expr, ExprKind},
-        interpreter::{Environment, Interpreter},
+        expression::{ExprArena, ExprId, ExprKind},
+        interpreter::{Error, Interpreter, STACK_SIZE},
+        parser::Parser,
+        resolver::Resolver,
+        scanner::Scanner,
         token::{Token, TokenType},
         value::Value,
     };
 
+    // runs a program like `interpret` and hands back the interpreter to look at its globals,
+    // errors raised outside of any function are left without a trace
+    fn run(source: &str) -> (Interpreter, Result<(), Error>) {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.scan_tokens();
+        let program = Parser::new(&scanner.tokens).parse().unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.locals = Rc::new(Resolver::new(&program.arena).resolve(&program.stmts));
+        interpreter.arena = Rc::new(program.arena);
+        let result = program
+            .stmts
+            .iter()
+            .try_for_each(|stmt| interpreter.execute(stmt));
+        (interpreter, result)
+    }
+
+    fn global(interpreter: &Interpreter, name: &str) -> Value {
+        let token = Token::new(TokenType::IDENTIFIER, name.to_string(), None, 0);
+        interpreter.globals.get(&token).unwrap()
+    }
+
+    fn literal(arena: &mut ExprArena, value: Value) -> ExprId {
+        arena.alloc(ExprKind::Literal(Some(value)))
+    }
+
+    fn evaluate_unary(operator: TokenType, lexeme: &str, right: Value) -> Value {
+        let mut arena = ExprArena::new();
+        let right = literal(&mut arena, right);
+        let expr = arena.alloc(ExprKind::Unary {
+            operator: Token::new(operator, lexeme.to_string(), None, 1),
+            right,
+        });
+        Interpreter::with_arena(arena).evaluate(expr).unwrap()
+    }
+
+    fn evaluate_binary(left: Value, operator: TokenType, lexeme: &str, right: Value) -> Value {
+        let mut arena = ExprArena::new();
+        let left = literal(&mut arena, left);
+        let right = literal(&mut arena, right);
+        let expr = arena.alloc(ExprKind::Binary {
+            left,
+            operator: Token::new(operator, lexeme.to_string(), None, 1),
+            right,
+        });
+        Interpreter::with_arena(arena).evaluate(expr).unwrap()
+    }
+
     // Add a test that tests evaluating a literal expression
     #[test]
     fn test_evaluating_literal() {
-        let interpreter = Interpreter::new();
-        let expr = super::Expr::new(super::ExprKind::Literal(Some(super::Value::Number(5.0))));
+        let mut arena = ExprArena::new();
+        let expr = literal(&mut arena, Value::Number(5.0));
+        let mut interpreter = Interpreter::with_arena(arena);
         let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Number(5.0));
+        assert_eq!(result, Value::Number(5.0));
+
+        // `nil` is the literal without a value
+        let mut arena = ExprArena::new();
+        let expr = arena.alloc(ExprKind::Literal(None));
+        let result = Interpreter::with_arena(arena).evaluate(expr).unwrap();
+        assert_eq!(result, Value::Nil);
     }
 
     // Add a test that tests evaluating a grouping expression
     #[test]
     fn test_evaluating_grouping() {
-        let interpreter = super::Interpreter::new();
-        let expr = super::Expr::new(super::ExprKind::Grouping(Box::new(super::Expr::new(
-            super::ExprKind::Literal(Some(super::Value::Number(5.0))),
-        ))));
+        let mut arena = ExprArena::new();
+        let inner = literal(&mut arena, Value::Number(5.0));
+        let expr = arena.alloc(ExprKind::Grouping(inner));
+        let mut interpreter = Interpreter::with_arena(arena);
         let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Number(5.0));
+        assert_eq!(result, Value::Number(5.0));
     }
 
     /**
@@ -323,39 +968,25 @@ mod test {
      *
      * Also, test the unary bang (!) operator case. !Nil should be true
      * !false should be true
-     * !true should be false
+     * !true should be false, and so should ! of any other value
      */
     #[test]
     fn test_evaluating_unary() {
-        let interpreter = super::Interpreter::new();
-        let expr = super::Expr::new(super::ExprKind::Unary {
-            operator: super::Token::new(super::TokenType::MINUS, "-".to_string(), None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-        });
-        let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Number(-5.0));
+        let result = evaluate_unary(TokenType::MINUS, "-", Value::Number(5.0));
+        assert_eq!(result, Value::Number(-5.0));
 
-        let expr = super::Expr::new(super::ExprKind::Unary {
-            operator: super::Token::new(super::TokenType::BANG, "!".to_string(), None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Nil,
-            )))),
-        });
+        let result = evaluate_unary(TokenType::BANG, "!", Value::Nil);
+        assert_eq!(result, Value::Boolean(true));
 
-        let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Boolean(true));
+        let result = evaluate_unary(TokenType::BANG, "!", Value::Boolean(false));
+        assert_eq!(result, Value::Boolean(true));
 
-        let expr = super::Expr::new(super::ExprKind::Unary {
-            operator: super::Token::new(super::TokenType::BANG, "!".to_string(), None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Boolean(false),
-            )))),
-        });
+        // every value other than nil and false is truthy
+        let result = evaluate_unary(TokenType::BANG, "!", Value::Number(5.0));
+        assert_eq!(result, Value::Boolean(false));
 
-        let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Boolean(true));
+        let result = evaluate_unary(TokenType::BANG, "!", Value::String("str".into()));
+        assert_eq!(result, Value::Boolean(false));
     }
 
     /**
@@ -363,54 +994,18 @@ mod test {
      */
     #[test]
     fn test_evaluating_binary() {
-        let interpreter = super::Interpreter::new();
-        let expr = super::Expr::new(super::ExprKind::Binary {
-            left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-            operator: super::Token::new(super::TokenType::MINUS, "-".to_string(), None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-        });
-        let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Number(0.0));
-
-        let expr = super::Expr::new(super::ExprKind::Binary {
-            left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-            operator: super::Token::new(super::TokenType::SLASH, "/".to_string(), None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-        });
-        let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Number(1.0));
-
-        let expr = super::Expr::new(super::ExprKind::Binary {
-            left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-            operator: super::Token::new(super::TokenType::STAR, "*".to_string(), None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-        });
-        let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Number(25.0));
-
-        let expr = super::Expr::new(super::ExprKind::Binary {
-            left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-            operator: super::Token::new(super::TokenType::PLUS, "+".to_string(), None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-        });
-        let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Number(10.0));
+        let five = || Value::Number(5.0);
+        let result = evaluate_binary(five(), TokenType::MINUS, "-", five());
+        assert_eq!(result, Value::Number(0.0));
+
+        let result = evaluate_binary(five(), TokenType::SLASH, "/", five());
+        assert_eq!(result, Value::Number(1.0));
+
+        let result = evaluate_binary(five(), TokenType::STAR, "*", five());
+        assert_eq!(result, Value::Number(25.0));
+
+        let result = evaluate_binary(five(), TokenType::PLUS, "+", five());
+        assert_eq!(result, Value::Number(10.0));
     }
 
     /**
@@ -418,92 +1013,40 @@ mod test {
      */
     #[test]
     fn test_evaluating_binary_comparison() {
-        let interpreter = super::Interpreter::new();
-        let expr = super::Expr::new(super::ExprKind::Binary {
-            left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-            operator: super::Token::new(super::TokenType::GREATER, ">".to_string(), None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-        });
-        let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Boolean(false));
-
-        let expr = super::Expr::new(super::ExprKind::Binary {
-            left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-            operator: super::Token::new(super::TokenType::GreaterEqual, ">=".to_string(), None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-        });
-        let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Boolean(true));
-
-        let expr = super::Expr::new(super::ExprKind::Binary {
-            left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-            operator: super::Token::new(super::TokenType::LESS, "<".to_string(), None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-        });
-        let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Boolean(false));
-
-        let expr = super::Expr::new(super::ExprKind::Binary {
-            left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-            operator: super::Token::new(super::TokenType::LessEqual, "<=".to_string(), None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-        });
-        let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Boolean(true));
-
-        let expr = super::Expr::new(super::ExprKind::Binary {
-            left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-            operator: super::Token::new(super::TokenType::BangEqual, "!=".to_string(), None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(
-                super::Value::Number(5.0),
-            )))),
-        });
-        let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Boolean(false));
+        let five = || Value::Number(5.0);
+        let result = evaluate_binary(five(), TokenType::GREATER, ">", five());
+        assert_eq!(result, Value::Boolean(false));
+
+        let result = evaluate_binary(five(), TokenType::GreaterEqual, ">=", five());
+        assert_eq!(result, Value::Boolean(true));
+
+        let result = evaluate_binary(five(), TokenType::LESS, "<", five());
+        assert_eq!(result, Value::Boolean(false));
+
+        let result = evaluate_binary(five(), TokenType::LessEqual, "<=", five());
+        assert_eq!(result, Value::Boolean(true));
+
+        let result = evaluate_binary(five(), TokenType::BangEqual, "!=", five());
+        assert_eq!(result, Value::Boolean(false));
 
         // test that nil is equal to nil
-        let expr = super::Expr::new(super::ExprKind::Binary {
-            left: Box::new(super::Expr::new(super::ExprKind::Literal(Some(Value::Nil)))),
-            operator: super::Token::new(super::TokenType::EqualEqual, "==".to_string(), None, 1),
-            right: Box::new(super::Expr::new(super::ExprKind::Literal(Some(Value::Nil)))),
-        });
-        let result = interpreter.evaluate(expr).unwrap();
-        assert_eq!(result, super::Value::Boolean(true));
+        let result = evaluate_binary(Value::Nil, TokenType::EqualEqual, "==", Value::Nil);
+        assert_eq!(result, Value::Boolean(true));
     }
 
     #[test]
     fn test_variable_expression() {
-        // Create an interpreter using the default implementation
-        let interpreter = Interpreter::default();
+        // Create a variable expression that refers to "x"
+        let mut arena = ExprArena::new();
+        let var_name = Token::new(TokenType::VAR, "x".to_string(), Some("x".to_string()), 0);
+        let var_expr = arena.alloc(ExprKind::Variable(var_name.clone()));
+
+        // Create an interpreter that evaluates expressions from that arena
+        let mut interpreter = Interpreter::with_arena(arena);
 
         // Define a variable "x" with an initial value 10 in the environment
         let initial_value = Value::Number(10.0);
-        let var_name = Token::new(TokenType::VAR, "x".to_string(), Some("x".to_string()), 0);
-        interpreter
-            .enviorment
-            .borrow_mut()
-            .define("x", initial_value.clone());
-
-        // Create a variable expression that refers to "x"
-        let var_expr = Expr::new(ExprKind::Variable(var_name.clone()));
+        interpreter.globals.define("x", initial_value.clone());
 
         // Evaluate the variable expression
         let result = interpreter.evaluate(var_expr).unwrap();
@@ -514,9 +1057,6 @@ mod test {
 
     #[test]
     fn test_var_initialization_statement() {
-        // Create an interpreter using the default implementation
-        let mut interpreter = Interpreter::default();
-
         // Define a token for the variable name
         let var_name = Token::new(TokenType::VAR, "y".to_string(), Some("y".to_string()), 0);
 
@@ -524,18 +1064,390 @@ mod test {
         let initial_value = Value::Number(42.0);
 
         // Create a variable declaration statement
+        let mut arena = ExprArena::new();
         let var_stmt = super::Stmt::Var {
             name: var_name.clone(),
-            initializer: Some(Expr::new(ExprKind::Literal(Some(initial_value.clone())))),
+            initializer: Some(literal(&mut arena, initial_value.clone())),
         };
 
         // Execute the statement
-        interpreter.execute(var_stmt).unwrap();
+        let mut interpreter = Interpreter::with_arena(arena);
+        interpreter.execute(&var_stmt).unwrap();
 
         // Check that the variable "y" has been correctly initialized in the environment
-        let result = interpreter.enviorment.borrow().get(&var_name).unwrap();
+        let result = interpreter.globals.get(&var_name).unwrap();
         assert_eq!(result, initial_value);
     }
+
+    #[test]
+    fn test_loops() {
+        let (interpreter, result) = run("var sum = 0;
+            for (var i = 0; i < 10; i = i + 1) { sum = sum + i; }
+            var n = 0;
+            while (n < 3) n = n + 1;");
+        result.unwrap();
+        assert_eq!(global(&interpreter, "sum"), Value::Number(45.0));
+        assert_eq!(global(&interpreter, "n"), Value::Number(3.0));
+    }
+
+    #[test]
+    fn test_if_and_logical_operators() {
+        let (interpreter, result) = run("var a; var b;
+            if (nil or 0) a = \"yes\"; else a = \"no\";
+            if (true and nil) b = 1; else b = 2;
+            var c = false or \"c\";
+            var d; if (\"\") d = !0; var e = 0 and [];
+            var nan = 0 / 0; var f = [nan == nan, 0 == -0, nil == false, [] == []];");
+        result.unwrap();
+        assert_eq!(global(&interpreter, "d"), Value::Boolean(false));
+        assert_eq!(global(&interpreter, "e").to_string(), "[]");
+        assert_eq!(
+            global(&interpreter, "f").to_string(),
+            "[false, true, false, false]"
+        );
+        assert_eq!(global(&interpreter, "a"), Value::String("yes".into()));
+        assert_eq!(global(&interpreter, "b"), Value::Number(2.0));
+        assert_eq!(global(&interpreter, "c"), Value::String("c".into()));
+    }
+
+    #[test]
+    fn test_recursive_function() {
+        let (interpreter, result) = run(
+            "fun fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); }
+            var f = fib(10);
+            fun noop() {}
+            var nothing = noop();",
+        );
+        result.unwrap();
+        assert_eq!(global(&interpreter, "f"), Value::Number(55.0));
+        assert_eq!(global(&interpreter, "nothing"), Value::Nil);
+    }
+
+    #[test]
+    fn test_closures() {
+        let (interpreter, result) = run("fun makeCounter() {
+                var i = 0;
+                fun count() { i = i + 1; return i; }
+                return count;
+            }
+            var counter = makeCounter();
+            counter();
+            var second = counter();");
+        result.unwrap();
+        assert_eq!(global(&interpreter, "second"), Value::Number(2.0));
+        assert_eq!(
+            global(&interpreter, "counter").to_string(),
+            "<fn count>".to_string()
+        );
+    }
+
+    #[test]
+    fn test_block_scopes() {
+        let (interpreter, result) = run("var a = 1; var b;
+            { var a = a + 2; { var a = a * 10; b = a; } a = a + 1; b = b + a; }");
+        result.unwrap();
+        assert_eq!(global(&interpreter, "a"), Value::Number(1.0));
+        assert_eq!(global(&interpreter, "b"), Value::Number(34.0));
+    }
+
+    #[test]
+    fn test_lists() {
+        let (interpreter, result) = run("var xs = [1, 2, 3];
+            var alias = xs;
+            alias[0] = xs[2] * 10;
+            push(xs, [4]);
+            var first = xs[0];
+            var nested = xs[3][0];
+            var size = len(xs);");
+        result.unwrap();
+        assert_eq!(global(&interpreter, "first"), Value::Number(30.0));
+        assert_eq!(global(&interpreter, "nested"), Value::Number(4.0));
+        assert_eq!(global(&interpreter, "size"), Value::Number(4.0));
+        assert_eq!(global(&interpreter, "xs").to_string(), "[30, 2, 3, [4]]");
+    }
+
+    #[test]
+    fn test_list_errors() {
+        let (_, result) = run("var xs = [1];\nprint xs[1];");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Index 1 out of range for list of length 1.\n[line 2]"
+        );
+
+        let (_, result) = run("var xs = [];\n\nxs[\"a\"] = 1;");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: List index must be a whole number, got a.\n[line 3]"
+        );
+
+        let (_, result) = run("var n = 1; n[0];");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Only lists and maps can be subscripted.\n[line 1]"
+        );
+
+        let (_, result) = run("pop([]);");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Can't pop from an empty list.\n[line 1]"
+        );
+
+        // the error shows the index while the list it is about is being assigned to
+        let (interpreter, result) = run("var xs = [1];
+            var caught;
+            try { xs[xs] = 2; } catch (e) { caught = e[\"message\"]; }");
+        result.unwrap();
+        assert_eq!(
+            global(&interpreter, "caught").to_string(),
+            "List index must be a whole number, got [1]."
+        );
+    }
+
+    #[test]
+    fn test_maps() {
+        let (interpreter, result) = run(
+            "var config = {\"name\": \"lox\", \"tags\": [], 0: \"zero\"};
+            config[\"version\"] = 2;
+            config[\"name\"] = config[\"name\"] + \"!\";
+            push(config[\"tags\"], \"fast\");
+            var zero = config[-0];
+            var found = has(config, \"version\") and !has(config, \"missing\");
+            var names = keys(config);",
+        );
+        result.unwrap();
+        assert_eq!(global(&interpreter, "zero"), Value::String("zero".into()));
+        assert_eq!(global(&interpreter, "found"), Value::Boolean(true));
+        assert_eq!(
+            global(&interpreter, "names").to_string(),
+            "[\"name\", \"tags\", 0, \"version\"]"
+        );
+        assert_eq!(
+            global(&interpreter, "config").to_string(),
+            "{\"name\": \"lox!\", \"tags\": [\"fast\"], 0: \"zero\", \"version\": 2}"
+        );
+    }
+
+    #[test]
+    fn test_map_errors() {
+        let (_, result) = run("var m = {};\nprint m[\"a\"];");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Key a is not in the map.\n[line 2]"
+        );
+
+        let (_, result) = run("var m = {[]: 1};");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Map keys must be nil, booleans, numbers or strings, got [].\n[line 1]"
+        );
+    }
+
+    #[test]
+    fn test_for_in_loops() {
+        let (interpreter, result) = run("var sum = 0;
+            for (var i in 1..5) sum = sum + i;
+            var xs = [1, 2];
+            var seen = [];
+            for (var x in xs) { if (x < 3) push(xs, x + 2); push(seen, x); }
+            var letters = \"\";
+            for (var c in \"héy\") letters = c + letters;
+            var keys = [];
+            for (var k in {\"a\": 1, \"b\": 2}) push(keys, k);
+            var fns = [];
+            for (var i in 0..3) { fun f() { return i; } push(fns, f); }
+            var captured = fns[0]() + fns[2]();");
+        result.unwrap();
+        assert_eq!(global(&interpreter, "sum"), Value::Number(10.0));
+        assert_eq!(global(&interpreter, "seen").to_string(), "[1, 2, 3, 4]");
+        assert_eq!(global(&interpreter, "letters"), Value::String("yéh".into()));
+        assert_eq!(global(&interpreter, "keys").to_string(), "[\"a\", \"b\"]");
+        // each iteration binds a new variable, so closures see their own value
+        assert_eq!(global(&interpreter, "captured"), Value::Number(2.0));
+    }
+
+    #[test]
+    fn test_for_in_errors() {
+        let (_, result) = run("\nfor (var x in 1) print x;");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Can only iterate over lists, maps, strings and ranges.\n[line 2]"
+        );
+
+        let (_, result) = run("for (var x in 0..\"a\") print x;");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Range bounds must be numbers.\n[line 1]"
+        );
+
+        // counting up from 2^53 by adding 1 would never get to the end
+        let (_, result) = run("for (var i in 9007199254740992..9007199254740994) print i;");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Range bounds must be between -9007199254740992 and 9007199254740992.\n[line 1]"
+        );
+        let (interpreter, result) = run("var n = 0;\nfor (var i in 0.5..3) n = n + i;");
+        result.unwrap();
+        assert_eq!(global(&interpreter, "n"), Value::Number(4.5));
+    }
+
+    #[test]
+    fn test_call_errors() {
+        let (_, result) = run("fun f(a) {}\nf(1, 2);");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Expected 1 arguments but got 2.\n[line 2]"
+        );
+
+        let (_, result) = run("\"text\"();");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Can only call functions and classes.\n[line 1]"
+        );
+
+        let (_, result) = run("undefined = 1;");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_try_catch() {
+        let (interpreter, result) = run("var caught;
+            try { throw [1]; } catch (e) { caught = e; }
+            var error;
+            try { print 1 - \"a\"; } catch (e) { error = e; }
+            var message = error[\"message\"];
+            var line = error[\"line\"];
+            var order = [];
+            fun f() {
+                try { return 1; } finally { push(order, \"finally\"); }
+            }
+            push(order, f());
+            try {
+                try { throw \"inner\"; } finally { push(order, \"cleanup\"); }
+            } catch (e) { push(order, e); }
+            try { throw 1; } catch (e) { try { throw e + 1; } catch (e) { push(order, e); } }");
+        result.unwrap();
+        assert_eq!(global(&interpreter, "caught").to_string(), "[1]");
+        assert_eq!(
+            global(&interpreter, "message"),
+            Value::String("Operands must be numbers.".into())
+        );
+        assert_eq!(global(&interpreter, "line"), Value::Number(4.0));
+        assert_eq!(
+            global(&interpreter, "order").to_string(),
+            "[\"finally\", 1, \"cleanup\", \"inner\", 2]"
+        );
+    }
+
+    #[test]
+    fn test_uncaught_throw() {
+        let (_, result) = run("fun f() { throw \"boom\"; }\ntry { f(); } finally {}");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Uncaught boom\n  at f (<stdin>:1)\n  at <script> (<stdin>:2)"
+        );
+
+        // a rethrow from the handler and an error in finally both propagate
+        let (_, result) = run("try { throw 1; } catch (e) { throw e + 1; }");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Uncaught 2\n[line 1]"
+        );
+        let (_, result) = run("try { throw 1; } catch (e) {} finally { throw 3; }");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Uncaught 3\n[line 1]"
+        );
+    }
+
+    #[test]
+    fn test_stack_traces() {
+        let mut scanner = Scanner::new(
+            "fun add(a, b) {\n  return a + b;\n}\nfun main() {\n  add(1, nil);\n}\nmain();"
+                .to_string(),
+        );
+        scanner.scan_tokens();
+        let program = Parser::new(&scanner.tokens).parse().unwrap();
+        let mut interpreter = Interpreter::with_file("math.lox");
+        let error = interpreter.interpret(program).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Error: Operands must be two numbers or two strings.
+  at add (math.lox:2)
+  at main (math.lox:5)
+  at <script> (math.lox:7)"
+        );
+        let frames: Vec<_> = error
+            .trace()
+            .iter()
+            .map(|frame| (frame.function.to_string(), frame.line))
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                ("add".to_string(), 2),
+                ("main".to_string(), 5),
+                ("<script>".to_string(), 7)
+            ]
+        );
+        // every frame is popped once the error has propagated
+        assert!(interpreter.frames.is_empty());
+    }
+
+    #[test]
+    fn test_stack_overflow() {
+        // the test thread's stack is too small for `MAX_CALL_DEPTH` calls
+        let overflow = std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn(|| {
+                let (interpreter, result) = run("\
+fun f(n) { return f(n + 1); }
+var caught;
+try { f(0); } catch (e) { caught = e[\"message\"]; }");
+                let caught = global(&interpreter, "caught");
+                (caught.to_string(), result.is_ok(), interpreter.frames.len())
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(overflow, ("Stack overflow.".to_string(), true, 0));
+    }
+
+    #[test]
+    fn test_undefined_variable_suggestions() {
+        let (_, result) = run("var counter = 0;\nprint countr;");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Undefined variable 'countr'.\nhelp: did you mean 'counter'?\n[line 2]"
+        );
+
+        // locals have no names at runtime, the resolver's hint supplies them
+        let (_, result) = run("fun f(total) { totl = 1; }\nf(0);");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Undefined variable 'totl'.\nhelp: did you mean 'total'?
+  at f (<stdin>:1)
+  at <script> (<stdin>:2)"
+        );
+
+        let (_, result) = run("print lne([]);");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("did you mean 'len'?"));
+
+        let (_, result) = run("print nothing;");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Error: Undefined variable 'nothing'.\n[line 1]"
+        );
+    }
+
+    #[test]
+    fn test_native_clock() {
+        let (interpreter, result) = run("var now = clock();");
+        result.unwrap();
+        assert!(matches!(global(&interpreter, "now"), Value::Number(n) if n > 0.0));
+    }
 }
 
 // Unit tests
@@ -546,7 +1458,7 @@ mod tests {
     #[test]
     fn test_define_and_get() {
         let mut env = Environment::default();
-        let value1 = Value::String("1234".to_string());
+        let value1 = Value::String("1234".into());
 
         env.define("z", value1.clone());
         let token = Token::new(TokenType::VAR, "z".to_string(), Some("z".to_string()), 0);
@@ -563,7 +1475,7 @@ mod tests {
         let result = env.get(&token);
 
         assert!(result.is_err());
-        if let Err(Error::Runtime { message, line }) = result {
+        if let Err(Error::Runtime { line, .. }) = result {
             assert_eq!(line, 0);
         } else {
             panic!("Expected runtime error for undefined variable.");
@@ -573,8 +1485,8 @@ mod tests {
     #[test]
     fn test_redefine_variable() {
         let mut env = Environment::default();
-        let value1 = Value::String("1234".to_string());
-        let value2 = Value::String("1".to_string());
+        let value1 = Value::String("1234".into());
+        let value2 = Value::String("1".into());
 
         env.define("z", value1);
         env.define("z", value2.clone());
@@ -0,0 +1,494 @@
+/**
+* JSON export of scanner and parser output, for tools that are not written in Rust.
+*
+* The schema is versioned by the top level `version` field. Fields are only ever added
+* within a version, so consumers should ignore keys they do not know about.
+*
+* Tokens (`intepreter tokens --json`):
+*
+*  { "version": 1, "tokens": [ token* ] }
+*  token    → { "type": TOKEN_TYPE, "lexeme": string, "literal": literal, "span": span,
+*               "doc"?: string }
+*  span     → { "start": byte offset, "end": byte offset (exclusive), "line": number }
+*  literal  → number for NUMBER tokens, string for STRING tokens, null for everything else
+*
+* JSON has no infinities or NaN, numbers that aren't finite, like `1e999` or the result of
+* `0 / 0`, are written as { "number": "inf" | "-inf" | "nan" } so they can't be taken for nil.
+*
+* "doc" is only present on tokens preceded by `///` doc comments, and holds their lines
+* joined with "\n". In the syntax tree the doc comment of a declaration is on its name.
+*
+* TOKEN_TYPE is the name of the `TokenType` variant, e.g. "LeftParen", "NUMBER" or "EOF".
+*
+* Syntax tree (`intepreter ast --json`):
+*
+*  { "version": 1, "statements": [ stmt* ] }
+*
+* Every node is an object with a "type" tag naming the `Stmt` or `ExprKind` variant.
+* Expression nodes also carry an "id", their index in the parser's `ExprArena`:
+*
+*  stmt →  { "type": "Block", "brace": token, "statements": [ stmt* ] }
+*        | { "type": "Class", "name": token, "superclass": expr | null, "methods": [ stmt* ] }
+*        | { "type": "Expression", "expression": expr }
+*        | { "type": "ForIn", "name": token, "iterable": expr, "body": stmt }
+*        | { "type": "Function", "name": token, "params": [ token* ], "body": [ stmt* ] }
+*        | { "type": "If", "condition": expr, "then": stmt, "else": stmt | null }
+*        | { "type": "Print", "keyword": token, "expression": expr }
+*        | { "type": "Return", "keyword": token, "value": expr | null }
+*        | { "type": "Throw", "keyword": token, "value": expr }
+*        | { "type": "Try", "body": [ stmt* ], "catch": { "name": token, "body": [ stmt* ] } | null,
+*            "finally": [ stmt* ] | null }
+*        | { "type": "Var", "name": token, "initializer": expr | null }
+*        | { "type": "While", "condition": expr, "body": stmt }
+*
+*  expr →  { "type": "Assign", "name": token, "value": expr }
+*        | { "type": "Binary", "left": expr, "operator": token, "right": expr }
+*        | { "type": "Call", "callee": expr, "paren": token, "arguments": [ expr* ] }
+*        | { "type": "Get", "object": expr, "name": token }
+*        | { "type": "Grouping", "expression": expr }
+*        | { "type": "List", "elements": [ expr* ] }
+*        | { "type": "Literal", "value": number | string | boolean | null }
+*        | { "type": "Logical", "left": expr, "operator": token, "right": expr }
+*        | { "type": "Map", "brace": token, "entries": [ { "key": expr, "value": expr }* ] }
+*        | { "type": "Set", "object": expr, "name": token, "value": expr }
+*        | { "type": "Subscript", "object": expr, "bracket": token, "index": expr }
+*        | { "type": "SubscriptSet", "object": expr, "bracket": token, "index": expr, "value": expr }
+*        | { "type": "Super", "keyword": token, "method": token }
+*        | { "type": "This", "keyword": token }
+*        | { "type": "Unary", "operator": token, "right": expr }
+*        | { "type": "Variable", "name": token }
+*/
+use std::rc::Rc;
+
+use serde_json::{json, Value as Json};
+
+use crate::{
+    expression::{ExprArena, ExprId, ExprKind, Program, Stmt},
+    token::{Token, TokenType},
+    value::Value,
+};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+pub fn tokens_to_json(tokens: &[Token]) -> Json {
+    json!({
+        "version": SCHEMA_VERSION,
+        "tokens": tokens.iter().map(token_to_json).collect::<Vec<_>>(),
+    })
+}
+
+pub fn program_to_json(program: &Program) -> Json {
+    json!({
+        "version": SCHEMA_VERSION,
+        "statements": stmts_to_json(&program.arena, &program.stmts),
+    })
+}
+
+pub fn token_to_json(token: &Token) -> Json {
+    let literal = match token.token_type {
+        TokenType::NUMBER | TokenType::STRING => Value::from_token(token.clone())
+            .map(|value| value_to_json(&value))
+            .unwrap_or(Json::Null),
+        _ => Json::Null,
+    };
+    let mut json = json!({
+        "type": token.token_type.to_string(),
+        "lexeme": token.lexeme(),
+        "literal": literal,
+        "span": {
+            "start": token.span.start,
+            "end": token.span.end,
+            "line": token.line,
+        },
+    });
+    if let Some(doc) = &token.doc {
+        json["doc"] = json!(doc.as_ref());
+    }
+    json
+}
+
+/// A value as JSON. A list or map that contains itself is written as the string "[...]" or
+/// "{...}" where it repeats, the way `print` shows it.
+pub(crate) fn value_to_json(value: &Value) -> Json {
+    value_json(value, &mut vec![])
+}
+
+// `writing` holds the lists and maps being written, like `printing` in `value::write_value`
+fn value_json(value: &Value, writing: &mut Vec<usize>) -> Json {
+    match value {
+        Value::Boolean(b) => json!(b),
+        Value::Callable(c) => json!(c.to_string()),
+        Value::List(list) => {
+            let address = Rc::as_ptr(list) as usize;
+            if writing.contains(&address) {
+                return json!("[...]");
+            }
+            writing.push(address);
+            let elements = list
+                .borrow()
+                .iter()
+                .map(|element| value_json(element, writing))
+                .collect();
+            writing.pop();
+            Json::Array(elements)
+        }
+        // JSON only has string keys, other keys are written the way `print` shows them
+        Value::Map(map) => {
+            let address = Rc::as_ptr(map) as usize;
+            if writing.contains(&address) {
+                return json!("{...}");
+            }
+            writing.push(address);
+            let entries = map
+                .borrow()
+                .iter()
+                .map(|(key, value)| (key.value().to_string(), value_json(value, writing)))
+                .collect();
+            writing.pop();
+            Json::Object(entries)
+        }
+        Value::Nil => Json::Null,
+        Value::Range(start, end) => json!({ "start": start, "end": end }),
+        Value::Number(n) => number_to_json(*n),
+        Value::String(s) => json!(&**s),
+    }
+}
+
+fn number_to_json(n: f64) -> Json {
+    match n {
+        f64::INFINITY => json!({ "number": "inf" }),
+        f64::NEG_INFINITY => json!({ "number": "-inf" }),
+        n if n.is_nan() => json!({ "number": "nan" }),
+        n => json!(n),
+    }
+}
+
+fn stmts_to_json(arena: &ExprArena, stmts: &[Stmt]) -> Vec<Json> {
+    stmts.iter().map(|stmt| stmt_to_json(arena, stmt)).collect()
+}
+
+fn tokens_to_list(tokens: &[Token]) -> Vec<Json> {
+    tokens.iter().map(token_to_json).collect()
+}
+
+pub fn stmt_to_json(arena: &ExprArena, stmt: &Stmt) -> Json {
+    match stmt {
+        Stmt::Block(block) => json!({
+            "type": "Block",
+            "brace": token_to_json(&block.brace),
+            "statements": stmts_to_json(arena, &block.stmts),
+        }),
+        Stmt::Class {
+            name,
+            superclass,
+            methods,
+        } => json!({
+            "type": "Class",
+            "name": token_to_json(name),
+            "superclass": superclass.map(|expr| expr_to_json(arena, expr)),
+            "methods": stmts_to_json(arena, methods),
+        }),
+        Stmt::Expression(expr) => json!({
+            "type": "Expression",
+            "expression": expr_to_json(arena, *expr),
+        }),
+        Stmt::ForIn {
+            name,
+            iterable,
+            body,
+        } => json!({
+            "type": "ForIn",
+            "name": token_to_json(name),
+            "iterable": expr_to_json(arena, *iterable),
+            "body": stmt_to_json(arena, body),
+        }),
+        Stmt::Function { name, params, body } => json!({
+            "type": "Function",
+            "name": token_to_json(name),
+            "params": tokens_to_list(params),
+            "body": stmts_to_json(arena, body),
+        }),
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => json!({
+            "type": "If",
+            "condition": expr_to_json(arena, *condition),
+            "then": stmt_to_json(arena, then_branch),
+            "else": else_branch.as_deref().map(|stmt| stmt_to_json(arena, stmt)),
+        }),
+        Stmt::Print { keyword, value } => json!({
+            "type": "Print",
+            "keyword": token_to_json(keyword),
+            "expression": expr_to_json(arena, *value),
+        }),
+        Stmt::Return { keyword, value } => json!({
+            "type": "Return",
+            "keyword": token_to_json(keyword),
+            "value": value.map(|expr| expr_to_json(arena, expr)),
+        }),
+        Stmt::Throw { keyword, value } => json!({
+            "type": "Throw",
+            "keyword": token_to_json(keyword),
+            "value": expr_to_json(arena, *value),
+        }),
+        Stmt::Try {
+            body,
+            catch,
+            finally,
+        } => json!({
+            "type": "Try",
+            "body": stmts_to_json(arena, &body.stmts),
+            "catch": catch.as_ref().map(|(name, body)| json!({
+                "name": token_to_json(name),
+                "body": stmts_to_json(arena, &body.stmts),
+            })),
+            "finally": finally.as_ref().map(|body| stmts_to_json(arena, &body.stmts)),
+        }),
+        Stmt::Var { name, initializer } => json!({
+            "type": "Var",
+            "name": token_to_json(name),
+            "initializer": initializer.map(|expr| expr_to_json(arena, expr)),
+        }),
+        Stmt::While { condition, body } => json!({
+            "type": "While",
+            "condition": expr_to_json(arena, *condition),
+            "body": stmt_to_json(arena, body),
+        }),
+    }
+}
+
+pub fn expr_to_json(arena: &ExprArena, expr: ExprId) -> Json {
+    let mut json = match &arena[expr].kind {
+        ExprKind::Assign { name, value } => json!({
+            "type": "Assign",
+            "name": token_to_json(name),
+            "value": expr_to_json(arena, *value),
+        }),
+        ExprKind::Binary {
+            left,
+            operator,
+            right,
+        } => json!({
+            "type": "Binary",
+            "left": expr_to_json(arena, *left),
+            "operator": token_to_json(operator),
+            "right": expr_to_json(arena, *right),
+        }),
+        ExprKind::Call {
+            callee,
+            paren,
+            arguments,
+        } => json!({
+            "type": "Call",
+            "callee": expr_to_json(arena, *callee),
+            "paren": token_to_json(paren),
+            "arguments": arguments.iter().map(|expr| expr_to_json(arena, *expr)).collect::<Vec<_>>(),
+        }),
+        ExprKind::Get { object, name } => json!({
+            "type": "Get",
+            "object": expr_to_json(arena, *object),
+            "name": token_to_json(name),
+        }),
+        ExprKind::Grouping(expression) => json!({
+            "type": "Grouping",
+            "expression": expr_to_json(arena, *expression),
+        }),
+        ExprKind::List(elements) => json!({
+            "type": "List",
+            "elements": elements.iter().map(|expr| expr_to_json(arena, *expr)).collect::<Vec<_>>(),
+        }),
+        ExprKind::Literal(value) => json!({
+            "type": "Literal",
+            "value": value.as_ref().map_or(Json::Null, value_to_json),
+        }),
+        ExprKind::Logical {
+            left,
+            operator,
+            right,
+        } => json!({
+            "type": "Logical",
+            "left": expr_to_json(arena, *left),
+            "operator": token_to_json(operator),
+            "right": expr_to_json(arena, *right),
+        }),
+        ExprKind::Map { brace, entries } => json!({
+            "type": "Map",
+            "brace": token_to_json(brace),
+            "entries": entries
+                .iter()
+                .map(|(key, value)| json!({
+                    "key": expr_to_json(arena, *key),
+                    "value": expr_to_json(arena, *value),
+                }))
+                .collect::<Vec<_>>(),
+        }),
+        ExprKind::Set {
+            object,
+            name,
+            value,
+        } => json!({
+            "type": "Set",
+            "object": expr_to_json(arena, *object),
+            "name": token_to_json(name),
+            "value": expr_to_json(arena, *value),
+        }),
+        ExprKind::Subscript {
+            object,
+            bracket,
+            index,
+        } => json!({
+            "type": "Subscript",
+            "object": expr_to_json(arena, *object),
+            "bracket": token_to_json(bracket),
+            "index": expr_to_json(arena, *index),
+        }),
+        ExprKind::SubscriptSet {
+            object,
+            bracket,
+            index,
+            value,
+        } => json!({
+            "type": "SubscriptSet",
+            "object": expr_to_json(arena, *object),
+            "bracket": token_to_json(bracket),
+            "index": expr_to_json(arena, *index),
+            "value": expr_to_json(arena, *value),
+        }),
+        ExprKind::Super { keyword, method } => json!({
+            "type": "Super",
+            "keyword": token_to_json(keyword),
+            "method": token_to_json(method),
+        }),
+        ExprKind::This(keyword) => json!({
+            "type": "This",
+            "keyword": token_to_json(keyword),
+        }),
+        ExprKind::Unary { operator, right } => json!({
+            "type": "Unary",
+            "operator": token_to_json(operator),
+            "right": expr_to_json(arena, *right),
+        }),
+        ExprKind::Variable(name) => json!({
+            "type": "Variable",
+            "name": token_to_json(name),
+        }),
+    };
+    json["id"] = json!(expr.0);
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+    use serde_json::json;
+
+    use super::{program_to_json, tokens_to_json, value_to_json};
+    use crate::{
+        parser::Parser,
+        scanner::Scanner,
+        value::{MapKey, Value},
+    };
+
+    #[test]
+    fn exports_tokens_with_spans() {
+        let mut scanner = Scanner::new("var a = \"hi\";\nprint 1.5;".to_string());
+        scanner.scan_tokens();
+        let json = tokens_to_json(&scanner.tokens);
+
+        assert_eq!(json["version"], 1);
+        let tokens = json["tokens"].as_array().unwrap();
+        assert_eq!(tokens.len(), 9);
+        assert_eq!(
+            tokens[3],
+            json!({
+                "type": "STRING",
+                "lexeme": "\"hi\"",
+                "literal": "hi",
+                "span": { "start": 8, "end": 12, "line": 1 },
+            })
+        );
+        assert_eq!(tokens[6]["literal"], 1.5);
+        assert_eq!(tokens[6]["span"]["line"], 2);
+        assert_eq!(tokens[0]["literal"], json!(null));
+        assert_eq!(tokens[8]["type"], "EOF");
+        assert!(tokens[0].get("doc").is_none());
+    }
+
+    #[test]
+    fn exports_doc_comments() {
+        let mut scanner = Scanner::new("/// The answer.\nvar answer = 42;".to_string());
+        scanner.scan_tokens();
+        assert_eq!(
+            tokens_to_json(&scanner.tokens)["tokens"][0]["doc"],
+            "The answer."
+        );
+    }
+
+    #[test]
+    fn exports_tagged_ast() {
+        let mut scanner = Scanner::new("var a = -1; { a = a + 2; }".to_string());
+        scanner.scan_tokens();
+        let program = Parser::new(&scanner.tokens).parse().unwrap();
+        let json = program_to_json(&program);
+
+        let var = &json["statements"][0];
+        assert_eq!(var["type"], "Var");
+        assert_eq!(var["name"]["lexeme"], "a");
+        assert_eq!(var["initializer"]["type"], "Unary");
+        assert_eq!(var["initializer"]["right"]["value"], 1.0);
+        assert_eq!(var["initializer"]["right"]["id"], 0);
+        assert_eq!(var["initializer"]["id"], 1);
+
+        let block = &json["statements"][1];
+        assert_eq!(block["type"], "Block");
+        let assign = &block["statements"][0]["expression"];
+        assert_eq!(assign["type"], "Assign");
+        assert_eq!(assign["value"]["type"], "Binary");
+        assert_eq!(assign["value"]["operator"]["lexeme"], "+");
+        assert_eq!(assign["value"]["left"]["type"], "Variable");
+    }
+
+    #[test]
+    fn writes_numbers_that_are_not_finite() {
+        let mut scanner = Scanner::new("print 1e999; print -1e999; print 0 / 0;".to_string());
+        scanner.scan_tokens();
+        assert_eq!(
+            tokens_to_json(&scanner.tokens)["tokens"][1]["literal"],
+            json!({ "number": "inf" })
+        );
+        assert_eq!(
+            value_to_json(&Value::Number(f64::NEG_INFINITY)),
+            json!({ "number": "-inf" })
+        );
+        assert_eq!(
+            value_to_json(&Value::Number(f64::NAN)),
+            json!({ "number": "nan" })
+        );
+        assert_eq!(value_to_json(&Value::Number(1.5)), json!(1.5));
+        assert_eq!(value_to_json(&Value::Nil), json!(null));
+    }
+
+    #[test]
+    fn writes_values_that_contain_themselves() {
+        let list = Value::list(vec![Value::Number(1.0)]);
+        let Value::List(elements) = &list else {
+            unreachable!()
+        };
+        elements.borrow_mut().push(list.clone());
+        let map = Value::map(IndexMap::new());
+        let Value::Map(entries) = &map else {
+            unreachable!()
+        };
+        let key = MapKey::new(Value::String("self".into())).unwrap();
+        entries.borrow_mut().insert(key, map.clone());
+
+        assert_eq!(value_to_json(&list), json!([1.0, "[...]"]));
+        assert_eq!(value_to_json(&map), json!({ "self": "{...}" }));
+        // a list that is in a value twice, but not in itself, is written out both times
+        let inner = Value::list(vec![]);
+        let twice = Value::list(vec![inner.clone(), inner]);
+        assert_eq!(value_to_json(&twice), json!([[], []]));
+    }
+}
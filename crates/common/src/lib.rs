@@ -1,8 +1,24 @@
+pub mod callable;
+pub mod debugger;
 pub mod expression;
+pub mod formatter;
+pub mod hook;
 pub mod interpreter;
+pub mod json;
+pub mod lint;
+pub mod lsp;
+pub mod natives;
+pub mod number;
 pub mod parser;
+pub mod profile;
+pub mod reader;
+pub mod resolver;
 pub mod scanner;
+pub mod suggest;
+pub mod symbols;
+pub mod syntax;
 pub mod token;
+pub mod trace;
 pub mod value;
 #[macro_use]
 extern crate lazy_static;
@@ -0,0 +1,677 @@
+/**
+* A linter that walks the AST looking for code that is legal but probably a mistake.
+*
+* Every rule has an id, used in config files and `// lox-allow(rule)` comments, and a severity.
+* All rules warn by default:
+*  unused-variable       a local variable or function that is never read
+*  shadowed-variable     a local that hides a local of the same name in an enclosing scope
+*  unreachable-code      statements after a `return` or `throw` in the same block
+*  self-assignment       `a = a`
+*  constant-comparison   `==` or `!=` between two literals, or against a list or map literal,
+*                        which is a new object that can't be equal to anything else
+*  empty-block           a block, or `try`, `catch` or `finally` body, without statements
+*
+* A config file sets the severity of rules, one `allow`, `warn` or `deny` and a rule id per line:
+*
+*  # comments start with a hash
+*  allow empty-block
+*  deny self-assignment
+*
+* A `// lox-allow(rule, ...)` comment allows rules on its own line, or on the next one when the
+* comment is on a line by itself.
+*
+* Lines come from the tokens kept in the AST, an empty block is reported on the line of its `{`.
+*/
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use thiserror::Error;
+
+use crate::{
+    expression::{Block, ExprArena, ExprId, ExprKind, Stmt},
+    parser::{self, Parser},
+    scanner::{self, Scanner},
+    token::{Token, TokenType, TriviaKind},
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0}")]
+    Scan(#[from] scanner::Error),
+    #[error("{0}")]
+    Parse(#[from] parser::Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Rule {
+    UnusedVariable,
+    ShadowedVariable,
+    UnreachableCode,
+    SelfAssignment,
+    ConstantComparison,
+    EmptyBlock,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::UnusedVariable,
+        Rule::ShadowedVariable,
+        Rule::UnreachableCode,
+        Rule::SelfAssignment,
+        Rule::ConstantComparison,
+        Rule::EmptyBlock,
+    ];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::ShadowedVariable => "shadowed-variable",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::SelfAssignment => "self-assignment",
+            Rule::ConstantComparison => "constant-comparison",
+            Rule::EmptyBlock => "empty-block",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.id() == id)
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Allow,
+    Warn,
+    Deny,
+}
+
+impl Severity {
+    fn from_name(name: &str) -> Option<Severity> {
+        match name {
+            "allow" => Some(Severity::Allow),
+            "warn" => Some(Severity::Warn),
+            "deny" => Some(Severity::Deny),
+            _ => None,
+        }
+    }
+}
+
+/// The severity of every rule, rules that aren't configured warn
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    severities: HashMap<Rule, Severity>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {message}", i + 1);
+            let Some((level, id)) = line.split_once(char::is_whitespace) else {
+                return Err(error(format!("Expect a severity and a rule in '{line}'.")));
+            };
+            let severity = Severity::from_name(level)
+                .ok_or_else(|| error(format!("Unknown severity '{level}'.")))?;
+            let rule = Rule::from_id(id.trim())
+                .ok_or_else(|| error(format!("Unknown rule '{}'.", id.trim())))?;
+            config.severities.insert(rule, severity);
+        }
+        Ok(config)
+    }
+
+    pub fn set(&mut self, rule: Rule, severity: Severity) {
+        self.severities.insert(rule, severity);
+    }
+
+    pub fn severity(&self, rule: Rule) -> Severity {
+        self.severities
+            .get(&rule)
+            .copied()
+            .unwrap_or(Severity::Warn)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    pub line: usize,
+    pub message: String,
+}
+
+/// `[line 3] warning[unused-variable]: 'a' is never used.`
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Deny => "error",
+            Severity::Allow | Severity::Warn => "warning",
+        };
+        write!(
+            f,
+            "[line {}] {severity}[{}]: {}",
+            self.line, self.rule, self.message
+        )
+    }
+}
+
+/// Lints a whole program, in order of line, or returns the errors that stopped it from parsing
+pub fn lint(source: &str, config: &Config) -> Result<Vec<Diagnostic>, Vec<Error>> {
+    // lossless, for the `lox-allow` comments
+    let mut scanner = Scanner::lossless(source.to_string());
+    scanner.scan_tokens();
+    let parser = Parser::new(&scanner.tokens);
+    let result = parser.parse();
+    let mut errors: Vec<Error> = scanner.errors.into_iter().map(Error::from).collect();
+    let program = match result {
+        Ok(program) if errors.is_empty() => program,
+        Ok(_) => return Err(errors),
+        Err(_) => {
+            errors.extend(parser.errors.take().into_iter().map(Error::from));
+            return Err(errors);
+        }
+    };
+
+    let mut linter = Linter {
+        arena: &program.arena,
+        scopes: vec![],
+        found: vec![],
+    };
+    linter.stmts(&program.stmts);
+
+    let allowed = allowed_by_comments(source, &scanner.tokens);
+    let mut diagnostics: Vec<Diagnostic> = linter
+        .found
+        .into_iter()
+        .filter(|(rule, line, _)| !allowed.contains(&(*line, *rule)))
+        .map(|(rule, line, message)| Diagnostic {
+            rule,
+            severity: config.severity(rule),
+            line,
+            message,
+        })
+        .filter(|diagnostic| diagnostic.severity != Severity::Allow)
+        .collect();
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    Ok(diagnostics)
+}
+
+// the lines and rules allowed by `// lox-allow(...)` comments
+fn allowed_by_comments(source: &str, tokens: &[Token]) -> HashSet<(usize, Rule)> {
+    let mut allowed = HashSet::new();
+    for token in tokens {
+        // trivia is contiguous, leading trivia ends where the token starts
+        let leading_len: usize = token.leading.iter().map(|t| t.text.len()).sum();
+        let mut offset = token.span.start - leading_len;
+        let trivia = token.leading.iter().map(|t| (t, false));
+        let mut trailing_offset = token.span.end;
+        for (piece, trailing) in trivia.chain(token.trailing.iter().map(|t| (t, true))) {
+            let start = if trailing { trailing_offset } else { offset };
+            if trailing {
+                trailing_offset += piece.text.len();
+            } else {
+                offset += piece.text.len();
+            }
+            if piece.kind != TriviaKind::Comment {
+                continue;
+            }
+            let Some(rules) = piece
+                .text
+                .strip_prefix("//")
+                .map(str::trim)
+                .and_then(|text| text.strip_prefix("lox-allow("))
+                .and_then(|text| text.split_once(')'))
+                .map(|(rules, _)| rules)
+            else {
+                continue;
+            };
+            let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+            let mut line = source[..start].matches('\n').count() + 1;
+            // a comment on a line of its own is about the next line
+            if source[line_start..start].trim().is_empty() {
+                line += 1;
+            }
+            for rule in rules.split(',').filter_map(|id| Rule::from_id(id.trim())) {
+                allowed.insert((line, rule));
+            }
+        }
+    }
+    allowed
+}
+
+struct Local {
+    name: Token,
+    used: bool,
+    // parameters don't have to be used
+    check_unused: bool,
+}
+
+struct Linter<'a> {
+    arena: &'a ExprArena,
+    // the locals of every scope, globals are not tracked
+    scopes: Vec<Vec<Local>>,
+    found: Vec<(Rule, usize, String)>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, rule: Rule, line: usize, message: String) {
+        self.found.push((rule, line, message));
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        let mut reported = false;
+        for (i, stmt) in stmts.iter().enumerate() {
+            self.stmt(stmt);
+            let keyword = match stmt {
+                Stmt::Return { keyword, .. } | Stmt::Throw { keyword, .. } => keyword,
+                _ => continue,
+            };
+            if i + 1 < stmts.len() && !reported {
+                self.report(
+                    Rule::UnreachableCode,
+                    keyword.line,
+                    format!("Code after this '{}' is never executed.", keyword.lexeme()),
+                );
+                reported = true;
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) {
+        if block.stmts.is_empty() {
+            self.report(
+                Rule::EmptyBlock,
+                block.brace.line,
+                "Empty block.".to_string(),
+            );
+        }
+        self.scopes.push(vec![]);
+        self.stmts(&block.stmts);
+        self.end_scope();
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(stmts) => self.block(stmts),
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                self.declare(name, true);
+                if let Some(superclass) = superclass {
+                    self.expr(*superclass);
+                }
+                for method in methods {
+                    if let Stmt::Function { params, body, .. } = method {
+                        self.function(params, body);
+                    }
+                }
+            }
+            Stmt::Expression(expr) | Stmt::Print { value: expr, .. } => self.expr(*expr),
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                self.expr(*iterable);
+                self.scopes.push(vec![]);
+                self.declare(name, true);
+                self.stmt(body);
+                self.end_scope();
+            }
+            Stmt::Function { name, params, body } => {
+                self.declare(name, true);
+                self.function(params, body);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(*condition);
+                self.stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(*value);
+                }
+            }
+            Stmt::Throw { value, .. } => self.expr(*value),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.block(body);
+                if let Some((name, handler)) = catch {
+                    self.scopes.push(vec![]);
+                    // a caught value is often only there to be ignored
+                    self.declare(name, false);
+                    self.block(handler);
+                    self.end_scope();
+                }
+                if let Some(finally) = finally {
+                    self.block(finally);
+                }
+            }
+            Stmt::Var { name, initializer } => {
+                if let Some(initializer) = initializer {
+                    self.expr(*initializer);
+                }
+                self.declare(name, true);
+            }
+            Stmt::While { condition, body } => {
+                self.expr(*condition);
+                self.stmt(body);
+            }
+        }
+    }
+
+    // a function body isn't reported when empty, an empty function is a fine no-op
+    fn function(&mut self, params: &[Token], body: &[Stmt]) {
+        self.scopes.push(vec![]);
+        for param in params {
+            self.declare(param, false);
+        }
+        self.stmts(body);
+        self.end_scope();
+    }
+
+    fn declare(&mut self, name: &Token, check_unused: bool) {
+        let Some((scope, enclosing)) = self.scopes.split_last_mut() else {
+            return;
+        };
+        let shadowed = enclosing
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter())
+            .find(|local| local.name.lexeme() == name.lexeme())
+            .map(|local| local.name.line);
+        scope.push(Local {
+            name: name.clone(),
+            used: false,
+            check_unused,
+        });
+        if let Some(line) = shadowed {
+            self.report(
+                Rule::ShadowedVariable,
+                name.line,
+                format!(
+                    "'{}' shadows the local declared on line {line}.",
+                    name.lexeme()
+                ),
+            );
+        }
+    }
+
+    fn end_scope(&mut self) {
+        for local in self.scopes.pop().unwrap_or_default() {
+            if local.check_unused && !local.used {
+                self.report(
+                    Rule::UnusedVariable,
+                    local.name.line,
+                    format!("'{}' is never used.", local.name.lexeme()),
+                );
+            }
+        }
+    }
+
+    fn use_variable(&mut self, name: &Token) {
+        let local = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|scope| scope.iter_mut().rev())
+            .find(|local| local.name.lexeme() == name.lexeme());
+        if let Some(local) = local {
+            local.used = true;
+        }
+    }
+
+    fn expr(&mut self, expr: ExprId) {
+        let arena = self.arena;
+        match &arena[expr].kind {
+            ExprKind::Assign { name, value } => {
+                if matches!(&arena[*value].kind, ExprKind::Variable(v) if v.lexeme() == name.lexeme())
+                {
+                    self.report(
+                        Rule::SelfAssignment,
+                        name.line,
+                        format!("'{}' is assigned to itself.", name.lexeme()),
+                    );
+                }
+                self.expr(*value);
+            }
+            ExprKind::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                self.comparison(*left, operator, *right);
+                self.expr(*left);
+                self.expr(*right);
+            }
+            ExprKind::Logical { left, right, .. } => {
+                self.expr(*left);
+                self.expr(*right);
+            }
+            ExprKind::Call {
+                callee, arguments, ..
+            } => {
+                self.expr(*callee);
+                for argument in arguments {
+                    self.expr(*argument);
+                }
+            }
+            ExprKind::Get { object, .. } => self.expr(*object),
+            ExprKind::Grouping(inner) => self.expr(*inner),
+            ExprKind::List(elements) => {
+                for element in elements {
+                    self.expr(*element);
+                }
+            }
+            ExprKind::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.expr(*key);
+                    self.expr(*value);
+                }
+            }
+            ExprKind::Set { object, value, .. } => {
+                self.expr(*value);
+                self.expr(*object);
+            }
+            ExprKind::Subscript { object, index, .. } => {
+                self.expr(*object);
+                self.expr(*index);
+            }
+            ExprKind::SubscriptSet {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expr(*object);
+                self.expr(*index);
+                self.expr(*value);
+            }
+            ExprKind::Literal(_) | ExprKind::Super { .. } | ExprKind::This(_) => {}
+            ExprKind::Unary { right, .. } => self.expr(*right),
+            ExprKind::Variable(name) => self.use_variable(name),
+        }
+    }
+
+    fn comparison(&mut self, left: ExprId, operator: &Token, right: ExprId) {
+        let equal = match operator.token_type {
+            TokenType::EqualEqual => true,
+            TokenType::BangEqual => false,
+            _ => return,
+        };
+        let (left, right) = (self.ungroup(left), self.ungroup(right));
+        let message = match (&self.arena[left].kind, &self.arena[right].kind) {
+            (ExprKind::Literal(Some(a)), ExprKind::Literal(Some(b))) => {
+                format!("Comparing two literals is always {}.", (a == b) == equal)
+            }
+            (ExprKind::List(_) | ExprKind::Map { .. }, _)
+            | (_, ExprKind::List(_) | ExprKind::Map { .. }) => format!(
+                "Lists and maps are compared by identity, a new one is never equal to anything, \
+                 so this is always {}.",
+                !equal
+            ),
+            _ => return,
+        };
+        self.report(Rule::ConstantComparison, operator.line, message);
+    }
+
+    fn ungroup(&self, mut expr: ExprId) -> ExprId {
+        while let ExprKind::Grouping(inner) = &self.arena[expr].kind {
+            expr = *inner;
+        }
+        expr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint, Config, Rule, Severity};
+
+    // the rule and line of every diagnostic
+    fn found(source: &str) -> Vec<(Rule, usize)> {
+        lint(source, &Config::default())
+            .unwrap()
+            .into_iter()
+            .map(|diagnostic| (diagnostic.rule, diagnostic.line))
+            .collect()
+    }
+
+    #[test]
+    fn reports_unused_and_shadowed_locals() {
+        let source = "\
+var global = 1;
+fun f(param) {
+  var unused = 1;
+  var used = 3;
+  {
+    var used = used;
+    print used;
+  }
+  fun helper() {}
+}
+f(1);";
+        assert_eq!(
+            found(source),
+            vec![
+                (Rule::UnusedVariable, 3),
+                (Rule::ShadowedVariable, 6),
+                (Rule::UnusedVariable, 9)
+            ]
+        );
+        // a closure reading a local counts as a use
+        assert_eq!(found("{ var a = 1; fun g() { return a; } g(); }"), vec![]);
+    }
+
+    #[test]
+    fn reports_unreachable_code() {
+        let source = "fun f() {\n  return 1;\n  print 2;\n  print 3;\n}\nthrow 1;\nprint 4;";
+        assert_eq!(
+            found(source),
+            vec![(Rule::UnreachableCode, 2), (Rule::UnreachableCode, 6)]
+        );
+    }
+
+    #[test]
+    fn reports_suspicious_expressions() {
+        assert_eq!(
+            found("var a = 1;\na = a;\nprint 1 == 1;\nprint a != (\"x\");\nprint a == [];\nprint a == {};"),
+            vec![
+                (Rule::SelfAssignment, 2),
+                (Rule::ConstantComparison, 3),
+                (Rule::ConstantComparison, 5),
+                (Rule::ConstantComparison, 6)
+            ]
+        );
+        let diagnostics = lint("print 1 == \"1\";", &Config::default()).unwrap();
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "[line 1] warning[constant-comparison]: Comparing two literals is always false."
+        );
+    }
+
+    #[test]
+    fn reports_empty_blocks() {
+        let source =
+            "var a;\nif (a) {}\nwhile (a) {}\ntry {\n  print 1;\n} catch (e) {}\nfun f() {}";
+        assert_eq!(
+            found(source),
+            vec![
+                (Rule::EmptyBlock, 2),
+                (Rule::EmptyBlock, 3),
+                (Rule::EmptyBlock, 6)
+            ]
+        );
+
+        // literals and blocks have no tokens, the line is that of the `{`
+        let source =
+            "var a = 1;\nprint a;\nif (nil == 1) {}\nwhile (false) {}\n{}\nwhile (false)\n{\n}";
+        let empty: Vec<_> = found(source)
+            .into_iter()
+            .filter(|(rule, _)| *rule == Rule::EmptyBlock)
+            .collect();
+        assert_eq!(
+            empty,
+            vec![
+                (Rule::EmptyBlock, 3),
+                (Rule::EmptyBlock, 4),
+                (Rule::EmptyBlock, 5),
+                (Rule::EmptyBlock, 7)
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_config_and_comments() {
+        let config = Config::parse("# strict\ndeny self-assignment\nallow empty-block\n").unwrap();
+        assert_eq!(config.severity(Rule::SelfAssignment), Severity::Deny);
+        assert_eq!(config.severity(Rule::UnusedVariable), Severity::Warn);
+        let diagnostics = lint("var a;\na = a;\nif (a) {}", &config).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "[line 2] error[self-assignment]: 'a' is assigned to itself."
+        );
+
+        assert_eq!(
+            Config::parse("forbid empty-block").unwrap_err(),
+            "line 1: Unknown severity 'forbid'."
+        );
+        assert_eq!(
+            Config::parse("\nallow typo").unwrap_err(),
+            "line 2: Unknown rule 'typo'."
+        );
+
+        let source = "\
+var a;
+a = a; // lox-allow(self-assignment)
+// lox-allow(empty-block, constant-comparison)
+if (1 == 1) {}
+if (a) {}
+while (false) {} // lox-allow(empty-block)
+// lox-allow(empty-block)
+{}";
+        assert_eq!(found(source), vec![(Rule::EmptyBlock, 5)]);
+    }
+}
@@ -0,0 +1,619 @@
+/**
+* A Language Server Protocol server, see https://microsoft.github.io/language-server-protocol/
+*
+* Messages are JSON-RPC 2.0, framed by a `Content-Length` header, read from one stream and written
+* to another, stdin and stdout for `intepreter lsp`. Documents are synced in full and analyzed
+* every time they change:
+*
+* - diagnostics: scanner and parser errors, and names that are neither declared in the document
+*   nor natives, with the same "did you mean" help as the runtime error they would cause
+* - go-to-definition and find-references for variables, functions and classes, see `symbols`
+* - document symbols: every declaration except parameters, nested in its function or class
+* - hover: the line a name is declared on, and its `///` doc comment
+* - formatting, with `formatter`
+*
+* Navigation needs a syntax tree, so it finds nothing while a document doesn't parse.
+* Positions are in UTF-16 code units, the protocol's default encoding.
+*/
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use serde_json::{json, Value as Json};
+
+use crate::{
+    expression::ExprKind,
+    formatter, natives,
+    parser::{self, Parser},
+    resolver::Resolver,
+    scanner::Scanner,
+    suggest,
+    symbols::{SymbolKind, Symbols},
+    token::{Span, Token},
+};
+
+// JSON-RPC error codes
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+
+// `DiagnosticSeverity` and `SymbolKind` of the protocol
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+
+fn symbol_kind(kind: SymbolKind) -> u8 {
+    match kind {
+        SymbolKind::Class => 5,
+        SymbolKind::Method => 6,
+        SymbolKind::Function => 12,
+        SymbolKind::Parameter | SymbolKind::Variable => 13,
+    }
+}
+
+/// Serves one client until it sends `exit` or closes `input`, and returns the exit code: 0 if the
+/// client asked the server to shut down first, as the protocol wants, 1 otherwise.
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> io::Result<i32> {
+    let mut server = Server::default();
+    while let Some(body) = read_message(&mut input)? {
+        let replies = match serde_json::from_str(&body) {
+            Ok(message) => server.handle(&message),
+            Err(e) => vec![error_response(Json::Null, PARSE_ERROR, &e.to_string())],
+        };
+        for reply in replies {
+            write_message(&mut output, &reply)?;
+        }
+        if let Some(code) = server.exit_code {
+            return Ok(code);
+        }
+    }
+    Ok(1)
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header.")
+    })?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+fn response(id: Json, result: Json) -> Json {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+fn error_response(id: Json, code: i64, message: &str) -> Json {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// Converts between byte offsets and the protocol's line and UTF-16 column positions
+struct LineIndex {
+    // the byte offset every line starts at
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn new(text: &str) -> Self {
+        let newlines = text.match_indices('\n').map(|(i, _)| i + 1);
+        LineIndex {
+            starts: std::iter::once(0).chain(newlines).collect(),
+        }
+    }
+
+    fn position(&self, text: &str, offset: usize) -> Json {
+        let line = self.starts.partition_point(|start| *start <= offset) - 1;
+        let character: usize = text[self.starts[line]..offset]
+            .chars()
+            .map(char::len_utf16)
+            .sum();
+        json!({ "line": line, "character": character })
+    }
+
+    // positions past the end of a line are clamped to it
+    fn offset(&self, text: &str, position: &Json) -> Option<usize> {
+        let line = position["line"].as_u64()? as usize;
+        let character = position["character"].as_u64()? as usize;
+        let start = *self.starts.get(line)?;
+        let end = self.starts.get(line + 1).copied().unwrap_or(text.len());
+        let mut units = 0;
+        for (i, c) in text[start..end].char_indices() {
+            if units >= character || c == '\n' {
+                return Some(start + i);
+            }
+            units += c.len_utf16();
+        }
+        Some(end)
+    }
+
+    fn range(&self, text: &str, span: Span) -> Json {
+        json!({ "start": self.position(text, span.start), "end": self.position(text, span.end) })
+    }
+
+    // a whole line, for errors that only know their (1-based) line
+    fn line_range(&self, text: &str, line: usize) -> Json {
+        let line = line.clamp(1, self.starts.len()) - 1;
+        let start = self.starts[line];
+        let end = start + text[start..].find('\n').unwrap_or(text.len() - start);
+        self.range(text, Span::new(start, end))
+    }
+}
+
+struct Document {
+    text: String,
+    lines: LineIndex,
+    // empty while the document doesn't parse
+    symbols: Symbols,
+    diagnostics: Vec<Json>,
+}
+
+impl Document {
+    fn new(text: String) -> Self {
+        let lines = LineIndex::new(&text);
+        let mut document = Document {
+            text,
+            lines,
+            symbols: Symbols::default(),
+            diagnostics: vec![],
+        };
+        document.analyze();
+        document
+    }
+
+    fn diagnostic(&mut self, range: Json, severity: u8, message: String) {
+        self.diagnostics.push(json!({
+            "range": range,
+            "severity": severity,
+            "source": "rlox",
+            "message": message,
+        }));
+    }
+
+    fn analyze(&mut self) {
+        let mut scanner = Scanner::new(self.text.clone());
+        scanner.scan_tokens();
+        for error in &scanner.errors {
+            // the line is in the range already
+            let text = error.to_string();
+            let message = text.split_once(": ").map_or(&*text, |(_, message)| message);
+            let range = self.lines.line_range(&self.text, error_line(&text));
+            self.diagnostic(range, SEVERITY_ERROR, message.to_string());
+        }
+
+        let parser = Parser::new(&scanner.tokens);
+        let program = match parser.parse() {
+            Ok(program) => program,
+            Err(_) => {
+                for error in parser.errors.take() {
+                    let (range, message) = match error {
+                        parser::Error::ParseErrorToken { token, message } => {
+                            (self.lines.range(&self.text, token.span), message)
+                        }
+                        error => (self.lines.line_range(&self.text, 1), error.to_string()),
+                    };
+                    self.diagnostic(range, SEVERITY_ERROR, message);
+                }
+                return;
+            }
+        };
+
+        self.symbols = Symbols::of(&program);
+        let locals = Resolver::new(&program.arena).resolve(&program.stmts);
+        // the expressions of names, for the resolver's hints
+        let exprs: HashMap<usize, _> = program
+            .arena
+            .iter()
+            .filter_map(|node| match &node.kind {
+                ExprKind::Variable(name) | ExprKind::Assign { name, .. } => {
+                    Some((name.span.start, node.id))
+                }
+                _ => None,
+            })
+            .collect();
+        let mut globals: Vec<&str> = self
+            .symbols
+            .definitions
+            .iter()
+            .filter(|definition| definition.parent.is_none())
+            .map(|definition| definition.name.lexeme())
+            .collect();
+        globals.extend(natives::names().map(|name| -> &str { name }));
+        let mut undefined = vec![];
+        for reference in &self.symbols.references {
+            let name = reference.name.lexeme();
+            if reference.definition.is_some() || globals.contains(&name) {
+                continue;
+            }
+            let hint = exprs
+                .get(&reference.name.span.start)
+                .and_then(|expr| locals.hint(*expr));
+            let mut message = format!("Undefined variable '{name}'.");
+            if let Some(suggestion) = suggest::closest(name, globals.iter().copied().chain(hint)) {
+                message.push('\n');
+                message.push_str(&suggest::help(suggestion));
+            }
+            undefined.push((self.lines.range(&self.text, reference.name.span), message));
+        }
+        for (range, message) in undefined {
+            self.diagnostic(range, SEVERITY_WARNING, message);
+        }
+    }
+
+    fn definition_at(&self, position: &Json) -> Option<usize> {
+        let offset = self.lines.offset(&self.text, position)?;
+        self.symbols.definition_at(offset)
+    }
+
+    fn name(&self, index: usize) -> &Token {
+        &self.symbols.definitions[index].name
+    }
+
+    fn document_symbol(&self, index: usize) -> Json {
+        let definition = &self.symbols.definitions[index];
+        let range = self.lines.range(&self.text, definition.name.span);
+        json!({
+            "name": definition.name.lexeme(),
+            "kind": symbol_kind(definition.kind),
+            "range": range,
+            "selectionRange": range,
+            "children": self.document_symbols(Some(index)),
+        })
+    }
+
+    fn document_symbols(&self, parent: Option<usize>) -> Vec<Json> {
+        let definitions = self.symbols.definitions.iter().enumerate();
+        definitions
+            .filter(|(_, definition)| {
+                definition.parent == parent && definition.kind != SymbolKind::Parameter
+            })
+            .map(|(index, _)| self.document_symbol(index))
+            .collect()
+    }
+
+    fn hover(&self, index: usize) -> Json {
+        let name = self.name(index);
+        let declaration = self.text.lines().nth(name.line - 1).unwrap_or("").trim();
+        let mut value = format!("```lox\n{declaration}\n```");
+        if let Some(doc) = &name.doc {
+            value.push_str("\n\n");
+            value.push_str(doc);
+        }
+        value.push_str(&format!("\n\nDeclared on line {}.", name.line));
+        json!({ "contents": { "kind": "markdown", "value": value } })
+    }
+}
+
+// the line of an error displayed jlox style, `[line 3] Error: ...`
+fn error_line(text: &str) -> usize {
+    text.strip_prefix("[line ")
+        .and_then(|rest| rest.split_once(']'))
+        .and_then(|(line, _)| line.parse().ok())
+        .unwrap_or(1)
+}
+
+/// The state of a session: the open documents and whether the client asked to shut down
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    shutdown: bool,
+    /// Set once the client sent `exit`
+    pub exit_code: Option<i32>,
+}
+
+impl Server {
+    /// Handles one request or notification, and returns the messages to send back
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message["method"].as_str().unwrap_or("");
+        let params = &message["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or("");
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "documentSymbolProvider": true,
+                    "hoverProvider": true,
+                    "documentFormattingProvider": true,
+                },
+                "serverInfo": { "name": "rlox" },
+            }),
+            "shutdown" => {
+                self.shutdown = true;
+                Json::Null
+            }
+            "exit" => {
+                self.exit_code = Some(if self.shutdown { 0 } else { 1 });
+                return vec![];
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or("");
+                return self.open(uri, text.to_string());
+            }
+            "textDocument/didChange" => {
+                // the whole text, as the server asked for in `textDocumentSync`
+                let changes = params["contentChanges"].as_array();
+                match changes.and_then(|changes| changes.last()?["text"].as_str()) {
+                    Some(text) => return self.open(uri, text.to_string()),
+                    None => return vec![],
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                return vec![notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )];
+            }
+            "textDocument/definition" => self.definition(uri, params),
+            "textDocument/references" => self.references(uri, params),
+            "textDocument/documentSymbol" => match self.documents.get(uri) {
+                Some(document) => json!(document.document_symbols(None)),
+                None => Json::Null,
+            },
+            "textDocument/hover" => self.hover(uri, params),
+            "textDocument/formatting" => self.format(uri),
+            _ => {
+                // notifications the server doesn't know about are ignored
+                return match message.get("id") {
+                    Some(id) => vec![error_response(
+                        id.clone(),
+                        METHOD_NOT_FOUND,
+                        &format!("Unknown method '{method}'."),
+                    )],
+                    None => vec![],
+                };
+            }
+        };
+        match message.get("id") {
+            Some(id) => vec![response(id.clone(), result)],
+            None => vec![],
+        }
+    }
+
+    fn open(&mut self, uri: &str, text: String) -> Vec<Json> {
+        let document = Document::new(text);
+        let diagnostics = notification(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": document.diagnostics }),
+        );
+        self.documents.insert(uri.to_string(), document);
+        vec![diagnostics]
+    }
+
+    fn location(&self, uri: &str, document: &Document, name: &Token) -> Json {
+        json!({ "uri": uri, "range": document.lines.range(&document.text, name.span) })
+    }
+
+    fn definition(&self, uri: &str, params: &Json) -> Json {
+        let Some(document) = self.documents.get(uri) else {
+            return Json::Null;
+        };
+        match document.definition_at(&params["position"]) {
+            Some(index) => self.location(uri, document, document.name(index)),
+            None => Json::Null,
+        }
+    }
+
+    fn references(&self, uri: &str, params: &Json) -> Json {
+        let Some(document) = self.documents.get(uri) else {
+            return Json::Null;
+        };
+        let Some(index) = document.definition_at(&params["position"]) else {
+            return json!([]);
+        };
+        let mut names: Vec<&Token> = document.symbols.references_to(index).collect();
+        if params["context"]["includeDeclaration"].as_bool() == Some(true) {
+            names.push(document.name(index));
+            names.sort_by_key(|name| name.span.start);
+        }
+        let locations = names
+            .into_iter()
+            .map(|name| self.location(uri, document, name));
+        json!(locations.collect::<Vec<_>>())
+    }
+
+    fn hover(&self, uri: &str, params: &Json) -> Json {
+        let Some(document) = self.documents.get(uri) else {
+            return Json::Null;
+        };
+        match document.definition_at(&params["position"]) {
+            Some(index) => document.hover(index),
+            None => Json::Null,
+        }
+    }
+
+    // one edit replacing the whole document, or none when it is formatted already
+    fn format(&self, uri: &str) -> Json {
+        let Some(document) = self.documents.get(uri) else {
+            return Json::Null;
+        };
+        match formatter::format(&document.text) {
+            Ok(formatted) if formatted == document.text => json!([]),
+            Ok(formatted) => json!([{
+                "range": document.lines.range(&document.text, Span::new(0, document.text.len())),
+                "newText": formatted,
+            }]),
+            // errors are already shown as diagnostics
+            Err(_) => Json::Null,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value as Json};
+
+    use super::{LineIndex, Server};
+
+    const URI: &str = "file:///test.lox";
+
+    fn open(server: &mut Server, text: &str) -> Json {
+        let mut replies = server.handle(&json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": URI, "languageId": "lox", "version": 1, "text": text } },
+        }));
+        replies.pop().unwrap()["params"]["diagnostics"].take()
+    }
+
+    fn request(server: &mut Server, method: &str, params: Json) -> Json {
+        let mut params = params;
+        params["textDocument"] = json!({ "uri": URI });
+        let mut replies = server.handle(&json!({
+            "jsonrpc": "2.0", "id": 1, "method": method, "params": params,
+        }));
+        replies.pop().unwrap()["result"].take()
+    }
+
+    fn position(line: usize, character: usize) -> Json {
+        json!({ "line": line, "character": character })
+    }
+
+    #[test]
+    fn converts_utf16_positions() {
+        let text = "print \"é😀\";\nx";
+        let lines = LineIndex::new(text);
+        let semicolon = text.find(';').unwrap();
+        // the emoji takes two code units
+        assert_eq!(lines.position(text, semicolon), position(0, 11));
+        assert_eq!(lines.offset(text, &position(0, 11)), Some(semicolon));
+        assert_eq!(lines.offset(text, &position(1, 0)), Some(text.len() - 1));
+        // past the end of a line
+        assert_eq!(lines.offset(text, &position(0, 99)), Some(semicolon + 1));
+        assert_eq!(lines.offset(text, &position(2, 0)), None);
+    }
+
+    #[test]
+    fn publishes_diagnostics() {
+        let mut server = Server::default();
+        assert_eq!(
+            open(&mut server, "print (1;\nprint @;"),
+            json!([
+                {
+                    "range": { "start": position(1, 0), "end": position(1, 8) },
+                    "severity": 1, "source": "rlox", "message": "Unexpected character.",
+                },
+                {
+                    "range": { "start": position(0, 8), "end": position(0, 9) },
+                    "severity": 1, "source": "rlox", "message": "Expect ')' after expression.",
+                },
+                {
+                    "range": { "start": position(1, 7), "end": position(1, 8) },
+                    "severity": 1, "source": "rlox", "message": "Expect expression.",
+                },
+            ])
+        );
+        assert_eq!(
+            open(
+                &mut server,
+                "{\n  var counter = 1;\n  print countr + len(nil);\n}"
+            ),
+            json!([{
+                "range": { "start": position(2, 8), "end": position(2, 14) },
+                "severity": 2, "source": "rlox",
+                "message": "Undefined variable 'countr'.\nhelp: did you mean 'counter'?",
+            }])
+        );
+    }
+
+    #[test]
+    fn navigates_between_definitions_and_references() {
+        let mut server = Server::default();
+        let source = "\
+/// Adds one.
+fun inc(n) {
+  return n + 1;
+}
+var total = inc(1);
+total = inc(total);";
+        assert_eq!(open(&mut server, source), json!([]));
+
+        let location = |line: usize, start: usize, end: usize| json!({ "uri": URI, "range": { "start": position(line, start), "end": position(line, end) } });
+        assert_eq!(
+            request(
+                &mut server,
+                "textDocument/definition",
+                json!({ "position": position(5, 9) })
+            ),
+            location(1, 4, 7)
+        );
+        assert_eq!(
+            request(
+                &mut server,
+                "textDocument/references",
+                json!({ "position": position(1, 5), "context": { "includeDeclaration": true } })
+            ),
+            json!([location(1, 4, 7), location(4, 12, 15), location(5, 8, 11)])
+        );
+        assert_eq!(
+            request(
+                &mut server,
+                "textDocument/hover",
+                json!({ "position": position(4, 13) })
+            )["contents"]["value"],
+            "```lox\nfun inc(n) {\n```\n\nAdds one.\n\nDeclared on line 2."
+        );
+
+        let symbols = request(&mut server, "textDocument/documentSymbol", json!({}));
+        let names: Vec<_> = symbols
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|symbol| (symbol["name"].clone(), symbol["kind"].clone()))
+            .collect();
+        assert_eq!(
+            names,
+            vec![(json!("inc"), json!(12)), (json!("total"), json!(13))]
+        );
+    }
+
+    #[test]
+    fn formats_documents() {
+        let mut server = Server::default();
+        open(&mut server, "var a=1;\n");
+        assert_eq!(
+            request(&mut server, "textDocument/formatting", json!({})),
+            json!([{
+                "range": { "start": position(0, 0), "end": position(1, 0) },
+                "newText": "var a = 1;\n",
+            }])
+        );
+        open(&mut server, "var a = ;");
+        assert_eq!(
+            request(&mut server, "textDocument/formatting", json!({})),
+            Json::Null
+        );
+
+        let reply =
+            server.handle(&json!({ "jsonrpc": "2.0", "id": 7, "method": "unknown/method" }));
+        assert_eq!(reply[0]["error"]["code"], -32601);
+    }
+}
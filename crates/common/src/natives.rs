@@ -0,0 +1,347 @@
+use std::{
+    cell::RefCell,
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    callable::{Callable, NativeFunction},
+    interpreter::Environment,
+    number,
+    value::{self, MapKey, Value},
+};
+use indexmap::IndexMap;
+
+const NATIVES: &[NativeFunction] = &[
+    NativeFunction {
+        name: "clock",
+        arity: 0,
+        function: clock,
+    },
+    NativeFunction {
+        name: "format",
+        arity: 2,
+        function: format,
+    },
+    NativeFunction {
+        name: "has",
+        arity: 2,
+        function: has,
+    },
+    NativeFunction {
+        name: "insert",
+        arity: 3,
+        function: insert,
+    },
+    NativeFunction {
+        name: "keys",
+        arity: 1,
+        function: keys,
+    },
+    NativeFunction {
+        name: "len",
+        arity: 1,
+        function: len,
+    },
+    NativeFunction {
+        name: "pop",
+        arity: 1,
+        function: pop,
+    },
+    NativeFunction {
+        name: "push",
+        arity: 2,
+        function: push,
+    },
+    NativeFunction {
+        name: "remove",
+        arity: 2,
+        function: remove,
+    },
+    NativeFunction {
+        name: "slice",
+        arity: 3,
+        function: slice,
+    },
+    NativeFunction {
+        name: "sort",
+        arity: 1,
+        function: sort,
+    },
+    NativeFunction {
+        name: "values",
+        arity: 1,
+        function: values,
+    },
+];
+
+/// Defines every native function in the given (global) environment
+pub fn define_natives(env: &mut Environment) {
+    for native in NATIVES {
+        let native = NativeFunction { ..*native };
+        env.define(
+            native.name,
+            Value::Callable(Callable::Native(Rc::new(native))),
+        );
+    }
+}
+
+/// The names of the native functions, which every program can use without declaring them
+pub fn names() -> impl Iterator<Item = &'static str> {
+    NATIVES.iter().map(|native| native.name)
+}
+
+fn list<'a>(name: &str, value: &'a Value) -> Result<&'a Rc<RefCell<Vec<Value>>>, String> {
+    match value {
+        Value::List(list) => Ok(list),
+        _ => Err(format!("{name} expects a list, got {value}.")),
+    }
+}
+
+fn map<'a>(
+    name: &str,
+    value: &'a Value,
+) -> Result<&'a Rc<RefCell<IndexMap<MapKey, Value>>>, String> {
+    match value {
+        Value::Map(map) => Ok(map),
+        _ => Err(format!("{name} expects a map, got {value}.")),
+    }
+}
+
+// seconds since the unix epoch
+fn clock(_: &[Value]) -> Result<Value, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|e| e.to_string())?;
+    Ok(Value::Number(now.as_secs_f64()))
+}
+
+// the most digits `format` accepts, more than enough for any double
+const MAX_PRECISION: f64 = 100.0;
+
+// format(number, precision) is C's "%.{precision}g"
+fn format(args: &[Value]) -> Result<Value, String> {
+    let Value::Number(n) = args[0] else {
+        return Err(format!("format expects a number, got {}.", args[0]));
+    };
+    match args[1] {
+        Value::Number(precision)
+            if precision.fract() == 0.0 && (0.0..=MAX_PRECISION).contains(&precision) =>
+        {
+            let formatted = number::format_general(n, precision as usize);
+            Ok(Value::String(Rc::from(formatted)))
+        }
+        ref precision => Err(format!(
+            "format expects a whole number precision from 0 to {MAX_PRECISION}, got {precision}."
+        )),
+    }
+}
+
+fn has(args: &[Value]) -> Result<Value, String> {
+    let key = MapKey::new(args[1].clone())?;
+    Ok(Value::Boolean(
+        map("has", &args[0])?.borrow().contains_key(&key),
+    ))
+}
+
+// insert(list, index, value), the index may be one past the end to append
+fn insert(args: &[Value]) -> Result<Value, String> {
+    let list = list("insert", &args[0])?;
+    // checked before borrowing the list mutably, the error shows the index, which can be the list
+    let index = value::list_index(&args[1], list.borrow().len() + 1)?;
+    list.borrow_mut().insert(index, args[2].clone());
+    Ok(Value::Nil)
+}
+
+// the keys of a map in insertion order
+fn keys(args: &[Value]) -> Result<Value, String> {
+    let map = map("keys", &args[0])?.borrow();
+    Ok(Value::list(
+        map.keys().map(|key| key.value().clone()).collect(),
+    ))
+}
+
+fn len(args: &[Value]) -> Result<Value, String> {
+    match &args[0] {
+        Value::List(list) => Ok(Value::Number(list.borrow().len() as f64)),
+        Value::Map(map) => Ok(Value::Number(map.borrow().len() as f64)),
+        Value::String(s) => Ok(Value::Number(s.chars().count() as f64)),
+        value => Err(format!("len expects a list, map or string, got {value}.")),
+    }
+}
+
+fn pop(args: &[Value]) -> Result<Value, String> {
+    list("pop", &args[0])?
+        .borrow_mut()
+        .pop()
+        .ok_or_else(|| "Can't pop from an empty list.".to_string())
+}
+
+fn push(args: &[Value]) -> Result<Value, String> {
+    list("push", &args[0])?.borrow_mut().push(args[1].clone());
+    Ok(Value::Nil)
+}
+
+// remove(list, index) and remove(map, key) return the removed element
+fn remove(args: &[Value]) -> Result<Value, String> {
+    if let Value::Map(map) = &args[0] {
+        let key = MapKey::new(args[1].clone())?;
+        // shift_remove keeps the order of the remaining keys
+        return map
+            .borrow_mut()
+            .shift_remove(&key)
+            .ok_or_else(|| format!("Key {} is not in the map.", key.value()));
+    }
+    let list = list("remove", &args[0])?;
+    let index = value::list_index(&args[1], list.borrow().len())?;
+    Ok(list.borrow_mut().remove(index))
+}
+
+// slice(list, start, end) copies the elements from start up to but not including end
+fn slice(args: &[Value]) -> Result<Value, String> {
+    let list = list("slice", &args[0])?.borrow();
+    let start = value::list_index(&args[1], list.len() + 1)?;
+    let end = value::list_index(&args[2], list.len() + 1)?;
+    if start > end {
+        return Err(format!("slice start {start} is after its end {end}."));
+    }
+    Ok(Value::list(list[start..end].to_vec()))
+}
+
+// sorts in place, the list must hold only numbers or only strings
+fn sort(args: &[Value]) -> Result<Value, String> {
+    let mut list = list("sort", &args[0])?.borrow_mut();
+    if list.iter().all(|value| matches!(value, Value::Number(_))) {
+        list.sort_by(|a, b| match (a, b) {
+            (Value::Number(a), Value::Number(b)) => a.total_cmp(b),
+            _ => unreachable!(),
+        });
+    } else if list.iter().all(|value| matches!(value, Value::String(_))) {
+        list.sort_by(|a, b| match (a, b) {
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            _ => unreachable!(),
+        });
+    } else {
+        return Err("sort expects a list of only numbers or only strings.".to_string());
+    }
+    Ok(Value::Nil)
+}
+
+// the values of a map in insertion order
+fn values(args: &[Value]) -> Result<Value, String> {
+    let map = map("values", &args[0])?.borrow();
+    Ok(Value::list(map.values().cloned().collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use indexmap::IndexMap;
+
+    use super::{format, has, insert, keys, len, pop, remove, slice, sort, values};
+    use crate::value::{intern, MapKey, Value};
+
+    fn numbers(ns: &[f64]) -> Value {
+        Value::list(ns.iter().map(|n| Value::Number(*n)).collect())
+    }
+
+    #[test]
+    fn edits_lists_in_place() {
+        let list = numbers(&[1.0, 2.0]);
+        insert(&[list.clone(), Value::Number(2.0), Value::Number(3.0)]).unwrap();
+        insert(&[list.clone(), Value::Number(0.0), Value::Number(0.0)]).unwrap();
+        assert_eq!(list.to_string(), "[0, 1, 2, 3]");
+        assert_eq!(
+            remove(&[list.clone(), Value::Number(1.0)]),
+            Ok(Value::Number(1.0))
+        );
+        assert_eq!(pop(std::slice::from_ref(&list)), Ok(Value::Number(3.0)));
+        assert_eq!(list.to_string(), "[0, 2]");
+        assert!(insert(&[list.clone(), Value::Number(3.0), Value::Nil]).is_err());
+        assert!(remove(&[list, Value::Number(2.0)]).is_err());
+    }
+
+    #[test]
+    fn reports_a_list_used_as_its_own_index() {
+        let list = numbers(&[1.0]);
+        assert_eq!(
+            insert(&[list.clone(), list.clone(), Value::Number(1.0)]),
+            Err("List index must be a whole number, got [1].".to_string())
+        );
+        assert_eq!(
+            remove(&[list.clone(), list]),
+            Err("List index must be a whole number, got [1].".to_string())
+        );
+    }
+
+    #[test]
+    fn slices_copy_a_range() {
+        let list = numbers(&[1.0, 2.0, 3.0]);
+        let sliced = slice(&[list.clone(), Value::Number(1.0), Value::Number(3.0)]).unwrap();
+        assert_eq!(sliced.to_string(), "[2, 3]");
+        assert_ne!(sliced, list);
+        assert!(slice(&[list.clone(), Value::Number(2.0), Value::Number(1.0)]).is_err());
+        assert!(slice(&[list, Value::Number(0.0), Value::Number(4.0)]).is_err());
+    }
+
+    #[test]
+    fn sorts_numbers_or_strings() {
+        let list = numbers(&[3.0, -1.0, 2.5]);
+        sort(std::slice::from_ref(&list)).unwrap();
+        assert_eq!(list.to_string(), "[-1, 2.5, 3]");
+
+        let words = Value::list(vec![Value::String(intern("b")), Value::String(intern("a"))]);
+        sort(std::slice::from_ref(&words)).unwrap();
+        assert_eq!(words.to_string(), "[\"a\", \"b\"]");
+
+        let mixed = Value::list(vec![Value::Number(1.0), Value::String(intern("a"))]);
+        assert!(sort(&[mixed]).is_err());
+    }
+
+    #[test]
+    fn formats_numbers_like_printf() {
+        assert_eq!(
+            format(&[Value::Number(1.0 / 3.0), Value::Number(3.0)]),
+            Ok(Value::String(intern("0.333")))
+        );
+        assert!(format(&[Value::Nil, Value::Number(3.0)]).is_err());
+        assert!(format(&[Value::Number(1.0), Value::Number(1.5)]).is_err());
+        assert!(format(&[Value::Number(1.0), Value::Number(-1.0)]).is_err());
+    }
+
+    #[test]
+    fn measures_lists_and_strings() {
+        assert_eq!(len(&[numbers(&[1.0])]), Ok(Value::Number(1.0)));
+        assert_eq!(
+            len(&[Value::String(intern("héllo"))]),
+            Ok(Value::Number(5.0))
+        );
+        assert!(len(&[Value::Nil]).is_err());
+    }
+
+    #[test]
+    fn reads_and_removes_map_entries() {
+        let mut entries = IndexMap::new();
+        for (key, value) in [("a", 1.0), ("b", 2.0), ("c", 3.0)] {
+            entries.insert(
+                MapKey::new(Value::String(intern(key))).unwrap(),
+                Value::Number(value),
+            );
+        }
+        let map = Value::map(entries);
+        let b = Value::String(intern("b"));
+
+        assert_eq!(has(&[map.clone(), b.clone()]), Ok(Value::Boolean(true)));
+        assert_eq!(remove(&[map.clone(), b.clone()]), Ok(Value::Number(2.0)));
+        assert_eq!(has(&[map.clone(), b.clone()]), Ok(Value::Boolean(false)));
+        assert!(remove(&[map.clone(), b]).is_err());
+        assert_eq!(
+            keys(std::slice::from_ref(&map)).unwrap().to_string(),
+            "[\"a\", \"c\"]"
+        );
+        assert_eq!(
+            values(std::slice::from_ref(&map)).unwrap().to_string(),
+            "[1, 3]"
+        );
+        assert_eq!(len(&[map]), Ok(Value::Number(2.0)));
+    }
+}
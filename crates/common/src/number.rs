@@ -0,0 +1,109 @@
+/**
+* How numbers are turned into text.
+*
+* `format` is what `print` and string conversion use. It follows jlox, which prints a double
+* with Java's `Double.toString` and then drops a trailing ".0":
+* - whole numbers print without a fraction: `1`, `-0`, `1000000`
+* - numbers from 0.001 up to, but not including, 10 million print in plain decimal notation
+*   with the fewest digits that read back as the same number: `0.1`, `123.456`
+* - all other finite numbers use scientific notation with at least one fractional digit and an
+*   unsigned or negative exponent: `1.0E7`, `1.5E-5`, `1.0E21`
+* - `NaN`, `Infinity` and `-Infinity`
+*
+* `format_general` is C's `%.Ng`, as used by clox's `printf("%g")`, and is exposed to scripts
+* through the `format` native.
+*/
+use std::fmt::Write;
+
+pub fn format(n: f64) -> String {
+    if n.is_nan() {
+        return "NaN".to_string();
+    }
+    if n.is_infinite() {
+        return if n > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+    let magnitude = n.abs();
+    if magnitude == 0.0 || (1e-3..1e7).contains(&magnitude) {
+        // Rust's `{}` is already the shortest representation, and leaves off the ".0"
+        return n.to_string();
+    }
+    let scientific = format!("{n:e}");
+    let (mantissa, exponent) = scientific.split_once('e').expect("{:e} has an exponent");
+    if mantissa.contains('.') {
+        format!("{mantissa}E{exponent}")
+    } else {
+        format!("{mantissa}.0E{exponent}")
+    }
+}
+
+/// C's `%.{precision}g`: `precision` significant digits, a precision of 0 is treated as 1,
+/// trailing zeros are removed and exponents have a sign and at least two digits
+pub fn format_general(n: f64, precision: usize) -> String {
+    if n.is_nan() {
+        return "nan".to_string();
+    }
+    if n.is_infinite() {
+        return if n > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    let precision = precision.max(1);
+    // the exponent after rounding to `precision` digits, 9.99 with 2 digits is 1.0e1
+    let scientific = format!("{:.*e}", precision - 1, n);
+    let (mantissa, exponent) = scientific.split_once('e').expect("{:e} has an exponent");
+    let exponent: i32 = exponent.parse().expect("{:e} exponent is an integer");
+
+    if exponent < -4 || exponent >= precision as i32 {
+        let mut result = trim_fraction(mantissa).to_string();
+        let sign = if exponent < 0 { '-' } else { '+' };
+        write!(result, "e{sign}{:02}", exponent.abs()).unwrap();
+        result
+    } else {
+        let decimals = (precision as i32 - 1 - exponent) as usize;
+        trim_fraction(&format!("{n:.decimals$}")).to_string()
+    }
+}
+
+// drops trailing zeros after the decimal point, and the point itself if nothing is left
+fn trim_fraction(digits: &str) -> &str {
+    if digits.contains('.') {
+        digits.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        digits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format, format_general};
+
+    #[test]
+    fn formats_like_jlox() {
+        assert_eq!(format(1.0), "1");
+        assert_eq!(format(-0.0), "-0");
+        assert_eq!(format(123.456), "123.456");
+        assert_eq!(format(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(format(0.001), "0.001");
+        assert_eq!(format(9999999.0), "9999999");
+        assert_eq!(format(1e7), "1.0E7");
+        assert_eq!(format(-1.5e-5), "-1.5E-5");
+        assert_eq!(format(1e21), "1.0E21");
+        assert_eq!(format(f64::NAN), "NaN");
+        assert_eq!(format(f64::INFINITY), "Infinity");
+        assert_eq!(format(f64::NEG_INFINITY), "-Infinity");
+    }
+
+    #[test]
+    fn formats_like_printf_g() {
+        assert_eq!(format_general(1.0, 6), "1");
+        assert_eq!(format_general(123.456789, 6), "123.457");
+        assert_eq!(format_general(0.0001, 6), "0.0001");
+        assert_eq!(format_general(0.00001, 6), "1e-05");
+        assert_eq!(format_general(1e21, 6), "1e+21");
+        assert_eq!(format_general(123456789.0, 6), "1.23457e+08");
+        assert_eq!(format_general(999999.5, 6), "1e+06");
+        assert_eq!(format_general(-0.0, 6), "-0");
+        assert_eq!(format_general(2.75, 0), "3");
+        assert_eq!(format_general(2.5, 3), "2.5");
+        assert_eq!(format_general(f64::NAN, 6), "nan");
+        assert_eq!(format_general(f64::NEG_INFINITY, 6), "-inf");
+    }
+}
@@ -2,46 +2,95 @@
 * Here we implement a parsed based on https://craftinginterpreters.com/parsing-expressions.html
 *
 * The grammar is:
-*  expression     → assignment ;
-   assignment     → IDENTIFIER "=" assignment
-                    | equality ;
+*  program        → declaration* EOF ;
+   declaration    → funDecl | varDecl | statement ;
+   funDecl        → "fun" function ;
+   function       → IDENTIFIER "(" parameters? ")" block ;
+   parameters     → IDENTIFIER ( "," IDENTIFIER )* ;
+   varDecl        → "var" IDENTIFIER ( "=" expression )? ";" ;
+   statement      → exprStmt | forStmt | forInStmt | ifStmt | printStmt | returnStmt | throwStmt | tryStmt | whileStmt | block ;
+   forStmt        → "for" "(" ( varDecl | exprStmt | ";" ) expression? ";" expression? ")" statement ;
+   forInStmt      → "for" "(" "var" IDENTIFIER "in" expression ")" statement ;
+   ifStmt         → "if" "(" expression ")" statement ( "else" statement )? ;
+   returnStmt     → "return" expression? ";" ;
+   throwStmt      → "throw" expression ";" ;
+   tryStmt        → "try" block ( "catch" "(" IDENTIFIER ")" block )? ( "finally" block )? ;
+   whileStmt      → "while" "(" expression ")" statement ;
+   block          → "{" declaration* "}" ;
+   expression     → assignment ;
+   assignment     → ( IDENTIFIER | call "[" expression "]" ) "=" assignment | logic_or ;
+   logic_or       → logic_and ( "or" logic_and )* ;
+   logic_and      → equality ( "and" equality )* ;
    equality       → comparison ( ( "!=" | "==" ) comparison )* ;
-   comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+   comparison     → range ( ( ">" | ">=" | "<" | "<=" ) range )* ;
+   range          → term ( ".." term )? ;
    term           → factor ( ( "-" | "+" ) factor )* ;
    factor         → unary ( ( "/" | "*" ) unary )* ;
-   unary          → ( "!" | "-" ) unary
-                    |primary ;
-   primary        → NUMBER | STRING | "true" | "false" | "nil"
-                   |"(" expression ")" ;
+   unary          → ( "!" | "-" ) unary | call ;
+   call           → primary ( "(" arguments? ")" | "[" expression "]" )* ;
+   arguments      → expression ( "," expression )* ;
+   primary        → NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")" | IDENTIFIER | list | map | interpolation ;
+   list           → "[" arguments? "]" ;
+   map            → "{" ( entry ( "," entry )* )? "}" ;
+   entry          → expression ":" expression ;
+   interpolation  → INTERPOLATION expression ( INTERPOLATION expression )* STRING ;
+
+   Comments are `//` to the end of the line or `/* */`, which nest. The scanner attaches the
+   lines of a `///` doc comment to the next token, and declarations keep them on their name.
+
+   The scanner splits `"a${x}b"` into an INTERPOLATION token for `"a${`, the tokens of `x` and
+   a STRING token for `}b"`.
+
+   `parse_lossless` builds a concrete syntax tree next to the AST, see `syntax`.
+
+   A "{" at the start of a statement is always a block, map literals are only parsed where an
+   expression is expected, e.g. `var m = {};` or `print {"a": 1};`.
+
 
    heavily inspired by https://github.com/mchlrhw/loxide/blob/main/treewalk/src/parser.rs
 */
 use crate::{
-    expression::{Expr, ExprKind, Stmt},
+    expression::{Block, ExprArena, ExprId, ExprKind, Program, Stmt},
+    syntax::{Builder, SyntaxKind, SyntaxNode},
     token::{Token, TokenType},
-    value::Value,
+    value::{self, Value},
 };
 use std::cell::RefCell;
 use thiserror::Error;
 
-macro_rules! bx {
-    ($e: expr) => {
-        Box::new($e)
-    };
-}
-
 #[derive(Clone, Debug, Error)]
 pub enum Error {
     #[error("parse error: {}", .0)]
     ParseErrorCustom(String),
-    #[error("Error on line: {} token: {} parse error: {message}", .token.line, token.token_type.to_string())]
-    ParseErrorToken { token: Token, message: String },
+    /// Reported the way jlox does, e.g. `[line 1] Error at ')': Expect expression.`
+    #[error("[line {}] Error{}: {message}", .token.line, location(token))]
+    ParseErrorToken { token: Box<Token>, message: String },
     #[error("parse error")]
     ParseErrorGeneric,
 }
 
-type ParseResult = Result<Vec<Stmt>, Error>;
-type ExprResult = Result<Expr, Error>;
+fn location(token: &Token) -> String {
+    if token.token_type == TokenType::EOF {
+        " at end".to_string()
+    } else {
+        format!(" at '{}'", token.lexeme())
+    }
+}
+
+// the scanner attaches a doc comment to the keyword of a declaration, the AST keeps it on the name
+fn documented(stmt: StmtResult, keyword: Token) -> StmtResult {
+    let mut stmt = stmt?;
+    if let Stmt::Function { name, .. } | Stmt::Var { name, .. } = &mut stmt {
+        name.doc = name.doc.take().or(keyword.doc);
+    }
+    Ok(stmt)
+}
+
+// the same limit as jlox, so scripts behave the same on both
+const MAX_ARGUMENTS: usize = 255;
+
+type ParseResult = Result<Program, Error>;
+type ExprResult = Result<ExprId, Error>;
 type StmtResult = Result<Stmt, Error>;
 
 #[derive(Debug)]
@@ -49,6 +98,9 @@ pub struct Parser {
     pub tokens: Vec<Token>,
     pub position: RefCell<usize>,
     pub errors: RefCell<Vec<Error>>,
+    pub arena: RefCell<ExprArena>,
+    // only built by `parse_lossless`
+    cst: RefCell<Option<Builder>>,
 }
 
 impl Parser {
@@ -57,9 +109,22 @@ impl Parser {
             tokens: tokens.to_owned(),
             position: RefCell::new(0),
             errors: RefCell::new(vec![]),
+            arena: RefCell::new(ExprArena::new()),
+            cst: RefCell::new(None),
         }
     }
 
+    /// Parses like `parse`, and also builds the concrete syntax tree of every token. With tokens
+    /// from `Scanner::lossless` the tree prints back to the exact source, even when it has errors.
+    pub fn parse_lossless(&self) -> (ParseResult, SyntaxNode) {
+        self.cst.replace(Some(Builder::default()));
+        let result = self.parse();
+        let mut builder = self.cst.take().expect("the builder is set above");
+        // the EOF token holds the trivia at the end of the source
+        builder.token(self.peek());
+        (result, builder.finish(SyntaxKind::Program))
+    }
+
     pub fn parse(&self) -> ParseResult {
         let mut statements = Vec::new();
         while !self.is_at_end() {
@@ -69,15 +134,23 @@ impl Parser {
         }
 
         if self.errors.borrow().is_empty() {
-            Ok(statements)
+            Ok(Program {
+                arena: self.arena.take(),
+                stmts: statements,
+            })
         } else {
             Err(self.errors.borrow()[0].clone())
         }
     }
 
     fn declaration(&self) -> Option<Stmt> {
-        let res: StmtResult = if self.match_token(vec![TokenType::VAR]).is_some() {
-            self.var_declaration()
+        let start = self.checkpoint();
+        let res: StmtResult = if let Some(keyword) = self.match_token(vec![TokenType::FUN]) {
+            let function = documented(self.function("function"), keyword);
+            self.node(start, SyntaxKind::FunDecl, function)
+        } else if let Some(keyword) = self.match_token(vec![TokenType::VAR]) {
+            let var = documented(self.var_declaration(), keyword);
+            self.node(start, SyntaxKind::VarDecl, var)
         } else {
             self.statement()
         };
@@ -87,11 +160,47 @@ impl Parser {
             Err(err) => {
                 self.errors.borrow_mut().push(err);
                 self.synchronize();
-                None
+                self.node(start, SyntaxKind::Error, None)
             }
         }
     }
 
+    // kind is only used in error messages, methods will reuse this once classes are parsed
+    fn function(&self, kind: &str) -> StmtResult {
+        let name = self.consume(TokenType::IDENTIFIER, &format!("Expect {kind} name."))?;
+        let params_start = self.checkpoint();
+        self.consume(
+            TokenType::LeftParen,
+            &format!("Expect '(' after {kind} name."),
+        )?;
+
+        let mut params = vec![];
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    let error = self.error(
+                        self.peek(),
+                        format!("Can't have more than {MAX_ARGUMENTS} parameters."),
+                    );
+                    self.errors.borrow_mut().push(error);
+                }
+                params.push(self.consume(TokenType::IDENTIFIER, "Expect parameter name.")?);
+                if self.match_token(vec![TokenType::COMMA]).is_none() {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RightParen, "Expect ')' after parameters.")?;
+        self.node(params_start, SyntaxKind::ParamList, ());
+
+        let body = self.braced_block(&format!("Expect '{{' before {kind} body."))?;
+        Ok(Stmt::Function {
+            name,
+            params,
+            body: body.stmts.into(),
+        })
+    }
+
     fn var_declaration(&self) -> StmtResult {
         let name = self.consume(TokenType::IDENTIFIER, "Expect variable name.")?;
 
@@ -125,7 +234,9 @@ impl Parser {
                 | TokenType::IF
                 | TokenType::WHILE
                 | TokenType::PRINT
-                | TokenType::RETURN => {
+                | TokenType::RETURN
+                | TokenType::THROW
+                | TokenType::TRY => {
                     return;
                 }
                 _ => {
@@ -151,10 +262,33 @@ impl Parser {
         self.peek().token_type == token_type
     }
 
+    // looks `offset` tokens past the current one without consuming anything
+    fn check_ahead(&self, offset: usize, token_type: TokenType) -> bool {
+        self.tokens
+            .get(*self.position.borrow() + offset)
+            .is_some_and(|token| token.token_type == token_type)
+    }
+
     fn increment_position(&self) {
+        if let Some(builder) = self.cst.borrow_mut().as_mut() {
+            builder.token(self.peek());
+        }
         *self.position.borrow_mut() += 1;
     }
 
+    // where the node of a rule starts, for `node` once the rule is done
+    fn checkpoint(&self) -> usize {
+        self.cst.borrow().as_ref().map_or(0, Builder::checkpoint)
+    }
+
+    // wraps what was consumed since `checkpoint` into a syntax node, passing `result` through
+    fn node<T>(&self, checkpoint: usize, kind: SyntaxKind, result: T) -> T {
+        if let Some(builder) = self.cst.borrow_mut().as_mut() {
+            builder.node(checkpoint, kind);
+        }
+        result
+    }
+
     fn advance(&self) -> Token {
         if !self.is_at_end() {
             self.increment_position();
@@ -180,18 +314,21 @@ impl Parser {
         Err(self.error(self.peek(), message.to_string()))
     }
 
+    // `line` is that of the expression's first token, see `line`
+    fn expr(&self, kind: ExprKind, line: usize) -> ExprId {
+        self.arena.borrow_mut().alloc_at(kind, line)
+    }
+
+    // the line of the next token, taken before parsing an expression as the line it starts on
+    fn line(&self) -> usize {
+        self.tokens[*self.position.borrow()].line
+    }
+
     fn error(&self, token: Token, message: String) -> Error {
-        let msg = if token.token_type == TokenType::EOF {
-            format!("{} at end", message.as_str())
-        } else {
-            format!(
-                "line: {} at {}, {}",
-                token.line,
-                token.clone().to_lexme(),
-                message.as_str(),
-            )
-        };
-        Error::ParseErrorCustom(msg)
+        Error::ParseErrorToken {
+            token: Box::new(token),
+            message,
+        }
     }
 
     fn is_at_end(&self) -> bool {
@@ -199,15 +336,196 @@ impl Parser {
     }
 
     fn statement(&self) -> StmtResult {
-        if self.match_token(vec![TokenType::PRINT]).is_some() {
-            return self.print_statement();
+        let start = self.checkpoint();
+        if self.match_token(vec![TokenType::FOR]).is_some() {
+            let stmt = self.for_statement();
+            let kind = match stmt {
+                Ok(Stmt::ForIn { .. }) => SyntaxKind::ForInStmt,
+                _ => SyntaxKind::ForStmt,
+            };
+            return self.node(start, kind, stmt);
+        } else if self.match_token(vec![TokenType::IF]).is_some() {
+            return self.node(start, SyntaxKind::IfStmt, self.if_statement());
+        } else if self.match_token(vec![TokenType::PRINT]).is_some() {
+            return self.node(start, SyntaxKind::PrintStmt, self.print_statement());
+        } else if self.match_token(vec![TokenType::RETURN]).is_some() {
+            return self.node(start, SyntaxKind::ReturnStmt, self.return_statement());
+        } else if self.match_token(vec![TokenType::THROW]).is_some() {
+            return self.node(start, SyntaxKind::ThrowStmt, self.throw_statement());
+        } else if self.match_token(vec![TokenType::TRY]).is_some() {
+            return self.node(start, SyntaxKind::TryStmt, self.try_statement());
+        } else if self.match_token(vec![TokenType::WHILE]).is_some() {
+            return self.node(start, SyntaxKind::WhileStmt, self.while_statement());
         } else if self.match_token(vec![TokenType::LeftBrace]).is_some() {
-            return self.block_statment();
+            return self.node(start, SyntaxKind::Block, self.block_statment());
+        }
+        self.node(start, SyntaxKind::ExprStmt, self.expression_statement())
+    }
+
+    // for loops are desugared into a while loop wrapped in blocks for the initializer and increment
+    fn for_statement(&self) -> StmtResult {
+        let keyword = self.previous();
+        self.consume(TokenType::LeftParen, "Expect '(' after 'for'.")?;
+        if self.check(TokenType::VAR) && self.check_ahead(2, TokenType::IN) {
+            return self.for_in_statement();
+        }
+
+        let initializer_start = self.checkpoint();
+        let initializer = if self.match_token(vec![TokenType::SEMICOLON]).is_some() {
+            None
+        } else if self.match_token(vec![TokenType::VAR]).is_some() {
+            let var = self.var_declaration();
+            Some(self.node(initializer_start, SyntaxKind::VarDecl, var)?)
+        } else {
+            let expression = self.expression_statement();
+            Some(self.node(initializer_start, SyntaxKind::ExprStmt, expression)?)
+        };
+
+        let condition = if self.check(TokenType::SEMICOLON) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::SEMICOLON, "Expect ';' after loop condition.")?;
+
+        let increment = if self.check(TokenType::RightParen) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+
+        let mut body = self.statement()?;
+        if let Some(increment) = increment {
+            body = Stmt::Block(Block {
+                brace: keyword.clone(),
+                stmts: vec![body, Stmt::Expression(increment)],
+            });
+        }
+
+        let condition = condition.unwrap_or_else(|| {
+            self.expr(ExprKind::Literal(Some(Value::Boolean(true))), keyword.line)
+        });
+        body = Stmt::While {
+            condition,
+            body: Box::new(body),
+        };
+
+        if let Some(initializer) = initializer {
+            body = Stmt::Block(Block {
+                brace: keyword,
+                stmts: vec![initializer, body],
+            });
         }
-        self.expression_statement()
+        Ok(body)
+    }
+
+    fn for_in_statement(&self) -> StmtResult {
+        self.consume(TokenType::VAR, "Expect 'var' in for loop.")?;
+        let name = self.consume(TokenType::IDENTIFIER, "Expect variable name.")?;
+        self.consume(TokenType::IN, "Expect 'in' after loop variable.")?;
+        let iterable = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after for clauses.")?;
+        let body = Box::new(self.statement()?);
+        Ok(Stmt::ForIn {
+            name,
+            iterable,
+            body,
+        })
+    }
+
+    fn if_statement(&self) -> StmtResult {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after if condition.")?;
+
+        let then_branch = Box::new(self.statement()?);
+        let else_branch = if self.match_token(vec![TokenType::ELSE]).is_some() {
+            Some(Box::new(self.statement()?))
+        } else {
+            None
+        };
+
+        Ok(Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    fn return_statement(&self) -> StmtResult {
+        let keyword = self.previous();
+        let value = if self.check(TokenType::SEMICOLON) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.consume(TokenType::SEMICOLON, "Expect ';' after return value.")?;
+        Ok(Stmt::Return { keyword, value })
+    }
+
+    fn throw_statement(&self) -> StmtResult {
+        let keyword = self.previous();
+        let value = self.expression()?;
+        self.consume(TokenType::SEMICOLON, "Expect ';' after thrown value.")?;
+        Ok(Stmt::Throw { keyword, value })
+    }
+
+    // a try without a catch or finally clause would do nothing, so one of them is required
+    fn try_statement(&self) -> StmtResult {
+        let body = self.braced_block("Expect '{' after 'try'.")?;
+        let catch_start = self.checkpoint();
+        let catch = if self.match_token(vec![TokenType::CATCH]).is_some() {
+            self.consume(TokenType::LeftParen, "Expect '(' after 'catch'.")?;
+            let name = self.consume(TokenType::IDENTIFIER, "Expect exception name.")?;
+            self.consume(TokenType::RightParen, "Expect ')' after exception name.")?;
+            let handler = self.braced_block("Expect '{' before catch body.")?;
+            self.node(catch_start, SyntaxKind::CatchClause, Some((name, handler)))
+        } else {
+            None
+        };
+        let finally_start = self.checkpoint();
+        let finally = if self.match_token(vec![TokenType::FINALLY]).is_some() {
+            let finally = self.braced_block("Expect '{' after 'finally'.")?;
+            self.node(finally_start, SyntaxKind::FinallyClause, Some(finally))
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(self.error(
+                self.peek(),
+                "Expect 'catch' or 'finally' after try block.".to_string(),
+            ));
+        }
+        Ok(Stmt::Try {
+            body: Box::new(body),
+            catch,
+            finally: finally.map(Box::new),
+        })
+    }
+
+    fn while_statement(&self) -> StmtResult {
+        self.consume(TokenType::LeftParen, "Expect '(' after 'while'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "Expect ')' after condition.")?;
+        let body = Box::new(self.statement()?);
+        Ok(Stmt::While { condition, body })
     }
 
     fn block_statment(&self) -> StmtResult {
+        Ok(Stmt::Block(self.block()?))
+    }
+
+    // a `{` followed by the rest of a block
+    fn braced_block(&self, message: &str) -> Result<Block, Error> {
+        let start = self.checkpoint();
+        self.consume(TokenType::LeftBrace, message)?;
+        self.node(start, SyntaxKind::Block, self.block())
+    }
+
+    // parses the declarations of a block, the opening brace has already been consumed
+    fn block(&self) -> Result<Block, Error> {
+        let brace = self.previous();
         let mut statements = vec![];
 
         while !self.check(TokenType::RightBrace) && !self.is_at_end() {
@@ -215,14 +533,18 @@ impl Parser {
                 statements.push(stmt);
             }
         }
-        self.consume(TokenType::RightBrace, "Expecting '}' after block.");
-        Ok(Stmt::Block(statements))
+        self.consume(TokenType::RightBrace, "Expecting '}' after block.")?;
+        Ok(Block {
+            brace,
+            stmts: statements,
+        })
     }
 
     fn print_statement(&self) -> StmtResult {
+        let keyword = self.previous();
         let value = self.expression()?;
         self.consume(TokenType::SEMICOLON, "Expect ';' after value.")?;
-        Ok(Stmt::Print(value))
+        Ok(Stmt::Print { keyword, value })
     }
 
     fn expression_statement(&self) -> StmtResult {
@@ -237,25 +559,85 @@ impl Parser {
     }
 
     fn assignment(&self) -> ExprResult {
-        let expr = self.equality()?;
+        let start = self.checkpoint();
+        let line = self.line();
+        let expr = self.or()?;
 
         if self.match_token(vec![TokenType::EQUAL]).is_some() {
             let equals = self.previous();
-            let value = Box::new(self.assignment()?);
+            let value = self.assignment()?;
 
-            if let ExprKind::Variable(name) = expr.kind {
-                return Ok(Expr::new(ExprKind::Assign {
-                    name: name,
-                    value: value,
-                }));
+            let target = match &self.arena.borrow()[expr].kind {
+                ExprKind::Variable(name) => Some(ExprKind::Assign {
+                    name: name.clone(),
+                    value,
+                }),
+                ExprKind::Subscript {
+                    object,
+                    bracket,
+                    index,
+                } => Some(ExprKind::SubscriptSet {
+                    object: *object,
+                    bracket: bracket.clone(),
+                    index: *index,
+                    value,
+                }),
+                _ => None,
+            };
+            if let Some(kind) = target {
+                return Ok(self.node(start, SyntaxKind::Assign, self.expr(kind, line)));
             }
             return Err(self.error(equals, "Invalid Assignment Target.".to_owned()));
         }
         Ok(expr)
     }
 
+    // logic_or -> logic_and ( "or" logic_and )* ;
+    fn or(&self) -> ExprResult {
+        let start = self.checkpoint();
+        let line = self.line();
+        let mut expr = self.and()?;
+        while self.match_token(vec![TokenType::OR]).is_some() {
+            let operator = self.previous();
+            let right = self.and()?;
+            let logical = self.expr(
+                ExprKind::Logical {
+                    left: expr,
+                    operator,
+                    right,
+                },
+                line,
+            );
+            expr = self.node(start, SyntaxKind::Logical, logical);
+        }
+        Ok(expr)
+    }
+
+    // logic_and -> equality ( "and" equality )* ;
+    fn and(&self) -> ExprResult {
+        let start = self.checkpoint();
+        let line = self.line();
+        let mut expr = self.equality()?;
+        while self.match_token(vec![TokenType::AND]).is_some() {
+            let operator = self.previous();
+            let right = self.equality()?;
+            let logical = self.expr(
+                ExprKind::Logical {
+                    left: expr,
+                    operator,
+                    right,
+                },
+                line,
+            );
+            expr = self.node(start, SyntaxKind::Logical, logical);
+        }
+        Ok(expr)
+    }
+
     // equality -> comparison ( ( "!=" | "==" ) comparison )* ;
     fn equality(&self) -> ExprResult {
+        let start = self.checkpoint();
+        let line = self.line();
         let mut expr = self.comparison()?;
         while self
             .match_token(vec![TokenType::BangEqual, TokenType::EqualEqual])
@@ -263,18 +645,24 @@ impl Parser {
         {
             let operator = self.previous();
             let right = self.comparison()?;
-            expr = Expr::new(ExprKind::Binary {
-                left: bx![expr],
-                operator,
-                right: bx![right],
-            });
+            let binary = self.expr(
+                ExprKind::Binary {
+                    left: expr,
+                    operator,
+                    right,
+                },
+                line,
+            );
+            expr = self.node(start, SyntaxKind::Binary, binary);
         }
         Ok(expr)
     }
 
-    // comparison -> term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+    // comparison -> range ( ( ">" | ">=" | "<" | "<=" ) range )* ;
     fn comparison(&self) -> ExprResult {
-        let mut expr = self.term()?;
+        let start = self.checkpoint();
+        let line = self.line();
+        let mut expr = self.range()?;
         while self
             .match_token(vec![
                 TokenType::GREATER,
@@ -285,97 +673,327 @@ impl Parser {
             .is_some()
         {
             let operator = self.previous();
-            let rightt = self.term()?;
-            expr = Expr::new(ExprKind::Binary {
-                left: bx![expr],
-                operator,
-                right: bx![rightt],
-            });
+            let right = self.range()?;
+            let binary = self.expr(
+                ExprKind::Binary {
+                    left: expr,
+                    operator,
+                    right,
+                },
+                line,
+            );
+            expr = self.node(start, SyntaxKind::Binary, binary);
+        }
+        Ok(expr)
+    }
+
+    // range -> term ( ".." term )? ;
+    fn range(&self) -> ExprResult {
+        let start = self.checkpoint();
+        let line = self.line();
+        let expr = self.term()?;
+        if self.match_token(vec![TokenType::DotDot]).is_some() {
+            let operator = self.previous();
+            let right = self.term()?;
+            let range = self.expr(
+                ExprKind::Binary {
+                    left: expr,
+                    operator,
+                    right,
+                },
+                line,
+            );
+            return Ok(self.node(start, SyntaxKind::Binary, range));
         }
         Ok(expr)
     }
 
     // term -> factor ( ( "-" | "+" ) factor )* ;
     fn term(&self) -> ExprResult {
+        let start = self.checkpoint();
+        let line = self.line();
         let mut expr = self.factor()?;
         while self
             .match_token(vec![TokenType::MINUS, TokenType::PLUS])
             .is_some()
         {
             let operator = self.previous();
-            let right = bx![self.factor()?];
-            expr = Expr::new(ExprKind::Binary {
-                left: bx![expr],
-                operator,
-                right,
-            })
+            let right = self.factor()?;
+            let binary = self.expr(
+                ExprKind::Binary {
+                    left: expr,
+                    operator,
+                    right,
+                },
+                line,
+            );
+            expr = self.node(start, SyntaxKind::Binary, binary);
         }
         Ok(expr)
     }
 
     // factor -> unray ((* | /) unray)*
     fn factor(&self) -> ExprResult {
+        let start = self.checkpoint();
+        let line = self.line();
         let mut expr = self.unary()?;
         while self
             .match_token(vec![TokenType::STAR, TokenType::SLASH])
             .is_some()
         {
             let operator = self.previous();
-            let right = bx![self.unary()?];
-            expr = Expr::new(ExprKind::Binary {
-                left: bx![expr],
-                operator,
-                right,
-            })
+            let right = self.unary()?;
+            let binary = self.expr(
+                ExprKind::Binary {
+                    left: expr,
+                    operator,
+                    right,
+                },
+                line,
+            );
+            expr = self.node(start, SyntaxKind::Binary, binary);
         }
         Ok(expr)
     }
 
-    // unary -> ( "!" | "-" ) unary | primary
+    // unary -> ( "!" | "-" ) unary | call
     fn unary(&self) -> ExprResult {
+        let start = self.checkpoint();
         if self
             .match_token(vec![TokenType::BANG, TokenType::MINUS])
             .is_some()
         {
             let operator = self.previous();
-            let right = bx![self.unary()?];
-            Expr::new(ExprKind::Unary { operator, right });
+            let right = self.unary()?;
+            let line = operator.line;
+            let unary = self.expr(ExprKind::Unary { operator, right }, line);
+            return Ok(self.node(start, SyntaxKind::Unary, unary));
         }
-        self.primary()
+        self.call()
     }
 
-    // primary -> NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")"
+    // call -> primary ( "(" arguments? ")" | "[" expression "]" )*
+    fn call(&self) -> ExprResult {
+        let start = self.checkpoint();
+        let line = self.line();
+        let mut expr = self.primary()?;
+        loop {
+            let arguments_start = self.checkpoint();
+            if self.match_token(vec![TokenType::LeftParen]).is_some() {
+                let call = self.finish_call(expr, arguments_start, line)?;
+                expr = self.node(start, SyntaxKind::Call, call);
+            } else if self.match_token(vec![TokenType::LeftBracket]).is_some() {
+                let index = self.expression()?;
+                let bracket = self.consume(TokenType::RightBracket, "Expect ']' after index.")?;
+                let subscript = self.expr(
+                    ExprKind::Subscript {
+                        object: expr,
+                        bracket,
+                        index,
+                    },
+                    line,
+                );
+                expr = self.node(start, SyntaxKind::Subscript, subscript);
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn finish_call(&self, callee: ExprId, arguments_start: usize, line: usize) -> ExprResult {
+        let mut arguments = vec![];
+        if !self.check(TokenType::RightParen) {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    let error = self.error(
+                        self.peek(),
+                        format!("Can't have more than {MAX_ARGUMENTS} arguments."),
+                    );
+                    self.errors.borrow_mut().push(error);
+                }
+                arguments.push(self.expression()?);
+                if self.match_token(vec![TokenType::COMMA]).is_none() {
+                    break;
+                }
+            }
+        }
+        let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
+        self.node(arguments_start, SyntaxKind::ArgList, ());
+
+        Ok(self.expr(
+            ExprKind::Call {
+                callee,
+                paren,
+                arguments,
+            },
+            line,
+        ))
+    }
+
+    // interpolation -> INTERPOLATION expression ( INTERPOLATION expression )* STRING
+    /**
+     * Lowers `"a${x}b"` into `"a" + (${ x) + "b"`, where `${` is a unary operator that turns
+     * any value into a string. Empty pieces of text are left out. The first `Interpolation`
+     * token has been consumed.
+     */
+    fn interpolation(&self, first: Token) -> ExprResult {
+        let mut result = None;
+        let mut segment = first;
+        loop {
+            result = self.concat(result, &segment);
+            let value = self.expression()?;
+            let operator = Token::new(
+                TokenType::Interpolation,
+                "${".to_string(),
+                None,
+                segment.line,
+            )
+            .with_span(segment.span());
+            let part = self.expr(
+                ExprKind::Unary {
+                    operator,
+                    right: value,
+                },
+                segment.line,
+            );
+            result = Some(match result {
+                Some(left) => self.plus(left, part, segment.line),
+                None => part,
+            });
+
+            match self.match_token(vec![TokenType::Interpolation]) {
+                Some(next) => segment = next,
+                None => {
+                    let end =
+                        self.consume(TokenType::STRING, "Expect end of string interpolation.")?;
+                    let result = self.concat(result, &end);
+                    return Ok(result.expect("an interpolation has at least one part"));
+                }
+            }
+        }
+    }
+
+    // appends the text of a string token to `result`, unless it is empty
+    fn concat(&self, result: Option<ExprId>, segment: &Token) -> Option<ExprId> {
+        let text = segment.literal.as_deref().unwrap_or_default();
+        if text.is_empty() {
+            return result;
+        }
+        let text = ExprKind::Literal(Some(Value::String(value::intern(text))));
+        let text = self.expr(text, segment.line);
+        Some(match result {
+            Some(left) => self.plus(left, text, segment.line),
+            None => text,
+        })
+    }
+
+    // `line` is that of the text or `${` the `+` is made up for, the sum starts where `left` does
+    fn plus(&self, left: ExprId, right: ExprId, line: usize) -> ExprId {
+        let first = self.arena.borrow()[left].line;
+        let operator = Token::new(TokenType::PLUS, "+".to_string(), None, line);
+        self.expr(
+            ExprKind::Binary {
+                left,
+                operator,
+                right,
+            },
+            first,
+        )
+    }
+
+    // primary -> NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")" | IDENTIFIER | list | map | interpolation
     fn primary(&self) -> ExprResult {
-        let token = self.advance();
-        match token.token_type {
+        // checked before anything is consumed, so the error points at the offending token, or at
+        // the end, and synchronizing starts from it
+        let start = self.checkpoint();
+        let token = self.peek();
+        if !matches!(
+            token.token_type,
+            TokenType::FALSE
+                | TokenType::TRUE
+                | TokenType::NIL
+                | TokenType::NUMBER
+                | TokenType::STRING
+                | TokenType::Interpolation
+                | TokenType::LeftParen
+                | TokenType::IDENTIFIER
+                | TokenType::LeftBracket
+                | TokenType::LeftBrace
+        ) {
+            return Err(self.error(token, "Expect expression.".to_string()));
+        }
+        self.advance();
+        let token_type = token.token_type;
+        let result = match token_type {
             TokenType::FALSE
             | TokenType::TRUE
             | TokenType::NIL
             | TokenType::NUMBER
-            | TokenType::STRING => Ok(Expr::new(ExprKind::Literal(Some(Value::from_token(token))))),
+            | TokenType::STRING => match Value::from_token(token.clone()) {
+                Ok(value) => Ok(self.expr(ExprKind::Literal(Some(value)), token.line)),
+                Err(message) => Err(self.error(token, message)),
+            },
             TokenType::LeftParen => {
                 let expr = self.expression()?;
                 self.consume(TokenType::RightParen, "Expect ')' after expression.")?;
-                Ok(Expr::new(ExprKind::Grouping(Box::new(expr))))
+                Ok(self.expr(ExprKind::Grouping(expr), token.line))
             }
-            TokenType::IDENTIFIER => Ok(Expr::new(ExprKind::Variable(self.previous()))),
-            _ => Err(Error::ParseErrorToken {
-                message: "Did not find a matching primary token".to_string(),
-                token: self.peek(),
-            }),
-        }
+            TokenType::IDENTIFIER => Ok(self.expr(ExprKind::Variable(self.previous()), token.line)),
+            TokenType::Interpolation => self.interpolation(token),
+            TokenType::LeftBracket => {
+                let mut elements = vec![];
+                if !self.check(TokenType::RightBracket) {
+                    loop {
+                        elements.push(self.expression()?);
+                        if self.match_token(vec![TokenType::COMMA]).is_none() {
+                            break;
+                        }
+                    }
+                }
+                self.consume(TokenType::RightBracket, "Expect ']' after list elements.")?;
+                Ok(self.expr(ExprKind::List(elements), token.line))
+            }
+            TokenType::LeftBrace => {
+                let mut entries = vec![];
+                if !self.check(TokenType::RightBrace) {
+                    loop {
+                        let entry_start = self.checkpoint();
+                        let key = self.expression()?;
+                        self.consume(TokenType::COLON, "Expect ':' after map key.")?;
+                        let value = self.expression()?;
+                        entries.push(self.node(entry_start, SyntaxKind::MapEntry, (key, value)));
+                        if self.match_token(vec![TokenType::COMMA]).is_none() {
+                            break;
+                        }
+                    }
+                }
+                self.consume(TokenType::RightBrace, "Expect '}' after map entries.")?;
+                let line = token.line;
+                Ok(self.expr(
+                    ExprKind::Map {
+                        brace: token,
+                        entries,
+                    },
+                    line,
+                ))
+            }
+            _ => unreachable!(),
+        };
+        let kind = match token_type {
+            TokenType::LeftParen => SyntaxKind::Grouping,
+            TokenType::IDENTIFIER => SyntaxKind::Variable,
+            TokenType::Interpolation => SyntaxKind::Interpolation,
+            TokenType::LeftBracket => SyntaxKind::List,
+            TokenType::LeftBrace => SyntaxKind::Map,
+            _ => SyntaxKind::Literal,
+        };
+        self.node(start, kind, result)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{
-        expression::{Expr, ExprKind, Stmt},
-        parser::Parser,
-        scanner,
-        token::Token,
-        token::TokenType,
-    };
+    use crate::{expression::Stmt, parser::Parser, scanner};
 
     /**
      * Test that takes the expression let i = 0; and parses it into an AST
@@ -388,7 +1006,10 @@ mod tests {
 
         let parser = Parser::new(&scanner.tokens);
         let expr = parser.expression().unwrap();
-        assert_eq!(expr.to_string(), "(+ (2) (2))");
+        assert_eq!(
+            parser.arena.borrow().display(expr).to_string(),
+            "(+ (2) (2))"
+        );
     }
 
     /**
@@ -400,13 +1021,13 @@ mod tests {
         scanner.scan_tokens();
         let parser = Parser::new(&scanner.tokens);
         let expr = parser.expression().unwrap();
-        assert_eq!(expr.to_string(), "(true)");
+        assert_eq!(parser.arena.borrow().display(expr).to_string(), "(true)");
 
         let mut scanner = scanner::Scanner::new("false".to_string());
         scanner.scan_tokens();
         let parser = Parser::new(&scanner.tokens);
         let expr = parser.expression().unwrap();
-        assert_eq!(expr.to_string(), "(false)");
+        assert_eq!(parser.arena.borrow().display(expr).to_string(), "(false)");
     }
 
     /**
@@ -418,14 +1039,20 @@ mod tests {
         scanner.scan_tokens();
         let parser = Parser::new(&scanner.tokens);
         let expr = parser.expression().unwrap();
-        assert_eq!(expr.to_string(), "(+ (2) (* (2) (2)))");
+        assert_eq!(
+            parser.arena.borrow().display(expr).to_string(),
+            "(+ (2) (* (2) (2)))"
+        );
 
         // and division
         let mut scanner = scanner::Scanner::new("2 + 2 / 2".to_string());
         scanner.scan_tokens();
         let parser = Parser::new(&scanner.tokens);
         let expr = parser.expression().unwrap();
-        assert_eq!(expr.to_string(), "(+ (2) (/ (2) (2)))");
+        assert_eq!(
+            parser.arena.borrow().display(expr).to_string(),
+            "(+ (2) (/ (2) (2)))"
+        );
     }
 
     /**
@@ -437,7 +1064,10 @@ mod tests {
         scanner.scan_tokens();
         let parser = Parser::new(&scanner.tokens);
         let expr = parser.expression().unwrap();
-        assert_eq!(expr.to_string(), "(+ (+ (1) (* (2) (3))) (/ (4) (5)))");
+        assert_eq!(
+            parser.arena.borrow().display(expr).to_string(),
+            "(+ (+ (1) (* (2) (3))) (/ (4) (5)))"
+        );
     }
 
     /**
@@ -450,7 +1080,7 @@ mod tests {
         let parser = Parser::new(&scanner.tokens);
         let expr = parser.expression().unwrap();
         assert_eq!(
-            expr.to_string(),
+            parser.arena.borrow().display(expr).to_string(),
             "(+ (* (group (+ (1) (2))) (3)) (/ (4) (5)))"
         );
     }
@@ -465,7 +1095,7 @@ mod tests {
         let parser = Parser::new(&scanner.tokens);
         let expr = parser.expression().unwrap();
         assert_eq!(
-            expr.to_string(),
+            parser.arena.borrow().display(expr).to_string(),
             "(== (+ (* (group (+ (1) (2))) (3)) (/ (4) (5))) (1))"
         );
     }
@@ -480,7 +1110,7 @@ mod tests {
         let parser = Parser::new(&scanner.tokens);
         let expr = parser.expression().unwrap();
         assert_eq!(
-            expr.to_string(),
+            parser.arena.borrow().display(expr).to_string(),
             "(== (group (== (+ (* (group (+ (1) (2))) (3)) (/ (4) (5))) (1))) (1))"
         );
     }
@@ -493,10 +1123,13 @@ mod tests {
         let mut scanner = scanner::Scanner::new("print 1 + 1;".to_string());
         scanner.scan_tokens();
         let parser = Parser::new(&scanner.tokens);
-        let stmts = parser.parse().unwrap();
+        let program = parser.parse().unwrap();
+        let stmts = &program.stmts;
         assert_eq!(stmts.len(), 1);
-        match stmts.get(0).unwrap() {
-            Stmt::Print(expr) => assert_eq!(expr.to_string(), "(+ (1) (1))"),
+        match stmts.first().unwrap() {
+            Stmt::Print { value: expr, .. } => {
+                assert_eq!(program.arena.display(*expr).to_string(), "(+ (1) (1))")
+            }
             _ => panic!("Expected a print statement"),
         }
     }
@@ -509,15 +1142,20 @@ mod tests {
         let mut scanner = scanner::Scanner::new("print 1 + 1; 1 + 2;".to_string());
         scanner.scan_tokens();
         let parser = Parser::new(&scanner.tokens);
-        let stmts = parser.parse().unwrap();
+        let program = parser.parse().unwrap();
+        let stmts = &program.stmts;
         assert_eq!(stmts.len(), 2);
-        match stmts.get(0).unwrap() {
-            Stmt::Print(expr) => assert_eq!(expr.to_string(), "(+ (1) (1))"),
+        match stmts.first().unwrap() {
+            Stmt::Print { value: expr, .. } => {
+                assert_eq!(program.arena.display(*expr).to_string(), "(+ (1) (1))")
+            }
             _ => panic!("Expected a print statement"),
         }
 
         match stmts.get(1).unwrap() {
-            Stmt::Expression(expr) => assert_eq!(expr.to_string(), "(+ (1) (2))"),
+            Stmt::Expression(expr) => {
+                assert_eq!(program.arena.display(*expr).to_string(), "(+ (1) (2))")
+            }
             _ => panic!("Expected an expression statement"),
         }
     }
@@ -527,16 +1165,169 @@ mod tests {
         let mut scanner = scanner::Scanner::new("var i = 1;".to_string());
         scanner.scan_tokens();
         let parser = Parser::new(&scanner.tokens);
-        let stmts = parser.parse().unwrap();
+        let program = parser.parse().unwrap();
+        let stmts = &program.stmts;
         assert_eq!(stmts.len(), 1);
-        match stmts.get(0).unwrap() {
+        match stmts.first().unwrap() {
             Stmt::Var { initializer, name } => {
                 assert_eq!(name.clone().literal.unwrap(), "i".to_string());
-                assert!(initializer.is_some());
-                // TODO: Not sure how to validate the initialize here
-                // assert_eq!(initializer.unwrap().kind, "1".to_string())
+                assert_eq!(
+                    program.arena.display(initializer.unwrap()).to_string(),
+                    "(1)"
+                );
             }
             _ => panic!("Expected a variable assignment"),
         }
     }
+
+    /**
+     * Expression ids are sequential, so parsing the same source twice gives the same tree
+     */
+    #[test]
+    fn parses_deterministically() {
+        let parse = || {
+            let mut scanner = scanner::Scanner::new("var a = 1 + 2; print a * 3;".to_string());
+            scanner.scan_tokens();
+            Parser::new(&scanner.tokens).parse().unwrap()
+        };
+        let (first, second) = (parse(), parse());
+        assert_eq!(first.arena.len(), 6);
+        assert_eq!(format!("{:?}", first), format!("{:?}", second));
+    }
+
+    fn parse_to_string(source: &str) -> String {
+        let mut scanner = scanner::Scanner::new(source.to_string());
+        scanner.scan_tokens();
+        Parser::new(&scanner.tokens).parse().unwrap().to_string()
+    }
+
+    #[test]
+    fn parses_functions_and_calls() {
+        assert_eq!(
+            parse_to_string("fun add(a, b) { return a + b; } print add(1, 2)(3);"),
+            "(fun add (a b) (return (+ a b)))\n(print (call (call add (1) (2)) (3)))"
+        );
+    }
+
+    #[test]
+    fn parses_control_flow() {
+        assert_eq!(
+            parse_to_string("if (a or b and c) print 1; else print 2; while (a) a = nil;"),
+            "(if (or a (and b c)) (print (1)) (print (2)))\n(while a (; (= a (nil))))"
+        );
+    }
+
+    /**
+     * for loops are desugared into while loops
+     */
+    #[test]
+    fn parses_for_loop() {
+        assert_eq!(
+            parse_to_string("for (var i = 0; i < 3; i = i + 1) print i;"),
+            "(block (var i (0)) (while (< i (3)) (block (print i) (; (= i (+ i (1)))))))"
+        );
+        assert_eq!(parse_to_string("for (;;) {}"), "(while (true) (block))");
+    }
+
+    #[test]
+    fn parses_lists_and_subscripts() {
+        assert_eq!(
+            parse_to_string("var xs = [1, [], f(2)[0]]; xs[1] = xs[0][0];"),
+            "(var xs (list (1) (list) ([] (call f (2)) (0))))\n(; ([]= xs (1) ([] ([] xs (0)) (0))))"
+        );
+    }
+
+    /**
+     * A brace starts a block at the beginning of a statement and a map everywhere else
+     */
+    #[test]
+    fn parses_maps_and_blocks() {
+        assert_eq!(
+            parse_to_string("var m = {\"a\": 1, 2: {}}; { print m[\"a\"]; } print {};"),
+            "(var m (map (\"a\") (1) (2) (map)))\n(block (print ([] m (\"a\"))))\n(print (map))"
+        );
+    }
+
+    #[test]
+    fn parses_for_in_loops() {
+        assert_eq!(
+            parse_to_string("for (var i in 0..n + 1) print i; for (var k in {}) {}"),
+            "(for i (.. (0) (+ n (1))) (print i))\n(for k (map) (block))"
+        );
+        // the C-style loop is still desugared
+        assert_eq!(
+            parse_to_string("for (var i = 0; i < 1;) {}"),
+            "(block (var i (0)) (while (< i (1)) (block)))"
+        );
+    }
+
+    #[test]
+    fn lowers_interpolation_to_concatenation() {
+        assert_eq!(
+            parse_to_string("print \"a${x}b${y + 1}\"; print \"${x}\";"),
+            "(print (+ (+ (+ (\"a\") (${ x)) (\"b\")) (${ (+ y (1)))))\n(print (${ x))"
+        );
+    }
+
+    #[test]
+    fn parses_try_and_throw() {
+        assert_eq!(
+            parse_to_string("try { throw \"x\"; } catch (e) { print e; } finally { f(); }"),
+            "(try (block (throw (\"x\"))) (catch e (block (print e))) (finally (block (; (call f)))))"
+        );
+        assert_eq!(
+            parse_to_string("try {} finally {}"),
+            "(try (block) (finally (block)))"
+        );
+    }
+
+    #[test]
+    fn reports_try_without_handler() {
+        let mut scanner = scanner::Scanner::new("try {} print 1;".to_string());
+        scanner.scan_tokens();
+        let error = Parser::new(&scanner.tokens).parse().unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Expect 'catch' or 'finally' after try block."));
+    }
+
+    #[test]
+    fn reports_errors_like_jlox() {
+        let mut scanner = scanner::Scanner::new("print;\nprint 1 +".to_string());
+        scanner.scan_tokens();
+        let parser = Parser::new(&scanner.tokens);
+        assert!(parser.parse().is_err());
+        let errors: Vec<String> = parser
+            .errors
+            .borrow()
+            .iter()
+            .map(|e| e.to_string())
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "[line 1] Error at ';': Expect expression.",
+                "[line 2] Error at end: Expect expression."
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_missing_paren_after_arguments() {
+        let mut scanner = scanner::Scanner::new("f(1, 2;".to_string());
+        scanner.scan_tokens();
+        let error = Parser::new(&scanner.tokens).parse().unwrap_err();
+        assert!(error.to_string().contains("Expect ')' after arguments."));
+    }
+
+    #[test]
+    fn keeps_doc_comments_on_declarations() {
+        let mut scanner = scanner::Scanner::new(
+            "/// The answer.\nvar answer = 42;\n/// Says hi.\nfun greet() { print \"hi\"; }\n/// Not a declaration.\nprint 1;".to_string(),
+        );
+        scanner.scan_tokens();
+        let program = Parser::new(&scanner.tokens).parse().unwrap();
+        let docs: Vec<_> = program.stmts.iter().map(Stmt::doc).collect();
+        assert_eq!(docs, vec![Some("The answer."), Some("Says hi."), None]);
+    }
 }
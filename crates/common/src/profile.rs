@@ -0,0 +1,381 @@
+/**
+* A profiler, built on `Hook`, for `intepreter profile <script>`.
+*
+* The `Profiler` counts the statements run on every line and the calls of every function, and
+* times them with the wall clock between hook events. The time between two events belongs to the
+* line the innermost call is at, so the time of a line is its self time: what the functions it
+* calls take is theirs. Natives have no lines, their time belongs to the line that called them.
+*
+* Functions are named `name:line` by the line they are declared on, so that two functions with
+* the same name are told apart, natives by their name. Their total time counts recursive calls
+* once, from the outermost call.
+*
+* `Profile::folded` writes every call stack with its self time in microseconds, one per line, in
+* the folded format flamegraph tools like `flamegraph.pl` and `inferno` read:
+*
+*  <script>;fib:1;fib:1 1234
+*/
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    callable::Callable,
+    expression::Stmt,
+    hook::Hook,
+    interpreter::{Error, Interpreter},
+    value::Value,
+};
+
+const SCRIPT: &str = "<script>";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LineStats {
+    /// How many statements on the line ran
+    pub count: u64,
+    pub time: Duration,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FunctionStats {
+    pub calls: u64,
+    pub self_time: Duration,
+    pub total_time: Duration,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub lines: HashMap<usize, LineStats>,
+    /// By the name of the function, see the module docs, the top level is `<script>`
+    pub functions: HashMap<String, FunctionStats>,
+    /// The self time of every call stack, its functions joined with `;` from the outermost in
+    pub stacks: HashMap<String, Duration>,
+}
+
+impl Profile {
+    /// The lines by self time and the functions by total time, slowest first, as tables.
+    /// `source` is the program the lines are quoted from.
+    pub fn table(&self, source: &str) -> String {
+        let source: Vec<_> = source.lines().collect();
+        let total = self
+            .functions
+            .get(SCRIPT)
+            .map_or(Duration::ZERO, |script| script.total_time);
+
+        let mut lines: Vec<_> = self.lines.iter().collect();
+        lines.sort_by(|(a_line, a), (b_line, b)| {
+            (b.time, b.count, a_line).cmp(&(a.time, a.count, b_line))
+        });
+        let mut table = String::new();
+        let _ = writeln!(table, "Lines by self time:");
+        let _ = writeln!(
+            table,
+            "{:>6} {:>10} {:>12} {:>7}  source",
+            "line", "count", "time", "%"
+        );
+        for (line, stats) in lines {
+            let text = source.get(line - 1).map_or("", |text| text.trim());
+            let _ = writeln!(
+                table,
+                "{line:>6} {:>10} {:>12} {:>7}  {text}",
+                stats.count,
+                millis(stats.time),
+                percent(stats.time, total),
+            );
+        }
+
+        let mut functions: Vec<_> = self.functions.iter().collect();
+        functions.sort_by(|(a_name, a), (b_name, b)| {
+            (b.total_time, b.self_time, a_name).cmp(&(a.total_time, a.self_time, b_name))
+        });
+        let width = functions.iter().map(|(name, _)| name.len()).max();
+        let width = width.unwrap_or(0).max("function".len());
+        let _ = writeln!(table);
+        let _ = writeln!(table, "Functions by total time:");
+        let _ = writeln!(
+            table,
+            "{:width$} {:>10} {:>12} {:>12} {:>7}",
+            "function", "calls", "self", "total", "%"
+        );
+        for (name, stats) in functions {
+            let _ = writeln!(
+                table,
+                "{name:width$} {:>10} {:>12} {:>12} {:>7}",
+                stats.calls,
+                millis(stats.self_time),
+                millis(stats.total_time),
+                percent(stats.total_time, total),
+            );
+        }
+        table
+    }
+
+    /// The call stacks in the folded format, sorted, without the ones that took no time at all
+    pub fn folded(&self) -> String {
+        let mut stacks: Vec<_> = self
+            .stacks
+            .iter()
+            .map(|(stack, time)| (stack, time.as_micros()))
+            .filter(|(_, micros)| *micros > 0)
+            .collect();
+        stacks.sort();
+        stacks
+            .into_iter()
+            .map(|(stack, micros)| format!("{stack} {micros}\n"))
+            .collect()
+    }
+}
+
+fn millis(time: Duration) -> String {
+    format!("{:.3}ms", time.as_secs_f64() * 1000.0)
+}
+
+fn percent(time: Duration, total: Duration) -> String {
+    if total.is_zero() {
+        return "-".to_string();
+    }
+    format!("{:.1}%", time.as_secs_f64() / total.as_secs_f64() * 100.0)
+}
+
+pub struct Profiler {
+    profile: Profile,
+    // the calls being made, outermost first, starting with the top level
+    frames: Vec<Frame>,
+    // when the last event happened, the time since then is the innermost frame's
+    last: Instant,
+}
+
+struct Frame {
+    function: String,
+    // the line the call is at, the line it was called from until its first statement runs
+    line: usize,
+    stack: String,
+    start: Instant,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Profiler {
+    /// A profiler whose clock starts now, set it as the hook right before running the program
+    pub fn new() -> Self {
+        let now = Instant::now();
+        let mut profile = Profile::default();
+        profile
+            .functions
+            .entry(SCRIPT.to_string())
+            .or_default()
+            .calls = 1;
+        Profiler {
+            profile,
+            frames: vec![Frame {
+                function: SCRIPT.to_string(),
+                line: 0,
+                stack: SCRIPT.to_string(),
+                start: now,
+            }],
+            last: now,
+        }
+    }
+
+    /// Stops the clock and hands over what was measured so far
+    pub fn finish(&mut self) -> Profile {
+        let now = self.tick();
+        let start = self.frames[0].start;
+        self.profile
+            .functions
+            .entry(SCRIPT.to_string())
+            .or_default()
+            .total_time = now - start;
+        std::mem::take(&mut self.profile)
+    }
+
+    // gives the time since the last event to the innermost frame
+    fn tick(&mut self) -> Instant {
+        let now = Instant::now();
+        let elapsed = now - self.last;
+        self.last = now;
+        let frame = self
+            .frames
+            .last()
+            .expect("the top level frame is never popped");
+        if frame.line > 0 {
+            self.profile.lines.entry(frame.line).or_default().time += elapsed;
+        }
+        let function = self.profile.functions.entry(frame.function.clone());
+        function.or_default().self_time += elapsed;
+        *self.profile.stacks.entry(frame.stack.clone()).or_default() += elapsed;
+        now
+    }
+}
+
+impl Hook for Profiler {
+    fn before_statement(
+        &mut self,
+        _interpreter: &Interpreter,
+        _stmt: &Stmt,
+        line: usize,
+    ) -> Result<(), Error> {
+        self.tick();
+        self.profile.lines.entry(line).or_default().count += 1;
+        if let Some(frame) = self.frames.last_mut() {
+            frame.line = line;
+        }
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        _interpreter: &Interpreter,
+        callable: &Callable,
+        _arguments: &[Value],
+        line: usize,
+    ) {
+        let start = self.tick();
+        let function = match callable {
+            Callable::Function(function) => format!("{}:{}", callable.name(), function.name.line),
+            Callable::Native(_) => callable.name().to_string(),
+        };
+        self.profile
+            .functions
+            .entry(function.clone())
+            .or_default()
+            .calls += 1;
+        let caller = self
+            .frames
+            .last()
+            .expect("the top level frame is never popped");
+        let stack = format!("{};{function}", caller.stack);
+        self.frames.push(Frame {
+            function,
+            line,
+            stack,
+            start,
+        });
+    }
+
+    fn returned(
+        &mut self,
+        _interpreter: &Interpreter,
+        _callable: &Callable,
+        _result: Result<&Value, &Error>,
+    ) {
+        let now = self.tick();
+        let frame = self
+            .frames
+            .pop()
+            .expect("popped by the call it returns from");
+        // a recursive call is already timed by the outermost call of the function
+        if self.frames.iter().all(|f| f.function != frame.function) {
+            let function = self.profile.functions.entry(frame.function).or_default();
+            function.total_time += now - frame.start;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use super::{FunctionStats, LineStats, Profile, Profiler};
+    use crate::{interpreter::Interpreter, parser::Parser, scanner::Scanner};
+
+    fn profile(source: &str) -> Profile {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.scan_tokens();
+        let program = Parser::new(&scanner.tokens).parse().unwrap();
+        let profiler = Rc::new(RefCell::new(Profiler::new()));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_hook(profiler.clone());
+        interpreter.interpret(program).unwrap();
+        let profile = profiler.borrow_mut().finish();
+        profile
+    }
+
+    #[test]
+    fn counts_lines_and_calls() {
+        let profile = profile(
+            "\
+fun fib(n) {
+  if (n < 2) return n;
+  return fib(n - 1) + fib(n - 2);
+}
+var list = [];
+push(list, fib(5));",
+        );
+        let count = |line| profile.lines[&line].count;
+        // fib(5) makes 15 calls, 8 of them return on line 2
+        assert_eq!((count(1), count(2), count(3)), (1, 15 + 8, 7));
+        assert_eq!((count(5), count(6)), (1, 1));
+        assert_eq!(profile.functions["fib:1"].calls, 15);
+        assert_eq!(profile.functions["push"].calls, 1);
+
+        let script = profile.functions["<script>"];
+        let fib = profile.functions["fib:1"];
+        assert!(fib.total_time >= fib.self_time);
+        assert!(script.total_time >= fib.total_time + script.self_time);
+        let stacks: Vec<_> = profile.stacks.keys().map(String::as_str).collect();
+        assert!(stacks.contains(&"<script>;fib:1;fib:1;fib:1"));
+        assert!(stacks.contains(&"<script>;push"));
+    }
+
+    #[test]
+    fn reports_the_slowest_first() {
+        let ms = Duration::from_millis;
+        let mut profile = Profile::default();
+        profile.lines.insert(
+            1,
+            LineStats {
+                count: 1,
+                time: ms(1),
+            },
+        );
+        profile.lines.insert(
+            2,
+            LineStats {
+                count: 10,
+                time: ms(3),
+            },
+        );
+        profile.functions.insert(
+            "<script>".to_string(),
+            FunctionStats {
+                calls: 1,
+                self_time: ms(1),
+                total_time: ms(4),
+            },
+        );
+        profile.functions.insert(
+            "f:2".to_string(),
+            FunctionStats {
+                calls: 10,
+                self_time: ms(3),
+                total_time: ms(3),
+            },
+        );
+        profile.stacks.insert("<script>".to_string(), ms(1));
+        profile.stacks.insert("<script>;f:2".to_string(), ms(3));
+        profile.stacks.insert("<script>;len".to_string(), ms(0));
+
+        assert_eq!(
+            profile.table("f();\nfun f() {}"),
+            "\
+Lines by self time:
+  line      count         time       %  source
+     2         10      3.000ms   75.0%  fun f() {}
+     1          1      1.000ms   25.0%  f();
+
+Functions by total time:
+function      calls         self        total       %
+<script>          1      1.000ms      4.000ms  100.0%
+f:2              10      3.000ms      3.000ms   75.0%
+"
+        );
+        assert_eq!(profile.folded(), "<script> 1000\n<script>;f:2 3000\n");
+    }
+}
@@ -0,0 +1,541 @@
+/**
+* Reads the S-expressions written by the `Display` impls in `expression` back into an AST.
+*
+* Printing and reading round trips, so `read_program(&program.to_string())` prints the same
+* text again. Line numbers are not part of the printed form, every token read back is on line 0.
+*/
+use std::str::FromStr;
+
+use crate::{
+    expression::{Block, ExprArena, ExprId, ExprKind, Program, Stmt},
+    token::{Token, TokenType},
+    value::{self, Value},
+};
+use thiserror::Error;
+
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum Error {
+    #[error("read error: {}", .0)]
+    ReadErrorCustom(String),
+    #[error("read error: unexpected end of input")]
+    UnexpectedEof,
+}
+
+type ReadResult<T> = Result<T, Error>;
+
+#[derive(Debug, PartialEq)]
+enum Sexp {
+    Atom(String),
+    Str(String),
+    List(Vec<Sexp>),
+}
+
+pub fn read_expr(input: &str, arena: &mut ExprArena) -> ReadResult<ExprId> {
+    let mut sexps = Reader::new(input).read_all()?;
+    if sexps.len() != 1 {
+        return Err(Error::ReadErrorCustom(format!(
+            "expected a single expression, found {}",
+            sexps.len()
+        )));
+    }
+    to_expr(arena, &sexps.remove(0))
+}
+
+pub fn read_program(input: &str) -> ReadResult<Program> {
+    let mut arena = ExprArena::new();
+    let stmts = to_stmts(&mut arena, &Reader::new(input).read_all()?)?;
+    Ok(Program { arena, stmts })
+}
+
+struct Reader<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a str) -> Self {
+        Reader {
+            chars: input.chars().peekable(),
+        }
+    }
+
+    fn read_all(&mut self) -> ReadResult<Vec<Sexp>> {
+        let mut sexps = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.chars.peek().is_none() {
+                return Ok(sexps);
+            }
+            sexps.push(self.read()?);
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+        }
+    }
+
+    fn read(&mut self) -> ReadResult<Sexp> {
+        self.skip_whitespace();
+        match self.chars.next() {
+            None => Err(Error::UnexpectedEof),
+            Some('(') => {
+                let mut items = vec![];
+                loop {
+                    self.skip_whitespace();
+                    match self.chars.peek() {
+                        None => return Err(Error::UnexpectedEof),
+                        Some(')') => {
+                            self.chars.next();
+                            return Ok(Sexp::List(items));
+                        }
+                        Some(_) => items.push(self.read()?),
+                    }
+                }
+            }
+            Some(')') => Err(Error::ReadErrorCustom("unexpected ')'".to_string())),
+            Some('"') => self.string(),
+            Some(c) => {
+                let mut atom = c.to_string();
+                while let Some(&c) = self.chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    atom.push(c);
+                    self.chars.next();
+                }
+                Ok(Sexp::Atom(atom))
+            }
+        }
+    }
+
+    fn string(&mut self) -> ReadResult<Sexp> {
+        let mut result = String::new();
+        loop {
+            match self.chars.next() {
+                None => return Err(Error::UnexpectedEof),
+                Some('"') => return Ok(Sexp::Str(result)),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => result.push('\n'),
+                    Some('r') => result.push('\r'),
+                    Some('t') => result.push('\t'),
+                    Some(c @ ('"' | '\\')) => result.push(c),
+                    Some(c) => {
+                        return Err(Error::ReadErrorCustom(format!("unknown escape '\\{c}'")))
+                    }
+                    None => return Err(Error::UnexpectedEof),
+                },
+                Some(c) => result.push(c),
+            }
+        }
+    }
+}
+
+fn token(token_type: TokenType, lexeme: &str) -> Token {
+    Token::new(token_type, lexeme.to_string(), Some(lexeme.to_string()), 0)
+}
+
+fn identifier(sexp: &Sexp) -> ReadResult<Token> {
+    match sexp {
+        Sexp::Atom(name) if is_identifier(name) => Ok(token(TokenType::IDENTIFIER, name)),
+        _ => Err(Error::ReadErrorCustom(format!(
+            "expected an identifier, found {sexp:?}"
+        ))),
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_alphabetic())
+        && name.chars().all(|c| c.is_alphanumeric())
+}
+
+fn operator(lexeme: &str) -> Option<TokenType> {
+    match TokenType::from_str(lexeme) {
+        Ok(
+            token_type @ (TokenType::MINUS
+            | TokenType::PLUS
+            | TokenType::SLASH
+            | TokenType::STAR
+            | TokenType::BANG
+            | TokenType::BangEqual
+            | TokenType::EqualEqual
+            | TokenType::GREATER
+            | TokenType::GreaterEqual
+            | TokenType::LESS
+            | TokenType::LessEqual
+            | TokenType::DotDot
+            | TokenType::Interpolation
+            | TokenType::AND
+            | TokenType::OR),
+        ) => Some(token_type),
+        _ => None,
+    }
+}
+
+fn is_unary(token_type: TokenType) -> bool {
+    matches!(
+        token_type,
+        TokenType::MINUS | TokenType::BANG | TokenType::Interpolation
+    )
+}
+
+fn literal(atom: &str) -> Option<Value> {
+    match atom {
+        "true" => Some(Value::Boolean(true)),
+        "false" => Some(Value::Boolean(false)),
+        "nil" => Some(Value::Nil),
+        _ => atom.parse::<f64>().ok().map(Value::Number),
+    }
+}
+
+fn to_expr(arena: &mut ExprArena, sexp: &Sexp) -> ReadResult<ExprId> {
+    let kind = match sexp {
+        Sexp::Atom(atom) if atom == "this" => ExprKind::This(token(TokenType::THIS, atom)),
+        Sexp::Atom(atom) if atom == "nil" => ExprKind::Literal(None),
+        Sexp::Atom(_) => ExprKind::Variable(identifier(sexp)?),
+        Sexp::Str(s) => {
+            return Err(Error::ReadErrorCustom(format!(
+                "string \"{s}\" must be wrapped in a literal"
+            )))
+        }
+        Sexp::List(items) => match items.as_slice() {
+            [Sexp::Str(s)] => ExprKind::Literal(Some(Value::String(value::intern(s)))),
+            [Sexp::Atom(atom)] => match literal(atom) {
+                Some(value) => ExprKind::Literal(Some(value)),
+                None if atom == "list" => ExprKind::List(vec![]),
+                None if atom == "map" => ExprKind::Map {
+                    brace: token(TokenType::LeftBrace, "{"),
+                    entries: vec![],
+                },
+                None => return Err(Error::ReadErrorCustom(format!("'{atom}' is not a literal"))),
+            },
+            [Sexp::Atom(head), rest @ ..] => expr_form(arena, head, rest)?,
+            _ => {
+                return Err(Error::ReadErrorCustom(format!(
+                    "malformed expression {sexp:?}"
+                )))
+            }
+        },
+    };
+    Ok(arena.alloc(kind))
+}
+
+fn expr_form(arena: &mut ExprArena, head: &str, rest: &[Sexp]) -> ReadResult<ExprKind> {
+    let kind = match (head, rest) {
+        ("group", [inner]) => ExprKind::Grouping(to_expr(arena, inner)?),
+        ("=", [name, value]) => ExprKind::Assign {
+            name: identifier(name)?,
+            value: to_expr(arena, value)?,
+        },
+        ("call", [callee, arguments @ ..]) => ExprKind::Call {
+            callee: to_expr(arena, callee)?,
+            paren: token(TokenType::RightParen, ")"),
+            arguments: arguments
+                .iter()
+                .map(|argument| to_expr(arena, argument))
+                .collect::<ReadResult<_>>()?,
+        },
+        (".", [object, name]) => ExprKind::Get {
+            object: to_expr(arena, object)?,
+            name: identifier(name)?,
+        },
+        (".=", [object, name, value]) => ExprKind::Set {
+            object: to_expr(arena, object)?,
+            name: identifier(name)?,
+            value: to_expr(arena, value)?,
+        },
+        ("list", elements) => ExprKind::List(
+            elements
+                .iter()
+                .map(|element| to_expr(arena, element))
+                .collect::<ReadResult<_>>()?,
+        ),
+        ("map", entries) if entries.len() % 2 == 0 => ExprKind::Map {
+            brace: token(TokenType::LeftBrace, "{"),
+            entries: entries
+                .chunks(2)
+                .map(|entry| Ok((to_expr(arena, &entry[0])?, to_expr(arena, &entry[1])?)))
+                .collect::<ReadResult<_>>()?,
+        },
+        ("[]", [object, index]) => ExprKind::Subscript {
+            object: to_expr(arena, object)?,
+            bracket: token(TokenType::RightBracket, "]"),
+            index: to_expr(arena, index)?,
+        },
+        ("[]=", [object, index, value]) => ExprKind::SubscriptSet {
+            object: to_expr(arena, object)?,
+            bracket: token(TokenType::RightBracket, "]"),
+            index: to_expr(arena, index)?,
+            value: to_expr(arena, value)?,
+        },
+        ("super", [method]) => ExprKind::Super {
+            keyword: token(TokenType::SUPER, "super"),
+            method: identifier(method)?,
+        },
+        (op, [right]) if matches!(operator(op), Some(token_type) if is_unary(token_type)) => {
+            ExprKind::Unary {
+                operator: token(operator(op).unwrap(), op),
+                right: to_expr(arena, right)?,
+            }
+        }
+        // `-` is the only operator that is both unary and binary
+        (op, [left, right]) if matches!(operator(op), Some(token_type) if !is_unary(token_type) || token_type == TokenType::MINUS) =>
+        {
+            let token_type = operator(op).unwrap();
+            let (left, operator, right) = (
+                to_expr(arena, left)?,
+                token(token_type, op),
+                to_expr(arena, right)?,
+            );
+            if matches!(token_type, TokenType::AND | TokenType::OR) {
+                ExprKind::Logical {
+                    left,
+                    operator,
+                    right,
+                }
+            } else {
+                ExprKind::Binary {
+                    left,
+                    operator,
+                    right,
+                }
+            }
+        }
+        _ => {
+            return Err(Error::ReadErrorCustom(format!(
+                "unknown expression form '{head}' with {} operands",
+                rest.len()
+            )))
+        }
+    };
+    Ok(kind)
+}
+
+fn to_stmt(arena: &mut ExprArena, sexp: &Sexp) -> ReadResult<Stmt> {
+    let items = match sexp {
+        Sexp::List(items) => items,
+        _ => {
+            return Err(Error::ReadErrorCustom(format!(
+                "expected a statement, found {sexp:?}"
+            )))
+        }
+    };
+    let stmt = match items.as_slice() {
+        [Sexp::Atom(head), rest @ ..] => match (head.as_str(), rest) {
+            ("block", stmts) => Stmt::Block(Block {
+                brace: token(TokenType::LeftBrace, "{"),
+                stmts: to_stmts(arena, stmts)?,
+            }),
+            ("class", [name, rest @ ..]) => {
+                let (superclass, methods) = match rest {
+                    [Sexp::List(sup), methods @ ..]
+                        if sup.first() == Some(&Sexp::Atom("<".to_string())) =>
+                    {
+                        match sup.as_slice() {
+                            [_, superclass] => (Some(to_expr(arena, superclass)?), methods),
+                            _ => {
+                                return Err(Error::ReadErrorCustom(
+                                    "malformed superclass".to_string(),
+                                ))
+                            }
+                        }
+                    }
+                    methods => (None, methods),
+                };
+                Stmt::Class {
+                    name: identifier(name)?,
+                    superclass,
+                    methods: to_stmts(arena, methods)?,
+                }
+            }
+            (";", [expr]) => Stmt::Expression(to_expr(arena, expr)?),
+            ("for", [name, iterable, body]) => Stmt::ForIn {
+                name: identifier(name)?,
+                iterable: to_expr(arena, iterable)?,
+                body: Box::new(to_stmt(arena, body)?),
+            },
+            ("fun", [name, Sexp::List(params), body @ ..]) => Stmt::Function {
+                name: identifier(name)?,
+                params: params.iter().map(identifier).collect::<ReadResult<_>>()?,
+                body: to_stmts(arena, body)?.into(),
+            },
+            ("if", [condition, then_branch, else_branch @ ..]) if else_branch.len() <= 1 => {
+                Stmt::If {
+                    condition: to_expr(arena, condition)?,
+                    then_branch: Box::new(to_stmt(arena, then_branch)?),
+                    else_branch: match else_branch.first() {
+                        Some(else_branch) => Some(Box::new(to_stmt(arena, else_branch)?)),
+                        None => None,
+                    },
+                }
+            }
+            ("print", [expr]) => Stmt::Print {
+                keyword: token(TokenType::PRINT, "print"),
+                value: to_expr(arena, expr)?,
+            },
+            ("return", value @ ([] | [_])) => Stmt::Return {
+                keyword: token(TokenType::RETURN, "return"),
+                value: match value.first() {
+                    Some(value) => Some(to_expr(arena, value)?),
+                    None => None,
+                },
+            },
+            ("throw", [value]) => Stmt::Throw {
+                keyword: token(TokenType::THROW, "throw"),
+                value: to_expr(arena, value)?,
+            },
+            ("try", [body, clauses @ ..]) => {
+                let mut catch = None;
+                let mut finally = None;
+                for clause in clauses {
+                    match clause {
+                        Sexp::List(items) => match items.as_slice() {
+                            [Sexp::Atom(head), name, handler]
+                                if head == "catch" && catch.is_none() && finally.is_none() =>
+                            {
+                                catch = Some((identifier(name)?, block(arena, handler)?));
+                            }
+                            [Sexp::Atom(head), body] if head == "finally" && finally.is_none() => {
+                                finally = Some(Box::new(block(arena, body)?));
+                            }
+                            _ => return Err(malformed_clause(clause)),
+                        },
+                        _ => return Err(malformed_clause(clause)),
+                    }
+                }
+                if catch.is_none() && finally.is_none() {
+                    return Err(Error::ReadErrorCustom(
+                        "try needs a catch or finally clause".to_string(),
+                    ));
+                }
+                Stmt::Try {
+                    body: Box::new(block(arena, body)?),
+                    catch,
+                    finally,
+                }
+            }
+            ("var", [name, initializer @ ..]) if initializer.len() <= 1 => Stmt::Var {
+                name: identifier(name)?,
+                initializer: match initializer.first() {
+                    Some(initializer) => Some(to_expr(arena, initializer)?),
+                    None => None,
+                },
+            },
+            ("while", [condition, body]) => Stmt::While {
+                condition: to_expr(arena, condition)?,
+                body: Box::new(to_stmt(arena, body)?),
+            },
+            _ => {
+                return Err(Error::ReadErrorCustom(format!(
+                    "unknown statement form '{head}'"
+                )))
+            }
+        },
+        _ => {
+            return Err(Error::ReadErrorCustom(format!(
+                "malformed statement {sexp:?}"
+            )))
+        }
+    };
+    Ok(stmt)
+}
+
+// the statements of a `(block ...)` form
+fn block(arena: &mut ExprArena, sexp: &Sexp) -> ReadResult<Block> {
+    match to_stmt(arena, sexp)? {
+        Stmt::Block(block) => Ok(block),
+        _ => Err(Error::ReadErrorCustom(format!(
+            "expected a block, found {sexp:?}"
+        ))),
+    }
+}
+
+fn malformed_clause(clause: &Sexp) -> Error {
+    Error::ReadErrorCustom(format!("malformed try clause {clause:?}"))
+}
+
+fn to_stmts(arena: &mut ExprArena, sexps: &[Sexp]) -> ReadResult<Vec<Stmt>> {
+    sexps.iter().map(|sexp| to_stmt(arena, sexp)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_expr, read_program};
+    use crate::expression::ExprArena;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    fn round_trip_expr(input: &str) {
+        let mut arena = ExprArena::new();
+        let expr = read_expr(input, &mut arena).unwrap();
+        assert_eq!(arena.display(expr).to_string(), input);
+    }
+
+    fn round_trip_stmts(input: &str) {
+        assert_eq!(read_program(input).unwrap().to_string(), input);
+    }
+
+    #[test]
+    fn reads_every_expression_form() {
+        round_trip_expr("(+ (25) (* (10) (group (/ (2) (4)))))");
+        round_trip_expr("(= a (- (1)))");
+        round_trip_expr("(! (true))");
+        round_trip_expr("(+ (\"a\\n\") (${ (- x)))");
+        round_trip_expr("(or (and a (false)) (nil))");
+        round_trip_expr("(call (. obj method) (1) (\"two\") nil)");
+        round_trip_expr("(call f)");
+        round_trip_expr("(.= this field (super init))");
+        round_trip_expr("(== (-0.5) (12.25))");
+        round_trip_expr("([]= xs (0) ([] (list (1) (list) a) (2)))");
+        round_trip_expr("(map (\"a\") (map) (1) (list))");
+    }
+
+    #[test]
+    fn reads_every_statement_form() {
+        round_trip_stmts(
+            "(var a (1))\n(var b)\n(print (+ a b))\n(; (= a (2)))\n(block (print a) (block))",
+        );
+        round_trip_stmts("(if (< a (1)) (print a) (print b))\n(if a (block))");
+        round_trip_stmts("(while (< i (10)) (; (= i (+ i (1)))))");
+        round_trip_stmts("(for x (.. (0) n) (print x))\n(for c (\"abc\") (block))");
+        round_trip_stmts("(fun add (a b) (return (+ a b)))\n(fun noop () (return))");
+        round_trip_stmts(
+            "(try (block (throw (\"x\"))) (catch e (block (print e))) (finally (block)))\n(try (block) (finally (block (print (1)))))",
+        );
+        round_trip_stmts("(class Foo (< Bar) (fun init () (; (.= this x (1)))))\n(class Baz)");
+    }
+
+    #[test]
+    fn reads_escaped_strings() {
+        round_trip_expr("(\"a \\\"quoted\\\" (paren)\\n\")");
+        let mut arena = ExprArena::new();
+        let expr = read_expr("(\"tab\\there\")", &mut arena).unwrap();
+        assert_eq!(arena.display(expr).to_string(), "(\"tab\\there\")");
+    }
+
+    #[test]
+    fn round_trips_parser_output() {
+        let source =
+            "var a = 1;\nprint a + 2 * (3 - \"x\");\n{\n  var b = !true;\n  a = b == nil;\n}";
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.scan_tokens();
+        let printed = Parser::new(&scanner.tokens).parse().unwrap().to_string();
+        assert_eq!(
+            printed,
+            "(var a (1))\n(print (+ a (* (2) (group (- (3) (\"x\"))))))\n(block (var b (! (true))) (; (= a (== b (nil)))))"
+        );
+        round_trip_stmts(&printed);
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        let mut arena = ExprArena::new();
+        assert!(read_expr("(+ (1)", &mut arena).is_err());
+        assert!(read_expr("(foo (1) (2))", &mut arena).is_err());
+        assert!(read_expr("(1) (2)", &mut arena).is_err());
+        assert!(read_program("(print)").is_err());
+        assert!(read_expr("(\"bad \\q escape\")", &mut arena).is_err());
+        assert!(read_expr("(map (1))", &mut arena).is_err());
+        assert!(read_program("(try (block))").is_err());
+        assert!(read_program("(try (block) (finally (block)) (catch e (block)))").is_err());
+    }
+}
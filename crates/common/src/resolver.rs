@@ -0,0 +1,340 @@
+/**
+* Static resolution of local variables, see https://craftinginterpreters.com/resolving-and-binding.html
+*
+* Every variable and assignment expression that refers to a local is given a `Slot`: how many
+* scopes to walk up from the current one, and the index of the variable within that scope.
+* Scopes are numbered in the order their declarations execute, parameters first, so at
+* runtime a declaration only has to push its value onto the current scope.
+*
+* Expressions without a slot refer to globals, which are still looked up by name. Globals can be
+* defined later or by natives, so an unknown name is only an error at runtime. When it is close to
+* a local in scope the resolver records that local as a hint for the error message.
+*/
+use std::collections::HashMap;
+
+use crate::{
+    expression::{ExprArena, ExprId, ExprKind, Stmt},
+    suggest,
+    token::Token,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Slot {
+    pub depth: usize,
+    pub index: usize,
+}
+
+/// Side table from `ExprId` to the slot of the local it refers to
+#[derive(Clone, Debug, Default)]
+pub struct Locals {
+    slots: Vec<Option<Slot>>,
+    // locals that unresolved names are likely typos of, by expression index
+    hints: HashMap<usize, String>,
+}
+
+impl Locals {
+    pub fn get(&self, expr: ExprId) -> Option<Slot> {
+        self.slots.get(expr.index()).copied().flatten()
+    }
+
+    /// The local in scope that an unresolved name was probably meant to be
+    pub fn hint(&self, expr: ExprId) -> Option<&str> {
+        self.hints.get(&expr.index()).map(String::as_str)
+    }
+}
+
+#[derive(Default)]
+struct Scope {
+    // the most recent declaration wins, redeclaring a name in the same scope takes a new slot
+    names: HashMap<String, usize>,
+    len: usize,
+}
+
+pub struct Resolver<'a> {
+    arena: &'a ExprArena,
+    scopes: Vec<Scope>,
+    locals: Locals,
+}
+
+impl<'a> Resolver<'a> {
+    pub fn new(arena: &'a ExprArena) -> Self {
+        Resolver {
+            arena,
+            scopes: vec![],
+            locals: Locals {
+                slots: vec![None; arena.len()],
+                hints: HashMap::new(),
+            },
+        }
+    }
+
+    pub fn resolve(mut self, stmts: &[Stmt]) -> Locals {
+        self.resolve_stmts(stmts);
+        self.locals
+    }
+
+    fn resolve_stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.resolve_stmt(stmt);
+        }
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(block) => self.resolve_block(&block.stmts),
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                self.declare(name);
+                if let Some(superclass) = superclass {
+                    self.resolve_expr(*superclass);
+                }
+                for method in methods {
+                    if let Stmt::Function { params, body, .. } = method {
+                        self.resolve_function(params, body);
+                    }
+                }
+            }
+            Stmt::Expression(expr) | Stmt::Print { value: expr, .. } => self.resolve_expr(*expr),
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                // every iteration gets a fresh scope holding just the loop variable
+                self.resolve_expr(*iterable);
+                self.scopes.push(Scope::default());
+                self.declare(name);
+                self.resolve_stmt(body);
+                self.scopes.pop();
+            }
+            Stmt::Function { name, params, body } => {
+                // declared before the body is resolved so the function can call itself
+                self.declare(name);
+                self.resolve_function(params, body);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expr(*condition);
+                self.resolve_stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_stmt(else_branch);
+                }
+            }
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.resolve_expr(*value);
+                }
+            }
+            Stmt::Throw { value, .. } => self.resolve_expr(*value),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.resolve_block(&body.stmts);
+                if let Some((name, handler)) = catch {
+                    // the caught value takes the first slot of the handler's scope
+                    self.scopes.push(Scope::default());
+                    self.declare(name);
+                    self.resolve_stmts(&handler.stmts);
+                    self.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.resolve_block(&finally.stmts);
+                }
+            }
+            Stmt::Var { name, initializer } => {
+                // the initializer is resolved first, so `var a = a;` reads the enclosing `a`
+                if let Some(initializer) = initializer {
+                    self.resolve_expr(*initializer);
+                }
+                self.declare(name);
+            }
+            Stmt::While { condition, body } => {
+                self.resolve_expr(*condition);
+                self.resolve_stmt(body);
+            }
+        }
+    }
+
+    fn resolve_block(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(Scope::default());
+        self.resolve_stmts(stmts);
+        self.scopes.pop();
+    }
+
+    // parameters and the body share a scope, matching how `Interpreter::call` binds arguments
+    fn resolve_function(&mut self, params: &[Token], body: &[Stmt]) {
+        self.scopes.push(Scope::default());
+        for param in params {
+            self.declare(param);
+        }
+        self.resolve_stmts(body);
+        self.scopes.pop();
+    }
+
+    fn resolve_expr(&mut self, expr: ExprId) {
+        match &self.arena[expr].kind {
+            ExprKind::Assign { name, value } => {
+                self.resolve_expr(*value);
+                self.resolve_local(expr, name);
+            }
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+                self.resolve_expr(*left);
+                self.resolve_expr(*right);
+            }
+            ExprKind::Call {
+                callee, arguments, ..
+            } => {
+                self.resolve_expr(*callee);
+                for argument in arguments {
+                    self.resolve_expr(*argument);
+                }
+            }
+            ExprKind::Get { object, .. } => self.resolve_expr(*object),
+            ExprKind::Grouping(inner) => self.resolve_expr(*inner),
+            ExprKind::List(elements) => {
+                for element in elements {
+                    self.resolve_expr(*element);
+                }
+            }
+            ExprKind::Literal(_) | ExprKind::Super { .. } | ExprKind::This(_) => {}
+            ExprKind::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.resolve_expr(*key);
+                    self.resolve_expr(*value);
+                }
+            }
+            ExprKind::Set { object, value, .. } => {
+                self.resolve_expr(*value);
+                self.resolve_expr(*object);
+            }
+            ExprKind::Subscript { object, index, .. } => {
+                self.resolve_expr(*object);
+                self.resolve_expr(*index);
+            }
+            ExprKind::SubscriptSet {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.resolve_expr(*object);
+                self.resolve_expr(*index);
+                self.resolve_expr(*value);
+            }
+            ExprKind::Unary { right, .. } => self.resolve_expr(*right),
+            ExprKind::Variable(name) => self.resolve_local(expr, name),
+        }
+    }
+
+    fn declare(&mut self, name: &Token) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.names.insert(name.lexeme().to_string(), scope.len);
+            scope.len += 1;
+        }
+    }
+
+    fn resolve_local(&mut self, expr: ExprId, name: &Token) {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(&index) = scope.names.get(name.lexeme()) {
+                self.locals.slots[expr.index()] = Some(Slot { depth, index });
+                return;
+            }
+        }
+        let names = self.scopes.iter().flat_map(|scope| scope.names.keys());
+        if let Some(hint) = suggest::closest(name.lexeme(), names.map(String::as_str)) {
+            self.locals.hints.insert(expr.index(), hint.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Locals, Resolver, Slot};
+    use crate::{
+        expression::{ExprId, ExprKind, Program},
+        parser::Parser,
+        scanner::Scanner,
+    };
+
+    fn resolve(source: &str) -> (Program, Locals) {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.scan_tokens();
+        let program = Parser::new(&scanner.tokens).parse().unwrap();
+        let locals = Resolver::new(&program.arena).resolve(&program.stmts);
+        (program, locals)
+    }
+
+    // slots of every variable expression named `name`, in parse order
+    fn slots_of(program: &Program, locals: &Locals, name: &str) -> Vec<Option<Slot>> {
+        (0..program.arena.len() as u32)
+            .map(ExprId)
+            .filter(|id| {
+                matches!(&program.arena[*id].kind, ExprKind::Variable(n) | ExprKind::Assign { name: n, .. } if n.lexeme() == name)
+            })
+            .map(|id| locals.get(id))
+            .collect()
+    }
+
+    #[test]
+    fn globals_have_no_slot() {
+        // the target of `a = 2` is parsed as a variable before it becomes an assignment
+        let (program, locals) = resolve("var a = 1; print a; a = 2;");
+        assert_eq!(slots_of(&program, &locals, "a"), vec![None; 3]);
+    }
+
+    #[test]
+    fn resolves_depth_and_index() {
+        let (program, locals) = resolve("fun f(a, b) { var c; { print c + b; } }");
+        assert_eq!(
+            slots_of(&program, &locals, "c"),
+            vec![Some(Slot { depth: 1, index: 2 })]
+        );
+        assert_eq!(
+            slots_of(&program, &locals, "b"),
+            vec![Some(Slot { depth: 1, index: 1 })]
+        );
+    }
+
+    #[test]
+    fn initializer_reads_enclosing_variable() {
+        let (program, locals) = resolve("{ var a = 1; { var a = a + 1; print a; } }");
+        assert_eq!(
+            slots_of(&program, &locals, "a"),
+            vec![
+                Some(Slot { depth: 1, index: 0 }),
+                Some(Slot { depth: 0, index: 0 })
+            ]
+        );
+    }
+
+    #[test]
+    fn hints_locals_close_to_unresolved_names() {
+        let (program, locals) = resolve("{ var counter = 1; print countr; print total; }");
+        let hints: Vec<_> = (0..program.arena.len() as u32)
+            .map(ExprId)
+            .filter(|id| matches!(program.arena[*id].kind, ExprKind::Variable(_)))
+            .map(|id| locals.hint(id))
+            .collect();
+        assert_eq!(hints, vec![Some("counter"), None]);
+    }
+
+    #[test]
+    fn redeclaration_takes_a_new_slot() {
+        let (program, locals) = resolve("{ var a = 1; var a = a; print a; }");
+        assert_eq!(
+            slots_of(&program, &locals, "a"),
+            vec![
+                Some(Slot { depth: 0, index: 0 }),
+                Some(Slot { depth: 0, index: 1 })
+            ]
+        );
+    }
+}
@@ -1,14 +1,37 @@
 use std::{collections::HashMap, str::FromStr};
 
-use crate::token::{Token, TokenType};
+use crate::token::{Span, Token, TokenType, Trivia, TriviaKind};
+use thiserror::Error;
+
+/// Errors are reported the way jlox reports them, e.g. `[line 1] Error: Unexpected character.`
+#[derive(Clone, Debug, Error, PartialEq)]
+pub enum Error {
+    // the same message as jlox, so the reference tests match
+    #[error("[line {line}] Error: Unexpected character.")]
+    UnexpectedCharacter { character: char, line: usize },
+    #[error("[line {line}] Error: Unterminated string.")]
+    UnterminatedString { line: usize },
+    #[error("[line {line}] Error: Invalid escape sequence '{escape}'.")]
+    InvalidEscape { escape: String, line: usize },
+    #[error("[line {line}] Error: {message}")]
+    InvalidNumber { message: String, line: usize },
+    #[error("[line {line}] Error: Unterminated block comment.")]
+    UnterminatedComment { line: usize },
+}
 
 pub struct Scanner {
     code: String,
     pub tokens: Vec<Token>,
+    pub errors: Vec<Error>,
     start: usize,
     current: usize,
     line: usize,
-    had_error: bool,
+    // one entry per `${` we are inside of, counting the `{`s opened within it that are still open
+    interpolations: Vec<usize>,
+    // `///` lines waiting for the next token
+    doc: Vec<String>,
+    // whether tokens keep the whitespace and comments around them
+    lossless: bool,
 }
 
 /**
@@ -19,10 +42,22 @@ impl Scanner {
         Scanner {
             code,
             tokens: Vec::new(),
+            errors: Vec::new(),
             start: 0,
             current: 0,
-            line: 0,
-            had_error: false,
+            line: 1,
+            interpolations: Vec::new(),
+            doc: Vec::new(),
+            lossless: false,
+        }
+    }
+
+    /// A scanner whose tokens carry the text between them as trivia, so that the source can be
+    /// reproduced byte for byte from the tokens
+    pub fn lossless(code: String) -> Self {
+        Scanner {
+            lossless: true,
+            ..Scanner::new(code)
         }
     }
 
@@ -38,12 +73,38 @@ impl Scanner {
             self.start = self.current;
             self.scan_token();
         }
+        self.start = self.current;
         self.add_token(TokenType::EOF);
-        println!("{}", self.code);
+        if self.lossless {
+            self.attach_trivia();
+        }
+    }
+
+    /**
+     * Hands the text between tokens to them as trivia. What follows a token on its own line
+     * trails it, everything from the end of that line on leads the next token. Text that was
+     * reported as an error is kept as `Skipped` trivia, so nothing is lost.
+     */
+    fn attach_trivia(&mut self) {
+        let mut end = 0;
+        for i in 0..self.tokens.len() {
+            let mut pieces = trivia(&self.code[end..self.tokens[i].span.start]);
+            if i > 0 {
+                let line_end = pieces
+                    .iter()
+                    .position(|piece| piece.kind == TriviaKind::Newline)
+                    .unwrap_or(pieces.len());
+                let leading = pieces.split_off(line_end);
+                self.tokens[i - 1].trailing = pieces.into();
+                pieces = leading;
+            }
+            self.tokens[i].leading = pieces.into();
+            end = self.tokens[i].span.end;
+        }
     }
 
     fn is_at_end(&mut self) -> bool {
-        return self.current > self.code.len() - 1;
+        self.current >= self.code.len()
     }
 
     fn scan_token(&mut self) {
@@ -55,9 +116,19 @@ impl Scanner {
         // Because of the nasty matcher that needs to be refactored.
         // We treat the case of a literal digit seperatly. Since it cant be pattern matched
         // as no tokens exist for it.
-        if c.is_digit(10) {
+        if c.is_ascii_digit() {
             return self.number();
         }
+        // braces inside an interpolation are counted, so the `}` that closes it can be told apart
+        match (c, self.interpolations.last_mut()) {
+            ('{', Some(open)) => *open += 1,
+            ('}', Some(0)) => {
+                self.interpolations.pop();
+                return self.string();
+            }
+            ('}', Some(open)) => *open -= 1,
+            _ => {}
+        }
         // This code is particularly nasty
         // Not sure if it stems from my misunderstanding of rust, or if im just overengineering
         // Basically what I try to do is check to see if a character is a two-character token,
@@ -69,7 +140,7 @@ impl Scanner {
 
                 match t {
                     Some(item) => {
-                        let second_char = item.clone();
+                        let second_char = *item;
                         // concantate double char token into 1 string then create token
                         let double_token_str = format!("{}{}", c, second_char);
                         token_to_add = self.match_double(
@@ -81,23 +152,14 @@ impl Scanner {
                     }
                     None => match token_type {
                         TokenType::SPACE | TokenType::SLASHRETURN | TokenType::TAB => {}
-                        TokenType::SEMICOLON => {
-                            if self.peek_next() == '\n' {
-                                self.advance();
-                            } else {
-                                self.line = self.line + 1;
-                            }
-                            self.add_token(token_type);
-                        }
                         TokenType::NEWLINE => {
-                            self.line = self.line + 1;
-                            return;
+                            self.line += 1;
                         }
                         TokenType::SLASH => {
                             if self.match_token_and_advance('/') {
-                                while self.peek() != '\n' && !self.is_at_end() {
-                                    self.advance();
-                                }
+                                self.line_comment();
+                            } else if self.match_token_and_advance('*') {
+                                self.block_comment();
                             } else {
                                 self.add_token(token_type);
                             }
@@ -109,59 +171,242 @@ impl Scanner {
                     },
                 }
             }
-            Err(_) => {
-                self.had_error = true;
-                println!("Error could not parse token {}, line {}", c, self.line)
+            Err(_) => self.errors.push(Error::UnexpectedCharacter {
+                character: c,
+                line: self.line,
+            }),
+        }
+    }
+
+    /**
+     * Skips a `//` comment. Comments starting with exactly three slashes are doc comments, their
+     * text is kept and attached to the next token, which is usually the declaration they document.
+     */
+    fn line_comment(&mut self) {
+        while self.peek() != '\n' && !self.is_at_end() {
+            self.advance();
+        }
+        let comment = self.lexeme();
+        if let Some(text) = comment.strip_prefix("///") {
+            if !text.starts_with('/') {
+                let text = text.strip_prefix(' ').unwrap_or(text).trim_end();
+                self.doc.push(text.to_string());
+            }
+        }
+    }
+
+    // skips a `/* */` comment, which can contain other block comments
+    fn block_comment(&mut self) {
+        let line = self.line;
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                self.errors.push(Error::UnterminatedComment { line });
+                return;
             }
-        };
+            match self.advance() {
+                '/' if self.match_token_and_advance('*') => depth += 1,
+                '*' if self.match_token_and_advance('/') => depth -= 1,
+                '\n' => self.line += 1,
+                _ => {}
+            }
+        }
     }
 
+    /**
+     * Scans a number literal, the first digit has been consumed. Besides `123` and `1.5` these
+     * can be hexadecimal `0xFF`, binary `0b1010` or have an exponent `6.02E23`, and digits may be
+     * grouped with underscores `1_000_000`. The token's literal is the value in decimal, so it
+     * can always be parsed as an `f64`.
+     */
     fn number(&mut self) {
-        while self.peek().is_digit(10) {
+        let first = self.char_at(self.start);
+        let value = match self.peek() {
+            'x' | 'X' if first == '0' => {
+                self.advance();
+                self.radix_number(16, "hexadecimal")
+            }
+            'b' | 'B' if first == '0' => {
+                self.advance();
+                self.radix_number(2, "binary")
+            }
+            _ => self.decimal_number(),
+        };
+        let literal = match value {
+            Ok(value) => value.to_string(),
+            Err(message) => {
+                self.errors.push(Error::InvalidNumber {
+                    message,
+                    line: self.line,
+                });
+                // a placeholder, so a malformed number doesn't cause parse errors as well
+                "0".to_string()
+            }
+        };
+        self.add_token_with_literal(TokenType::NUMBER, Some(literal));
+    }
+
+    fn decimal_number(&mut self) -> Result<f64, String> {
+        let mut separators_ok = self.digits(10, true).1;
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
+            separators_ok &= self.digits(10, false).1;
         }
-
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        if matches!(self.peek(), 'e' | 'E') {
             self.advance();
-
-            while self.peek().is_digit(10) {
+            if matches!(self.peek(), '+' | '-') {
                 self.advance();
             }
+            let (count, ok) = self.digits(10, false);
+            if count == 0 {
+                return Err(format!(
+                    "Expect digits in the exponent of '{}'.",
+                    self.lexeme()
+                ));
+            }
+            separators_ok &= ok;
+        }
+        if !separators_ok {
+            return Err(self.misplaced_separator());
         }
+        self.lexeme()
+            .replace('_', "")
+            .parse()
+            .map_err(|_| format!("Invalid number '{}'.", self.lexeme()))
+    }
 
-        let result = self.code.get(self.start..self.current);
-        match result {
-            Some(literal) => {
-                self.add_token_with_literal(TokenType::NUMBER, Some(literal.to_string()));
-            }
-            None => {
-                println!("Could not parse literal");
+    fn radix_number(&mut self, radix: u32, name: &str) -> Result<f64, String> {
+        let digits_start = self.current;
+        let (count, separators_ok) = self.digits(radix, false);
+        if count == 0 {
+            return Err(format!("Expect {name} digits after '{}'.", self.lexeme()));
+        }
+        // `0b12` or `0xFG` is one malformed number rather than a number and then an identifier
+        if self.peek().is_alphanumeric() {
+            while self.peek().is_alphanumeric() || self.peek() == '_' {
+                self.advance();
             }
+            return Err(format!("Invalid {name} number '{}'.", self.lexeme()));
+        }
+        if !separators_ok {
+            return Err(self.misplaced_separator());
         }
+        // exact up to 2^53, like any other number literal larger values are rounded
+        Ok(self.code[digits_start..self.current]
+            .chars()
+            .filter_map(|c| c.to_digit(radix))
+            .fold(0.0, |value, digit| value * radix as f64 + digit as f64))
     }
 
-    fn string(&mut self) {
-        while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line = self.line + 1;
+    /**
+     * Consumes digits of the given radix and the underscores between them. Returns how many
+     * digits there were, and whether every underscore was between two digits. `after_digit`
+     * says whether the character before the first one was a digit.
+     */
+    fn digits(&mut self, radix: u32, mut after_digit: bool) -> (usize, bool) {
+        let mut count = 0;
+        let mut separators_ok = true;
+        loop {
+            let c = self.peek();
+            if c.is_digit(radix) {
+                count += 1;
+                after_digit = true;
+            } else if c == '_' {
+                separators_ok &= after_digit && self.peek_next().is_digit(radix);
+                after_digit = false;
+            } else {
+                return (count, separators_ok);
             }
             self.advance();
         }
+    }
 
-        if self.is_at_end() {
-            println!("Error found unterminated string on line {}", self.line);
-        }
-        self.advance();
+    fn misplaced_separator(&self) -> String {
+        format!(
+            "Digit separators must be between digits in '{}'.",
+            self.lexeme()
+        )
+    }
+
+    fn lexeme(&self) -> &str {
+        &self.code[self.start..self.current]
+    }
 
-        let result = self.code.get(self.start + 1..self.current - 1);
-        match result {
-            Some(literal) => {
-                self.add_token_with_literal(TokenType::STRING, Some(literal.to_string()));
+    /**
+     * Scans the rest of a string, after its opening quote or after the `}` that closes an
+     * interpolation. The token's literal is the text with escapes processed. A string that
+     * continues with `${` becomes an `Interpolation` token, and scanning carries on with the
+     * tokens of the interpolated expression.
+     */
+    fn string(&mut self) {
+        let mut literal = String::new();
+        loop {
+            if self.is_at_end() {
+                self.errors
+                    .push(Error::UnterminatedString { line: self.line });
+                return;
             }
-            None => {
-                println!("Could not parse literal");
+            match self.advance() {
+                '"' => break,
+                '$' if self.peek() == '{' => {
+                    self.advance();
+                    self.interpolations.push(0);
+                    self.add_token_with_literal(TokenType::Interpolation, Some(literal));
+                    return;
+                }
+                '\\' => {
+                    if let Some(c) = self.escape() {
+                        literal.push(c);
+                    }
+                }
+                c => {
+                    if c == '\n' {
+                        self.line += 1;
+                    }
+                    literal.push(c);
+                }
             }
         }
+        self.add_token_with_literal(TokenType::STRING, Some(literal));
+    }
+
+    // the character an escape sequence stands for, the backslash has been consumed
+    fn escape(&mut self) -> Option<char> {
+        let escape_start = self.current - 1;
+        let c = match self.advance() {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            c @ ('"' | '\\' | '$') => Some(c),
+            'u' => self.unicode_escape(),
+            _ => None,
+        };
+        if c.is_none() {
+            self.errors.push(Error::InvalidEscape {
+                escape: self.code[escape_start..self.current].to_string(),
+                line: self.line,
+            });
+        }
+        c
+    }
+
+    // `\u{...}` with one to six hex digits naming a unicode scalar value
+    fn unicode_escape(&mut self) -> Option<char> {
+        if !self.match_token_and_advance('{') {
+            return None;
+        }
+        let digits_start = self.current;
+        while self.peek().is_ascii_hexdigit() {
+            self.advance();
+        }
+        let digits_end = self.current;
+        if !self.match_token_and_advance('}') || !(1..=6).contains(&(digits_end - digits_start)) {
+            return None;
+        }
+        u32::from_str_radix(&self.code[digits_start..digits_end], 16)
+            .ok()
+            .and_then(char::from_u32)
     }
 
     // peeks to see what the next character is
@@ -169,14 +414,13 @@ impl Scanner {
         if self.is_at_end() {
             return '\0';
         }
-        return self.char_at(self.current);
+        self.char_at(self.current)
     }
 
     fn peek_next(&mut self) -> char {
-        if self.current + 1 >= self.code.len() {
-            return '\0';
-        }
-        return self.char_at(self.current + 1);
+        let mut chars = self.code[self.current..].chars();
+        chars.next();
+        chars.next().unwrap_or('\0')
     }
 
     fn match_token_and_advance(&mut self, expected: char) -> bool {
@@ -186,8 +430,8 @@ impl Scanner {
         if self.char_at(self.current) != expected {
             return false;
         }
-        self.current = self.current + 1;
-        return true;
+        self.current += expected.len_utf8();
+        true
     }
 
     // Checks to see if the current token is a special character
@@ -199,32 +443,28 @@ impl Scanner {
         one_char_token: TokenType,
         two_char_token: TokenType,
     ) -> TokenType {
-        if self.is_at_end() {
-            return TokenType::EOF;
-        }
-        if self.code.chars().nth(self.current).unwrap() != expected {
+        if self.is_at_end() || self.char_at(self.current) != expected {
             return one_char_token;
         };
-        self.current += 1;
-        return two_char_token;
+        self.current += expected.len_utf8();
+        two_char_token
     }
 
+    // `current` is a byte offset, so it moves by the UTF-8 width of the character
     fn advance(&mut self) -> char {
-        match self.code.chars().nth(self.current) {
+        match self.code[self.current..].chars().next() {
             Some(c) => {
-                self.current += 1;
-                return c;
-            }
-            None => {
-                return '\0';
+                self.current += c.len_utf8();
+                c
             }
+            None => '\0',
         }
     }
 
     fn char_at(&mut self, n: usize) -> char {
-        match self.code.chars().nth(n) {
-            Some(c) => return c,
-            None => panic!("Could not read {} th char", n.to_string()),
+        match self.code[n..].chars().next() {
+            Some(c) => c,
+            None => panic!("Could not read char at byte {}", n),
         }
     }
 
@@ -235,12 +475,17 @@ impl Scanner {
     fn add_token_with_literal(&mut self, token_type: TokenType, literal: Option<String>) {
         match self.code.get(self.start..self.current) {
             Some(lexeme) => {
-                let token = Token::new(
+                let mut token = Token::new(
                     token_type,
                     String::from(lexeme),
                     literal.or(Some(String::from(lexeme))),
                     self.line,
-                );
+                )
+                .with_span(Span::new(self.start, self.current));
+                if !self.doc.is_empty() {
+                    token.doc = Some(self.doc.join("\n").into());
+                    self.doc.clear();
+                }
                 self.tokens.push(token);
             }
             None => self.tokens.push(Token::new(
@@ -262,24 +507,81 @@ impl Scanner {
     }
 }
 
+// splits the text between two tokens into trivia
+fn trivia(text: &str) -> Vec<Trivia> {
+    let mut pieces: Vec<Trivia> = vec![];
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let (kind, len) = match c {
+            '\n' => (TriviaKind::Newline, 1),
+            ' ' | '\t' | '\r' => (
+                TriviaKind::Whitespace,
+                rest.find(|c| !matches!(c, ' ' | '\t' | '\r'))
+                    .unwrap_or(rest.len()),
+            ),
+            _ if rest.starts_with("//") => {
+                (TriviaKind::Comment, rest.find('\n').unwrap_or(rest.len()))
+            }
+            _ if rest.starts_with("/*") => (TriviaKind::Comment, block_comment_len(rest)),
+            _ => (TriviaKind::Skipped, c.len_utf8()),
+        };
+        let (text, remaining) = rest.split_at(len);
+        match pieces.last_mut() {
+            Some(last) if kind == TriviaKind::Skipped && last.kind == kind => last.text += text,
+            _ => pieces.push(Trivia {
+                kind,
+                text: text.to_string(),
+            }),
+        }
+        rest = remaining;
+    }
+    pieces
+}
+
+// the length of the possibly nested block comment `text` starts with, or all of it if unterminated
+fn block_comment_len(text: &str) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+    while i < text.len() {
+        if text[i..].starts_with("/*") {
+            depth += 1;
+            i += 2;
+        } else if text[i..].starts_with("*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += text[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+    text.len()
+}
+
 // declare a hashmap of identifiers to token type
 lazy_static! {
     static ref KEYWORDS: HashMap<&'static str, TokenType> = {
         let mut m = HashMap::new();
         m.insert("and", TokenType::AND);
+        m.insert("catch", TokenType::CATCH);
         m.insert("class", TokenType::CLASS);
         m.insert("else", TokenType::ELSE);
         m.insert("false", TokenType::FALSE);
+        m.insert("finally", TokenType::FINALLY);
         m.insert("for", TokenType::FOR);
         m.insert("fun", TokenType::FUN);
         m.insert("if", TokenType::IF);
+        m.insert("in", TokenType::IN);
         m.insert("nil", TokenType::NIL);
         m.insert("or", TokenType::OR);
         m.insert("print", TokenType::PRINT);
         m.insert("return", TokenType::RETURN);
         m.insert("super", TokenType::SUPER);
         m.insert("this", TokenType::THIS);
+        m.insert("throw", TokenType::THROW);
         m.insert("true", TokenType::TRUE);
+        m.insert("try", TokenType::TRY);
         m.insert("var", TokenType::VAR);
         m.insert("while", TokenType::WHILE);
         m
@@ -288,5 +590,233 @@ lazy_static! {
 
 lazy_static! {
     static ref TWO_CHAR_TOKENS: HashMap<char, char> =
-        HashMap::from([('!', '='), ('=', '='), ('<', '='), ('>', '=')]);
+        HashMap::from([('!', '='), ('=', '='), ('<', '='), ('>', '='), ('.', '.')]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, Scanner};
+    use crate::token::{TokenType, Trivia, TriviaKind};
+
+    fn scan(source: &str) -> Scanner {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.scan_tokens();
+        scanner
+    }
+
+    // the type and literal of every token but the EOF
+    fn tokens(source: &str) -> Vec<(TokenType, String)> {
+        let scanner = scan(source);
+        assert_eq!(scanner.errors, vec![]);
+        let mut tokens: Vec<_> = scanner
+            .tokens
+            .iter()
+            .map(|token| (token.token_type, token.literal.clone().unwrap_or_default()))
+            .collect();
+        tokens.pop();
+        tokens
+    }
+
+    #[test]
+    fn processes_escapes() {
+        assert_eq!(
+            tokens(r#""a\tb\n\"c\" \\ \$ \u{e9}\u{1F600}\0""#),
+            vec![(TokenType::STRING, "a\tb\n\"c\" \\ $ é😀\0".to_string())]
+        );
+    }
+
+    #[test]
+    fn reports_invalid_escapes() {
+        let scanner = scan("\"\\q \\u{110000} \\u{}\"\n\"open");
+        assert_eq!(
+            scanner.errors,
+            vec![
+                Error::InvalidEscape {
+                    escape: "\\q".to_string(),
+                    line: 1
+                },
+                Error::InvalidEscape {
+                    escape: "\\u{110000}".to_string(),
+                    line: 1
+                },
+                Error::InvalidEscape {
+                    escape: "\\u{}".to_string(),
+                    line: 1
+                },
+                Error::UnterminatedString { line: 2 },
+            ]
+        );
+        assert_eq!(
+            scan("#").errors[0].to_string(),
+            "[line 1] Error: Unexpected character."
+        );
+    }
+
+    #[test]
+    fn splits_interpolations() {
+        assert_eq!(
+            tokens(r#""a${x}b${ {1: 2}[1] }""#),
+            vec![
+                (TokenType::Interpolation, "a".to_string()),
+                (TokenType::IDENTIFIER, "x".to_string()),
+                (TokenType::Interpolation, "b".to_string()),
+                (TokenType::LeftBrace, "{".to_string()),
+                (TokenType::NUMBER, "1".to_string()),
+                (TokenType::COLON, ":".to_string()),
+                (TokenType::NUMBER, "2".to_string()),
+                (TokenType::RightBrace, "}".to_string()),
+                (TokenType::LeftBracket, "[".to_string()),
+                (TokenType::NUMBER, "1".to_string()),
+                (TokenType::RightBracket, "]".to_string()),
+                (TokenType::STRING, "".to_string()),
+            ]
+        );
+        // a string inside an interpolation can have interpolations of its own
+        let nested: Vec<TokenType> = tokens(r#""${"${1}"}""#)
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect();
+        assert_eq!(
+            nested,
+            vec![
+                TokenType::Interpolation,
+                TokenType::Interpolation,
+                TokenType::NUMBER,
+                TokenType::STRING,
+                TokenType::STRING
+            ]
+        );
+    }
+
+    #[test]
+    fn scans_number_literals() {
+        let numbers: Vec<String> = tokens("12 1.5 0xFF 0Xff 0b1010 1e-9 6.02E23 2.5e+3 1_000_000")
+            .into_iter()
+            .map(|(_, literal)| literal)
+            .collect();
+        assert_eq!(
+            numbers,
+            [
+                "12",
+                "1.5",
+                "255",
+                "255",
+                "10",
+                "0.000000001",
+                "602000000000000000000000",
+                "2500",
+                "1000000"
+            ]
+        );
+        // a `.` without digits after it is not part of the number
+        assert_eq!(
+            tokens("1.").into_iter().map(|(t, _)| t).collect::<Vec<_>>(),
+            vec![TokenType::NUMBER, TokenType::DOT]
+        );
+    }
+
+    #[test]
+    fn reports_malformed_numbers() {
+        let messages = |source: &str| -> Vec<String> {
+            let scanner = scan(source);
+            // every malformed number still becomes a single token
+            assert_eq!(scanner.tokens.len(), 2, "{source}");
+            scanner.errors.iter().map(Error::to_string).collect()
+        };
+        assert_eq!(
+            messages("0x"),
+            ["[line 1] Error: Expect hexadecimal digits after '0x'."]
+        );
+        assert_eq!(
+            messages("0b"),
+            ["[line 1] Error: Expect binary digits after '0b'."]
+        );
+        assert_eq!(
+            messages("1e"),
+            ["[line 1] Error: Expect digits in the exponent of '1e'."]
+        );
+        assert_eq!(
+            messages("1e+"),
+            ["[line 1] Error: Expect digits in the exponent of '1e+'."]
+        );
+        assert_eq!(
+            messages("0xFG"),
+            ["[line 1] Error: Invalid hexadecimal number '0xFG'."]
+        );
+        assert_eq!(
+            messages("0b102"),
+            ["[line 1] Error: Invalid binary number '0b102'."]
+        );
+        for source in ["1_", "1__0", "1_.5", "1.5_", "1e_5", "0x_1"] {
+            assert_eq!(
+                messages(source),
+                [format!(
+                    "[line 1] Error: Digit separators must be between digits in '{source}'."
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn skips_nested_block_comments() {
+        let scanner = scan("1 /* a /* nested */ comment\n */ 2 /**/ 3");
+        assert_eq!(scanner.errors, vec![]);
+        let lines: Vec<_> = scanner
+            .tokens
+            .iter()
+            .map(|t| (t.lexeme(), t.line))
+            .collect();
+        assert_eq!(lines, vec![("1", 1), ("2", 2), ("3", 2), ("", 2)]);
+
+        assert_eq!(
+            scan("1\n/* a /* b */\n").errors,
+            vec![Error::UnterminatedComment { line: 2 }]
+        );
+        assert_eq!(
+            Error::UnterminatedComment { line: 2 }.to_string(),
+            "[line 2] Error: Unterminated block comment."
+        );
+    }
+
+    #[test]
+    fn attaches_doc_comments_to_the_next_token() {
+        let scanner = scan("/// Adds two numbers.\n///\n///  Indented.\n// not a doc\n//// not a doc either\nfun add() {}");
+        let docs: Vec<_> = scanner.tokens.iter().map(|t| t.doc.as_deref()).collect();
+        assert_eq!(docs[0], Some("Adds two numbers.\n\n Indented."));
+        assert!(docs[1..].iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn keeps_trivia_in_lossless_mode() {
+        let mut scanner = Scanner::lossless("a /* b */ // c\n\t#@\n".to_string());
+        scanner.scan_tokens();
+        let trivia = |kind, text: &str| Trivia {
+            kind,
+            text: text.to_string(),
+        };
+        let a = &scanner.tokens[0];
+        assert!(a.leading.is_empty());
+        assert_eq!(
+            *a.trailing,
+            [
+                trivia(TriviaKind::Whitespace, " "),
+                trivia(TriviaKind::Comment, "/* b */"),
+                trivia(TriviaKind::Whitespace, " "),
+                trivia(TriviaKind::Comment, "// c"),
+            ]
+        );
+        // unexpected characters are kept, and the rest of the file leads the EOF token
+        let eof = &scanner.tokens[1];
+        assert_eq!(
+            *eof.leading,
+            [
+                trivia(TriviaKind::Newline, "\n"),
+                trivia(TriviaKind::Whitespace, "\t"),
+                trivia(TriviaKind::Skipped, "#@"),
+                trivia(TriviaKind::Newline, "\n"),
+            ]
+        );
+        // tokens of a normal scan have no trivia
+        assert!(scan("a /* b */").tokens[0].trailing.is_empty());
+    }
 }
@@ -0,0 +1,80 @@
+/**
+* "Did you mean" suggestions for misspelled names.
+*
+* Names are compared with the optimal string alignment distance: the number of single character
+* insertions, deletions, substitutions and transpositions of neighbours needed to turn one into
+* the other. Like rustc, a candidate is only suggested when it is within a third of the length of
+* the name that was written, so short names don't match everything.
+*/
+use std::mem;
+
+/// The candidate closest to `name`, if any is close enough to be a likely typo.
+/// Ties go to the candidate that sorts first, so the suggestion doesn't depend on hash order.
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// The help note appended to an error about an undefined name
+pub fn help(suggestion: &str) -> String {
+    format!("help: did you mean '{suggestion}'?")
+}
+
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // rows i - 2, i - 1 and i of the usual dynamic programming table
+    let mut before: Vec<usize> = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        mem::swap(&mut before, &mut previous);
+        mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{closest, distance};
+
+    #[test]
+    fn measures_edits() {
+        assert_eq!(distance("counter", "counter"), 0);
+        assert_eq!(distance("countr", "counter"), 1);
+        assert_eq!(distance("conuter", "counter"), 1);
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn suggests_only_close_names() {
+        let names = ["counter", "count", "clock", "len"];
+        assert_eq!(closest("conuter", names), Some("counter"));
+        assert_eq!(closest("coutn", names), Some("count"));
+        // equally close names go to the one that sorts first
+        assert_eq!(closest("countr", names), Some("count"));
+        assert_eq!(closest("lne", names), Some("len"));
+        assert_eq!(closest("x", names), None);
+        assert_eq!(closest("total", names), None);
+        // the name itself is not a suggestion
+        assert_eq!(closest("len", names), None);
+    }
+}
@@ -0,0 +1,406 @@
+/**
+* The declarations of a program and the names that refer to them, for editor features like
+* go-to-definition and find-references.
+*
+* Locals are scoped the way the `Resolver` scopes them. Globals are looked up when the code runs,
+* so a function can use a global declared further down the file: a name that isn't a local refers
+* to the closest global declaration of that name before it, or the first one after it.
+*
+* Properties and methods are looked up on objects at runtime, so only the names of variables,
+* functions and classes are resolved.
+*/
+use std::collections::HashMap;
+
+use crate::{
+    expression::{ExprArena, ExprId, ExprKind, Program, Stmt},
+    token::Token,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Class,
+    Function,
+    Method,
+    Parameter,
+    Variable,
+}
+
+#[derive(Clone, Debug)]
+pub struct Definition {
+    pub name: Token,
+    pub kind: SymbolKind,
+    /// The function or class the definition is in, as an index into `Symbols::definitions`
+    pub parent: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Reference {
+    pub name: Token,
+    /// `None` for names that aren't declared in the program, like natives or typos
+    pub definition: Option<usize>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    /// In source order
+    pub definitions: Vec<Definition>,
+    /// Every use of a name, in source order, not including the definitions themselves
+    pub references: Vec<Reference>,
+}
+
+impl Symbols {
+    pub fn of(program: &Program) -> Symbols {
+        let mut collector = Collector {
+            arena: &program.arena,
+            symbols: Symbols::default(),
+            scopes: vec![],
+            globals: HashMap::new(),
+            parent: None,
+            unresolved: vec![],
+        };
+        collector.stmts(&program.stmts);
+        collector.finish()
+    }
+
+    /// The definition declared or referred to by the name at a byte offset. An offset just after
+    /// the name counts, that's where the cursor is after typing it.
+    pub fn definition_at(&self, offset: usize) -> Option<usize> {
+        let contains = |name: &Token| name.span.start <= offset && offset <= name.span.end;
+        self.definitions
+            .iter()
+            .position(|definition| contains(&definition.name))
+            .or_else(|| {
+                self.references
+                    .iter()
+                    .find(|reference| contains(&reference.name))
+                    .and_then(|reference| reference.definition)
+            })
+    }
+
+    /// The names that refer to a definition, in source order
+    pub fn references_to(&self, definition: usize) -> impl Iterator<Item = &Token> {
+        self.references
+            .iter()
+            .filter(move |reference| reference.definition == Some(definition))
+            .map(|reference| &reference.name)
+    }
+}
+
+struct Collector<'a> {
+    arena: &'a ExprArena,
+    symbols: Symbols,
+    // the locals of every scope by name, the latest declaration wins
+    scopes: Vec<HashMap<String, usize>>,
+    // every global declaration of a name, in source order
+    globals: HashMap<String, Vec<usize>>,
+    parent: Option<usize>,
+    // references to globals that aren't declared yet, resolved once they all are
+    unresolved: Vec<usize>,
+}
+
+impl Collector<'_> {
+    fn finish(mut self) -> Symbols {
+        for index in self.unresolved {
+            let reference = &mut self.symbols.references[index];
+            reference.definition = self
+                .globals
+                .get(reference.name.lexeme())
+                .and_then(|definitions| definitions.first())
+                .copied();
+        }
+        // an assignment's value is walked before its target
+        self.symbols
+            .references
+            .sort_by_key(|reference| reference.name.span.start);
+        self.symbols
+    }
+
+    fn define(&mut self, name: &Token, kind: SymbolKind) -> usize {
+        let index = self.symbols.definitions.len();
+        self.symbols.definitions.push(Definition {
+            name: name.clone(),
+            kind,
+            parent: self.parent,
+        });
+        // methods are found through their object, never by name
+        if kind != SymbolKind::Method {
+            match self.scopes.last_mut() {
+                Some(scope) => {
+                    scope.insert(name.lexeme().to_string(), index);
+                }
+                None => self
+                    .globals
+                    .entry(name.lexeme().to_string())
+                    .or_default()
+                    .push(index),
+            }
+        }
+        index
+    }
+
+    fn refer(&mut self, name: &Token) {
+        let definition = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name.lexeme()))
+            .or_else(|| self.globals.get(name.lexeme())?.last())
+            .copied();
+        if definition.is_none() {
+            self.unresolved.push(self.symbols.references.len());
+        }
+        self.symbols.references.push(Reference {
+            name: name.clone(),
+            definition,
+        });
+    }
+
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        self.stmts(stmts);
+        self.scopes.pop();
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Block(block) => self.block(&block.stmts),
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+            } => {
+                let class = self.define(name, SymbolKind::Class);
+                if let Some(superclass) = superclass {
+                    self.expr(*superclass);
+                }
+                let parent = self.parent.replace(class);
+                for method in methods {
+                    if let Stmt::Function { name, params, body } = method {
+                        let method = self.define(name, SymbolKind::Method);
+                        self.function(method, params, body);
+                    }
+                }
+                self.parent = parent;
+            }
+            Stmt::Expression(expr) | Stmt::Print { value: expr, .. } => self.expr(*expr),
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => {
+                self.expr(*iterable);
+                self.scopes.push(HashMap::new());
+                self.define(name, SymbolKind::Variable);
+                self.stmt(body);
+                self.scopes.pop();
+            }
+            Stmt::Function { name, params, body } => {
+                let function = self.define(name, SymbolKind::Function);
+                self.function(function, params, body);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(*condition);
+                self.stmt(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.stmt(else_branch);
+                }
+            }
+            Stmt::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expr(*value);
+                }
+            }
+            Stmt::Throw { value, .. } => self.expr(*value),
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.block(&body.stmts);
+                if let Some((name, handler)) = catch {
+                    self.scopes.push(HashMap::new());
+                    self.define(name, SymbolKind::Variable);
+                    self.stmts(&handler.stmts);
+                    self.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.block(&finally.stmts);
+                }
+            }
+            Stmt::Var { name, initializer } => {
+                // like the resolver, `var a = a;` reads the enclosing `a`
+                if let Some(initializer) = initializer {
+                    self.expr(*initializer);
+                }
+                self.define(name, SymbolKind::Variable);
+            }
+            Stmt::While { condition, body } => {
+                self.expr(*condition);
+                self.stmt(body);
+            }
+        }
+    }
+
+    fn function(&mut self, function: usize, params: &[Token], body: &[Stmt]) {
+        let parent = self.parent.replace(function);
+        self.scopes.push(HashMap::new());
+        for param in params {
+            self.define(param, SymbolKind::Parameter);
+        }
+        self.stmts(body);
+        self.scopes.pop();
+        self.parent = parent;
+    }
+
+    fn expr(&mut self, expr: ExprId) {
+        let arena = self.arena;
+        match &arena[expr].kind {
+            ExprKind::Assign { name, value } => {
+                self.expr(*value);
+                self.refer(name);
+            }
+            ExprKind::Binary { left, right, .. } | ExprKind::Logical { left, right, .. } => {
+                self.expr(*left);
+                self.expr(*right);
+            }
+            ExprKind::Call {
+                callee, arguments, ..
+            } => {
+                self.expr(*callee);
+                for argument in arguments {
+                    self.expr(*argument);
+                }
+            }
+            ExprKind::Get { object, .. } => self.expr(*object),
+            ExprKind::Grouping(inner) => self.expr(*inner),
+            ExprKind::List(elements) => {
+                for element in elements {
+                    self.expr(*element);
+                }
+            }
+            ExprKind::Literal(_) | ExprKind::Super { .. } | ExprKind::This(_) => {}
+            ExprKind::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.expr(*key);
+                    self.expr(*value);
+                }
+            }
+            ExprKind::Set { object, value, .. } => {
+                self.expr(*value);
+                self.expr(*object);
+            }
+            ExprKind::Subscript { object, index, .. } => {
+                self.expr(*object);
+                self.expr(*index);
+            }
+            ExprKind::SubscriptSet {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.expr(*object);
+                self.expr(*index);
+                self.expr(*value);
+            }
+            ExprKind::Unary { right, .. } => self.expr(*right),
+            ExprKind::Variable(name) => self.refer(name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SymbolKind, Symbols};
+    use crate::{parser::Parser, scanner::Scanner};
+
+    fn symbols(source: &str) -> Symbols {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.scan_tokens();
+        Symbols::of(&Parser::new(&scanner.tokens).parse().unwrap())
+    }
+
+    // the line of the definition every reference resolves to, in source order
+    fn resolved_lines(symbols: &Symbols) -> Vec<Option<usize>> {
+        symbols
+            .references
+            .iter()
+            .map(|reference| {
+                reference
+                    .definition
+                    .map(|definition| symbols.definitions[definition].name.line)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn resolves_locals_by_scope() {
+        let symbols = symbols(
+            "\
+var a = 1;
+{
+  var a = a;
+  print a;
+}
+print a;",
+        );
+        assert_eq!(resolved_lines(&symbols), vec![Some(1), Some(3), Some(1)]);
+    }
+
+    #[test]
+    fn resolves_globals_declared_later() {
+        let symbols = symbols(
+            "\
+fun f() {
+  return g(x) + len(x);
+}
+fun g(x) {
+  return x;
+}
+var x = f();
+var x = x;",
+        );
+        // `x` in `f` is the first global `x`, the initializer of the second reads the first
+        assert_eq!(
+            resolved_lines(&symbols),
+            vec![Some(4), Some(7), None, Some(7), Some(4), Some(1), Some(7)]
+        );
+        let kinds: Vec<_> = symbols.definitions.iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                SymbolKind::Function,
+                SymbolKind::Function,
+                SymbolKind::Parameter,
+                SymbolKind::Variable,
+                SymbolKind::Variable
+            ]
+        );
+        assert_eq!(symbols.definitions[2].parent, Some(1));
+    }
+
+    #[test]
+    fn finds_the_definition_under_a_position() {
+        let source = "var count = 0;\ncount = count + 1;";
+        let symbols = symbols(source);
+        assert_eq!(
+            symbols.definition_at(source.find("count").unwrap()),
+            Some(0)
+        );
+        // right after the last `count`
+        assert_eq!(symbols.definition_at(source.rfind(" + ").unwrap()), Some(0));
+        assert_eq!(symbols.definition_at(source.find('0').unwrap()), None);
+        let lines: Vec<_> = symbols.references_to(0).map(|name| name.line).collect();
+        assert_eq!(lines, vec![2, 2]);
+    }
+}
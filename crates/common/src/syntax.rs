@@ -0,0 +1,255 @@
+/**
+* A lossless concrete syntax tree, for tools like formatters that have to keep every detail of
+* the source.
+*
+* The AST drops parentheses, commas and comments, and desugars `for` loops and interpolated
+* strings. The concrete syntax tree keeps every token the parser consumed instead, grouped into
+* nodes for the grammar rules they matched. With tokens from `Scanner::lossless`, which carry
+* the whitespace and comments around them as trivia, printing the tree gives back the exact
+* source, including any part of it that failed to parse.
+*
+*  let mut scanner = Scanner::lossless(source);
+*  scanner.scan_tokens();
+*  let (program, tree) = Parser::new(&scanner.tokens).parse_lossless();
+*  assert_eq!(tree.to_string(), source);
+*
+* Nodes are only ever built around tokens, a rule that consumed nothing leaves no node.
+*/
+use std::fmt::{self, Write};
+
+use crate::token::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Program,
+
+    // declarations and statements
+    Block,
+    ExprStmt,
+    ForInStmt,
+    ForStmt,
+    FunDecl,
+    IfStmt,
+    PrintStmt,
+    ReturnStmt,
+    ThrowStmt,
+    TryStmt,
+    VarDecl,
+    WhileStmt,
+
+    // parts of declarations and statements
+    CatchClause,
+    FinallyClause,
+    ParamList,
+
+    // expressions, `Assign` includes assignments to a subscript
+    ArgList,
+    Assign,
+    Binary,
+    Call,
+    Grouping,
+    Interpolation,
+    List,
+    Literal,
+    Logical,
+    Map,
+    MapEntry,
+    Subscript,
+    Unary,
+    Variable,
+
+    // the tokens of a declaration that failed to parse
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(Token),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    /// The nodes directly below this one
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    /// Every token in the node, in source order
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = vec![];
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a Token>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+
+    /// An indented outline of the tree, one node or token per line, without trivia
+    pub fn tree(&self) -> String {
+        let mut out = String::new();
+        self.write_tree(&mut out, 0);
+        out
+    }
+
+    fn write_tree(&self, out: &mut String, depth: usize) {
+        writeln!(out, "{:indent$}{:?}", "", self.kind, indent = depth * 2).unwrap();
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.write_tree(out, depth + 1),
+                SyntaxElement::Token(token) => writeln!(
+                    out,
+                    "{:indent$}{} {:?}",
+                    "",
+                    token.token_type,
+                    token.lexeme(),
+                    indent = (depth + 1) * 2
+                )
+                .unwrap(),
+            }
+        }
+    }
+}
+
+/// The source text of the node: every token with its trivia
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.tokens() {
+            for trivia in token.leading.iter() {
+                f.write_str(&trivia.text)?;
+            }
+            f.write_str(token.lexeme())?;
+            for trivia in token.trailing.iter() {
+                f.write_str(&trivia.text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/**
+ * Builds the tree bottom up while the parser runs. Consumed tokens are pushed as they come, and
+ * when a rule is done the elements pushed since its checkpoint are wrapped into a node. That way
+ * a rule like `term` can decide it is a `Binary` node after its left operand has been parsed.
+ */
+#[derive(Debug, Default)]
+pub(crate) struct Builder {
+    elements: Vec<SyntaxElement>,
+}
+
+impl Builder {
+    pub(crate) fn checkpoint(&self) -> usize {
+        self.elements.len()
+    }
+
+    pub(crate) fn token(&mut self, token: Token) {
+        self.elements.push(SyntaxElement::Token(token));
+    }
+
+    pub(crate) fn node(&mut self, checkpoint: usize, kind: SyntaxKind) {
+        if checkpoint < self.elements.len() {
+            let children = self.elements.split_off(checkpoint);
+            self.elements
+                .push(SyntaxElement::Node(SyntaxNode { kind, children }));
+        }
+    }
+
+    pub(crate) fn finish(self, kind: SyntaxKind) -> SyntaxNode {
+        SyntaxNode {
+            kind,
+            children: self.elements,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SyntaxKind, SyntaxNode};
+    use crate::{parser::Parser, scanner::Scanner};
+
+    fn parse(source: &str) -> SyntaxNode {
+        let mut scanner = Scanner::lossless(source.to_string());
+        scanner.scan_tokens();
+        Parser::new(&scanner.tokens).parse_lossless().1
+    }
+
+    #[test]
+    fn prints_back_the_source() {
+        let sources = [
+            "",
+            "  // only a comment\n",
+            "/// Adds.\nfun add(a, b) {\n  return a + b; // sum\n}\r\n\nprint add(1, /* two */ 2);\n",
+            "for (var i = 0; i < 3; i = i + 1) { print \"i = ${ i * 2 }\"; }",
+            "try { throw [1, 2][0]; } catch (e) { print {\"e\": e}; } finally {}\n\n",
+            "var x = 0xFF; while (!x) x = -x; if (x) {} else print nil;",
+            "for (var a in 1..3) print a or false and true;",
+        ];
+        for source in sources {
+            assert_eq!(parse(source).to_string(), source);
+        }
+    }
+
+    #[test]
+    fn prints_back_source_with_errors() {
+        let sources = [
+            "var = 1;\nprint 2;",
+            "print (1 + ;\n# @ \"unterminated",
+            "fun f( { /* open",
+            "print 1 +",
+        ];
+        for source in sources {
+            assert_eq!(parse(source).to_string(), source);
+        }
+        let tree = parse("var = 1;\nprint 2;");
+        let kinds: Vec<_> = tree.nodes().map(|node| node.kind).collect();
+        assert_eq!(kinds, vec![SyntaxKind::Error, SyntaxKind::PrintStmt]);
+    }
+
+    #[test]
+    fn groups_tokens_into_nodes() {
+        assert_eq!(
+            parse("a = f(1, 2) * -b;").tree(),
+            "\
+Program
+  ExprStmt
+    Assign
+      Variable
+        IDENTIFIER \"a\"
+      EQUAL \"=\"
+      Binary
+        Call
+          Variable
+            IDENTIFIER \"f\"
+          ArgList
+            LeftParen \"(\"
+            Literal
+              NUMBER \"1\"
+            COMMA \",\"
+            Literal
+              NUMBER \"2\"
+            RightParen \")\"
+        STAR \"*\"
+        Unary
+          MINUS \"-\"
+          Variable
+            IDENTIFIER \"b\"
+    SEMICOLON \";\"
+  EOF \"\"
+"
+        );
+    }
+}
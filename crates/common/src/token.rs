@@ -1,4 +1,7 @@
-use std::fmt::{Debug, Display, Formatter};
+use std::{
+    fmt::{Debug, Display, Formatter},
+    rc::Rc,
+};
 
 use strum_macros::EnumString;
 
@@ -13,6 +16,10 @@ pub enum TokenType {
     LeftBrace,
     #[strum(serialize = "}")]
     RightBrace,
+    #[strum(serialize = "[")]
+    LeftBracket,
+    #[strum(serialize = "]")]
+    RightBracket,
     #[strum(serialize = ",")]
     COMMA,
     #[strum(serialize = ".")]
@@ -47,12 +54,18 @@ pub enum TokenType {
     LESS,
     #[strum(serialize = "<=")]
     LessEqual,
+    #[strum(serialize = "..")]
+    DotDot,
 
     // Literals.
     #[strum(serialize = "")]
     IDENTIFIER,
     #[strum(serialize = "String")]
     STRING,
+    // the text of a string up to a `${`, the interpolated expression's tokens follow it.
+    // Also the operator that turns an interpolated value into a string, see `Parser::primary`.
+    #[strum(serialize = "${")]
+    Interpolation,
     #[strum(serialize = "Number")]
     NUMBER,
 
@@ -71,18 +84,24 @@ pub enum TokenType {
     // Keywords.
     #[strum(serialize = "and")]
     AND,
+    #[strum(serialize = "catch")]
+    CATCH,
     #[strum(serialize = "class")]
     CLASS,
     #[strum(serialize = "else")]
     ELSE,
     #[strum(serialize = "false")]
     FALSE,
+    #[strum(serialize = "finally")]
+    FINALLY,
     #[strum(serialize = "fun")]
     FUN,
     #[strum(serialize = "for")]
     FOR,
     #[strum(serialize = "if")]
     IF,
+    #[strum(serialize = "in")]
+    IN,
     #[strum(serialize = "nil")]
     NIL,
     #[strum(serialize = "or")]
@@ -95,8 +114,12 @@ pub enum TokenType {
     SUPER,
     #[strum(serialize = "this")]
     THIS,
+    #[strum(serialize = "throw")]
+    THROW,
     #[strum(serialize = "true")]
     TRUE,
+    #[strum(serialize = "try")]
+    TRY,
     #[strum(serialize = "var")]
     VAR,
     #[strum(serialize = "while")]
@@ -112,12 +135,56 @@ impl Display for TokenType {
     }
 }
 
+/// Byte range of a token in the scanned source, `end` is exclusive
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    // spaces, tabs and carriage returns
+    Whitespace,
+    Newline,
+    // `//` and `/* */` comments, doc comments included
+    Comment,
+    // text the scanner reported an error for, like an unexpected character
+    Skipped,
+}
+
+/// Source text between tokens, which only a lossless scanner keeps
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub token_type: TokenType,
     lexeme: String,
     pub literal: Option<String>,
     pub line: usize,
+    pub span: Span,
+    /// The `///` comment lines before the token, without the slashes
+    pub doc: Option<Rc<str>>,
+    /// Trivia before the token, from the end of the previous token's line
+    pub leading: Rc<[Trivia]>,
+    /// Trivia after the token up to the end of its line
+    pub trailing: Rc<[Trivia]>,
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        Display::fmt(&self.token_type, f)
+    }
 }
 
 impl Token {
@@ -127,16 +194,20 @@ impl Token {
             lexeme,
             literal,
             line,
+            span: Span::default(),
+            doc: None,
+            leading: Rc::new([]),
+            trailing: Rc::new([]),
         }
     }
 
-    pub fn to_string(self) -> String {
-        let token_as_string = self.token_type.to_string();
-        return token_as_string;
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
     }
 
     pub fn to_lexme(self) -> String {
-        return self.lexeme;
+        self.lexeme
     }
 
     pub fn typ(&self) -> &TokenType {
@@ -150,4 +221,8 @@ impl Token {
     pub fn line(&self) -> usize {
         self.line
     }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
 }
@@ -0,0 +1,336 @@
+/**
+* An execution trace, built on `Hook`, for `intepreter <script> --trace [--json]`.
+*
+* Every statement that has a line of its own, every call and what it returned, and every variable
+* definition and assignment is written as it happens, indented by the depth of the call it happens
+* in. Calls and their returns are at the depth of the caller, the function's body one deeper:
+*
+*  [line 5] print add(1, 2);
+*  call add(1, 2)
+*    [line 2] return a + b;
+*  return add -> 3
+*
+* Statements are shown by the source line they start on, so the `return` in `if (n < 2) return n;`
+* repeats the line of the `if`. A call that raises an error is `unwind` with the first line of the error in place of `return`.
+* Strings are quoted so that `"nil"` and `nil` can be told apart.
+*
+* With `Format::Json` every event is a JSON object on a line of its own:
+*
+*  { "event": "statement", "depth": number, "line": number, "source": string }
+*  { "event": "call", "depth": number, "line": number, "function": string, "arguments": [value*] }
+*  { "event": "return", "depth": number, "function": string, "value": value }
+*  { "event": "unwind", "depth": number, "function": string, "error": string }
+*  { "event": "define" | "assign", "depth": number, "line": number, "name": string, "value": value }
+*
+* Values are written the way `json` writes literals, see `json::value_to_json`.
+*/
+use std::io::Write;
+
+use serde_json::{json, Value as Json};
+
+use crate::{
+    callable::Callable,
+    expression::Stmt,
+    hook::Hook,
+    interpreter::{Error, Interpreter},
+    json::value_to_json,
+    token::Token,
+    value::Value,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+pub struct Tracer<W> {
+    output: W,
+    format: Format,
+    source: Vec<String>,
+}
+
+enum Event<'a> {
+    Statement {
+        line: usize,
+        source: &'a str,
+    },
+    Call {
+        line: usize,
+        function: &'a str,
+        arguments: &'a [Value],
+    },
+    Return {
+        function: &'a str,
+        result: Result<&'a Value, &'a Error>,
+    },
+    Define {
+        name: &'a Token,
+        value: &'a Value,
+    },
+    Assign {
+        name: &'a Token,
+        value: &'a Value,
+    },
+}
+
+impl<W: Write> Tracer<W> {
+    /// A tracer of the program read from `source`, which the statements are quoted from
+    pub fn new(source: &str, format: Format, output: W) -> Self {
+        Tracer {
+            output,
+            format,
+            source: source.lines().map(str::to_string).collect(),
+        }
+    }
+
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    fn write(&mut self, depth: usize, event: Event<'_>) {
+        write(&mut self.output, self.format, depth, event);
+    }
+}
+
+// the trace is best effort, a closed output doesn't stop the program
+fn write(output: &mut impl Write, format: Format, depth: usize, event: Event<'_>) {
+    let _ = match format {
+        Format::Text => writeln!(output, "{:1$}{2}", "", depth * 2, event.text()),
+        Format::Json => writeln!(output, "{}", event.json(depth)),
+    };
+}
+
+impl Event<'_> {
+    fn text(&self) -> String {
+        match self {
+            Event::Statement { line, source } => format!("[line {line}] {source}"),
+            Event::Call {
+                function,
+                arguments,
+                ..
+            } => {
+                let arguments: Vec<_> = arguments.iter().map(quoted).collect();
+                format!("call {function}({})", arguments.join(", "))
+            }
+            Event::Return {
+                function,
+                result: Ok(value),
+            } => format!("return {function} -> {}", quoted(value)),
+            Event::Return {
+                function,
+                result: Err(error),
+            } => {
+                let error = error.to_string();
+                format!("unwind {function}: {}", error.lines().next().unwrap_or(""))
+            }
+            Event::Define { name, value } => {
+                format!("define {} = {}", name.lexeme(), quoted(value))
+            }
+            Event::Assign { name, value } => {
+                format!("assign {} = {}", name.lexeme(), quoted(value))
+            }
+        }
+    }
+
+    fn json(&self, depth: usize) -> Json {
+        match self {
+            Event::Statement { line, source } => json!({
+                "event": "statement",
+                "depth": depth,
+                "line": line,
+                "source": source,
+            }),
+            Event::Call {
+                line,
+                function,
+                arguments,
+            } => json!({
+                "event": "call",
+                "depth": depth,
+                "line": line,
+                "function": function,
+                "arguments": arguments.iter().map(value_to_json).collect::<Vec<_>>(),
+            }),
+            Event::Return {
+                function,
+                result: Ok(value),
+            } => json!({
+                "event": "return",
+                "depth": depth,
+                "function": function,
+                "value": value_to_json(value),
+            }),
+            Event::Return {
+                function,
+                result: Err(error),
+            } => json!({
+                "event": "unwind",
+                "depth": depth,
+                "function": function,
+                "error": error.to_string().lines().next().unwrap_or(""),
+            }),
+            Event::Define { name, value } => variable_json("define", depth, name, value),
+            Event::Assign { name, value } => variable_json("assign", depth, name, value),
+        }
+    }
+}
+
+fn variable_json(event: &str, depth: usize, name: &Token, value: &Value) -> Json {
+    json!({
+        "event": event,
+        "depth": depth,
+        "line": name.line,
+        "name": name.lexeme(),
+        "value": value_to_json(value),
+    })
+}
+
+fn quoted(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("\"{s}\""),
+        _ => value.to_string(),
+    }
+}
+
+impl<W: Write> Hook for Tracer<W> {
+    fn before_statement(
+        &mut self,
+        interpreter: &Interpreter,
+        _stmt: &Stmt,
+        line: usize,
+    ) -> Result<(), Error> {
+        let source = self.source.get(line - 1).map_or("", |text| text.trim());
+        let event = Event::Statement { line, source };
+        write(&mut self.output, self.format, interpreter.depth(), event);
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &Interpreter,
+        callable: &Callable,
+        arguments: &[Value],
+        line: usize,
+    ) {
+        let event = Event::Call {
+            line,
+            function: callable.name(),
+            arguments,
+        };
+        self.write(interpreter.depth(), event);
+    }
+
+    fn returned(
+        &mut self,
+        interpreter: &Interpreter,
+        callable: &Callable,
+        result: Result<&Value, &Error>,
+    ) {
+        let event = Event::Return {
+            function: callable.name(),
+            result,
+        };
+        self.write(interpreter.depth(), event);
+    }
+
+    fn define(&mut self, interpreter: &Interpreter, name: &Token, value: &Value) {
+        self.write(interpreter.depth(), Event::Define { name, value });
+    }
+
+    fn assign(&mut self, interpreter: &Interpreter, name: &Token, value: &Value) {
+        self.write(interpreter.depth(), Event::Assign { name, value });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::{Format, Tracer};
+    use crate::{interpreter::Interpreter, parser::Parser, scanner::Scanner};
+
+    fn trace(source: &str, format: Format) -> String {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.scan_tokens();
+        let program = Parser::new(&scanner.tokens).parse().unwrap();
+        let tracer = Rc::new(RefCell::new(Tracer::new(source, format, vec![])));
+        let mut interpreter = Interpreter::new();
+        interpreter.set_hook(tracer.clone());
+        let _ = interpreter.interpret(program);
+        let output = tracer.borrow().output().clone();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn traces_statements_calls_and_variables() {
+        let source = "\
+fun add(a, b) {
+  var sum = a + b;
+  return sum;
+}
+var x = add(1, 2);
+x = \"done\";";
+        assert_eq!(
+            trace(source, Format::Text),
+            "\
+[line 1] fun add(a, b) {
+define add = <fn add>
+[line 5] var x = add(1, 2);
+call add(1, 2)
+  [line 2] var sum = a + b;
+  define sum = 3
+  [line 3] return sum;
+return add -> 3
+define x = 3
+[line 6] x = \"done\";
+assign x = \"done\"
+"
+        );
+    }
+
+    #[test]
+    fn traces_errors_unwinding_calls() {
+        let source = "fun f(n) {\n  return len(n);\n}\nf(nil);";
+        let output = trace(source, Format::Text);
+        let lines: Vec<_> = output.lines().skip(3).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "call f(nil)",
+                "  [line 2] return len(n);",
+                "  call len(nil)",
+                "  unwind len: Error: len expects a list, map or string, got nil.",
+                "unwind f: Error: len expects a list, map or string, got nil.",
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_json_lines() {
+        let output = trace("var s = \"a\";\ns = len(s);", Format::Json);
+        let events: Vec<serde_json::Value> = output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let names: Vec<_> = events
+            .iter()
+            .map(|e| e["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "statement",
+                "define",
+                "statement",
+                "call",
+                "return",
+                "assign"
+            ]
+        );
+        assert_eq!(events[1]["value"], "a");
+        assert_eq!(events[3]["arguments"], serde_json::json!(["a"]));
+        assert_eq!(events[5]["value"], 1.0);
+        assert_eq!(events[5]["line"], 2);
+    }
+}
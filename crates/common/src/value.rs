@@ -1,22 +1,57 @@
-use std::fmt;
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fmt,
+    hash::{Hash, Hasher},
+    mem,
+    rc::Rc,
+};
 
-use crate::token::{Token, TokenType};
+use indexmap::IndexMap;
+
+use crate::{
+    callable::Callable,
+    number,
+    token::{Token, TokenType},
+};
 
 #[derive(Clone, Debug)]
 pub enum Value {
     Boolean(bool),
+    Callable(Callable),
+    // lists are shared and mutable, so copies of a list value see each other's changes
+    List(Rc<RefCell<Vec<Value>>>),
+    // maps iterate in insertion order, and are shared the same way as lists
+    Map(Rc<RefCell<IndexMap<MapKey, Value>>>),
     Nil,
     Number(f64),
-    String(String),
+    // `start..end`, counting up from start and excluding end
+    Range(f64, f64),
+    // literals are interned, strings built at runtime are not so they can be freed
+    String(Rc<str>),
 }
 
+/**
+ * Equality as used by `==` and `!=`, following clox:
+ * - values of different types are never equal, so `nil == false` and `1 == "1"` are false
+ * - numbers compare as IEEE 754 doubles: `0 == -0` is true and `nan == nan` is false
+ * - strings and ranges compare by contents
+ * - lists, maps and functions compare by identity, two separately created lists are different
+ *   even when they hold the same elements. Any other kind of object added later should too.
+ *
+ * Map keys don't use this, see `MapKey` for how they treat -0 and NaN.
+ */
 impl PartialEq for Value {
     fn eq(&self, other: &Value) -> bool {
         match (self, other) {
             (Value::Boolean(s), Value::Boolean(o)) => s == o,
+            (Value::Callable(s), Value::Callable(o)) => s == o,
+            (Value::List(s), Value::List(o)) => Rc::ptr_eq(s, o),
+            (Value::Map(s), Value::Map(o)) => Rc::ptr_eq(s, o),
             (Value::Nil, Value::Nil) => true,
             (Value::Number(s), Value::Number(o)) => s == o,
-            (Value::String(s), Value::String(o)) => s == o,
+            (Value::Range(s1, s2), Value::Range(o1, o2)) => s1 == o1 && s2 == o2,
+            (Value::String(s), Value::String(o)) => Rc::ptr_eq(s, o) || s == o,
             _ => false,
         }
     }
@@ -24,28 +59,362 @@ impl PartialEq for Value {
 
 impl fmt::Display for Value {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            Self::Boolean(b) => write!(f, "{b}"),
-            // Self::Callable(c) => write!(f, "{c}"),
-            // Self::Instance(i) => write!(f, "{}", i.borrow()),
-            Self::Nil => write!(f, "nil"),
-            Self::Number(n) => write!(f, "{n}"),
-            Self::String(s) => write!(f, "{s}"),
+        write_value(f, self, &mut vec![])
+    }
+}
+
+// `printing` holds the lists and maps being written, one that contains itself is written as
+// `[...]` or `{...}` where it repeats
+fn write_value(
+    f: &mut fmt::Formatter<'_>,
+    value: &Value,
+    printing: &mut Vec<usize>,
+) -> fmt::Result {
+    match value {
+        Value::Boolean(b) => write!(f, "{b}"),
+        Value::Callable(c) => write!(f, "{c}"),
+        Value::List(list) => {
+            let address = Rc::as_ptr(list) as usize;
+            if printing.contains(&address) {
+                return write!(f, "[...]");
+            }
+            printing.push(address);
+            write!(f, "[")?;
+            for (i, element) in list.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_nested(f, element, printing)?;
+            }
+            printing.pop();
+            write!(f, "]")
+        }
+        Value::Map(map) => {
+            let address = Rc::as_ptr(map) as usize;
+            if printing.contains(&address) {
+                return write!(f, "{{...}}");
+            }
+            printing.push(address);
+            write!(f, "{{")?;
+            for (i, (key, value)) in map.borrow().iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_nested(f, key.value(), printing)?;
+                write!(f, ": ")?;
+                write_nested(f, value, printing)?;
+            }
+            printing.pop();
+            write!(f, "}}")
+        }
+        // Value::Instance(i) => write!(f, "{}", i.borrow()),
+        Value::Nil => write!(f, "nil"),
+        Value::Number(n) => write!(f, "{}", number::format(*n)),
+        Value::Range(start, end) => {
+            write!(f, "{}..{}", number::format(*start), number::format(*end))
         }
+        Value::String(s) => write!(f, "{s}"),
+    }
+}
+
+// strings inside lists and maps are quoted so that `["a, b"]` and `["a", "b"]` print differently
+fn write_nested(
+    f: &mut fmt::Formatter<'_>,
+    value: &Value,
+    printing: &mut Vec<usize>,
+) -> fmt::Result {
+    match value {
+        Value::String(s) => write!(f, "\"{s}\""),
+        _ => write_value(f, value, printing),
     }
 }
 
 impl Value {
-    pub fn from_token(token: Token) -> Value {
+    /// Only `nil` and `false` are falsey, every other value including 0 and "" is truthy
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Nil | Value::Boolean(false))
+    }
+
+    pub fn list(elements: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(elements)))
+    }
+
+    pub fn map(entries: IndexMap<MapKey, Value>) -> Value {
+        Value::Map(Rc::new(RefCell::new(entries)))
+    }
+
+    /// The value of a literal token. Tokens from the scanner always convert, hand made tokens
+    /// with a missing or malformed literal are an error rather than a panic.
+    pub fn from_token(token: Token) -> Result<Value, String> {
         match token.token_type {
-            TokenType::FALSE => Value::Boolean(false),
-            TokenType::TRUE => Value::Boolean(true),
-            TokenType::NIL => Value::Nil,
-            TokenType::NUMBER => {
-                Value::Number(token.literal.clone().unwrap().parse::<f64>().unwrap())
+            TokenType::FALSE => Ok(Value::Boolean(false)),
+            TokenType::TRUE => Ok(Value::Boolean(true)),
+            TokenType::NIL => Ok(Value::Nil),
+            TokenType::NUMBER => token
+                .literal
+                .as_deref()
+                .and_then(|literal| literal.parse().ok())
+                .map(Value::Number)
+                .ok_or_else(|| format!("Invalid number literal '{}'.", token.lexeme())),
+            TokenType::STRING => token
+                .literal
+                .as_deref()
+                .map(|literal| Value::String(intern(literal)))
+                .ok_or_else(|| format!("Invalid string literal {}.", token.lexeme())),
+            _ => Err(format!("'{}' is not a literal.", token.lexeme())),
+        }
+    }
+}
+
+/**
+ * A value that can be used as a map key. Only nil, booleans, numbers and strings are allowed,
+ * since lists and maps are mutable and compared by identity.
+ *
+ * `Value` equality follows IEEE 754, which `Hash` can't: `-0 == 0` and `NaN != NaN`. Keys are
+ * normalized instead, so `0` and `-0` are the same key and every NaN is one key equal to itself.
+ */
+#[derive(Clone, Debug)]
+pub struct MapKey(Value);
+
+impl MapKey {
+    pub fn new(value: Value) -> Result<MapKey, String> {
+        match value {
+            Value::Number(n) => {
+                let n = if n == 0.0 {
+                    0.0
+                } else if n.is_nan() {
+                    f64::NAN
+                } else {
+                    n
+                };
+                Ok(MapKey(Value::Number(n)))
             }
-            TokenType::STRING => Value::String(token.literal.clone().unwrap()),
-            _ => panic!("Not a supported value token"),
+            Value::Boolean(_) | Value::Nil | Value::String(_) => Ok(MapKey(value)),
+            _ => Err(format!(
+                "Map keys must be nil, booleans, numbers or strings, got {value}."
+            )),
+        }
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.0
+    }
+}
+
+impl PartialEq for MapKey {
+    fn eq(&self, other: &MapKey) -> bool {
+        match (&self.0, &other.0) {
+            (Value::Number(s), Value::Number(o)) => s.to_bits() == o.to_bits(),
+            (s, o) => s == o,
+        }
+    }
+}
+
+impl Eq for MapKey {}
+
+impl Hash for MapKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        mem::discriminant(&self.0).hash(state);
+        match &self.0 {
+            Value::Boolean(b) => b.hash(state),
+            Value::Number(n) => n.to_bits().hash(state),
+            Value::String(s) => s.hash(state),
+            _ => {}
         }
     }
 }
+
+/// Checks that `index` is a whole number within `0..len`, as used by subscripts and list natives
+pub fn list_index(index: &Value, len: usize) -> Result<usize, String> {
+    match index {
+        Value::Number(n) if n.fract() == 0.0 => {
+            if *n >= 0.0 && *n < len as f64 {
+                Ok(*n as usize)
+            } else {
+                Err(format!("Index {n} out of range for list of length {len}."))
+            }
+        }
+        _ => Err(format!("List index must be a whole number, got {index}.")),
+    }
+}
+
+thread_local! {
+    static STRINGS: RefCell<HashSet<Rc<str>>> = RefCell::new(HashSet::new());
+}
+
+/**
+ * Returns the shared copy of `s`. Every string literal goes through here, so equal literals
+ * point at the same allocation and comparing them does not have to look at their contents.
+ */
+pub fn intern(s: &str) -> Rc<str> {
+    STRINGS.with(|strings| {
+        let mut strings = strings.borrow_mut();
+        if let Some(interned) = strings.get(s) {
+            return Rc::clone(interned);
+        }
+        let interned: Rc<str> = Rc::from(s);
+        strings.insert(Rc::clone(&interned));
+        interned
+    })
+}
+
+/// Concatenates two strings into a single allocation, without building a `String` first. The
+/// result is a runtime string and isn't interned.
+pub fn concat(left: &str, right: &str) -> Rc<str> {
+    // collecting an iterator of known length into an `Rc<[T]>` allocates once
+    let bytes: Rc<[u8]> = left.bytes().chain(right.bytes()).collect();
+    // SAFETY: two UTF-8 strings back to back are valid UTF-8, and `[u8]` and `str` have the same
+    // layout
+    unsafe { Rc::from_raw(Rc::into_raw(bytes) as *const str) }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use indexmap::IndexMap;
+
+    use super::{concat, intern, list_index, MapKey, Value};
+    use crate::token::{Token, TokenType};
+
+    #[test]
+    fn interns_equal_strings_once() {
+        let a = intern("lox");
+        let b = intern(&String::from("lox"));
+        assert!(Rc::ptr_eq(&a, &b));
+        assert!(!Rc::ptr_eq(&a, &intern("other")));
+    }
+
+    #[test]
+    fn compares_strings_by_contents() {
+        let interned = Value::String(intern("ab"));
+        let built = Value::String(concat("a", "b"));
+        assert_eq!(interned, built);
+        assert_ne!(built, Value::String(intern("ba")));
+    }
+
+    #[test]
+    fn prints_lists_and_maps_that_contain_themselves() {
+        let list = Value::list(vec![Value::Number(1.0)]);
+        let Value::List(elements) = &list else {
+            unreachable!()
+        };
+        elements.borrow_mut().push(list.clone());
+        assert_eq!(list.to_string(), "[1, [...]]");
+
+        let map = Value::map(IndexMap::new());
+        let Value::Map(entries) = &map else {
+            unreachable!()
+        };
+        let key = MapKey::new(Value::String(intern("self"))).unwrap();
+        entries.borrow_mut().insert(key, map.clone());
+        // the same list twice isn't a cycle
+        let pair = Value::list(vec![map.clone(), map.clone()]);
+        assert_eq!(pair.to_string(), "[{\"self\": {...}}, {\"self\": {...}}]");
+
+        // break the cycles, or the values are never freed
+        elements.borrow_mut().clear();
+        entries.borrow_mut().clear();
+    }
+
+    #[test]
+    fn only_nil_and_false_are_falsey() {
+        assert!(!Value::Nil.is_truthy());
+        assert!(!Value::Boolean(false).is_truthy());
+        for value in [
+            Value::Boolean(true),
+            Value::Number(0.0),
+            Value::Number(f64::NAN),
+            Value::String(intern("")),
+            Value::list(vec![]),
+            Value::map(IndexMap::new()),
+        ] {
+            assert!(value.is_truthy(), "{value} should be truthy");
+        }
+    }
+
+    #[test]
+    fn compares_numbers_as_doubles() {
+        assert_eq!(Value::Number(0.0), Value::Number(-0.0));
+        assert_ne!(Value::Number(f64::NAN), Value::Number(f64::NAN));
+        assert_ne!(Value::Nil, Value::Boolean(false));
+        assert_ne!(Value::Number(1.0), Value::String(intern("1")));
+        assert_eq!(Value::Range(0.0, 1.0), Value::Range(0.0, 1.0));
+    }
+
+    #[test]
+    fn concatenates_multibyte_strings() {
+        assert_eq!(&*concat("héllo ", "wörld"), "héllo wörld");
+        assert_eq!(&*concat("", ""), "");
+    }
+
+    #[test]
+    fn compares_lists_by_identity() {
+        let list = Value::list(vec![Value::Number(1.0)]);
+        assert_eq!(list, list.clone());
+        assert_ne!(list, Value::list(vec![Value::Number(1.0)]));
+    }
+
+    #[test]
+    fn displays_nested_lists() {
+        let list = Value::list(vec![
+            Value::Number(1.0),
+            Value::String(intern("a, b")),
+            Value::list(vec![]),
+        ]);
+        assert_eq!(list.to_string(), "[1, \"a, b\", []]");
+    }
+
+    #[test]
+    fn checks_list_indexes() {
+        assert_eq!(list_index(&Value::Number(2.0), 3), Ok(2));
+        assert!(list_index(&Value::Number(3.0), 3).is_err());
+        assert!(list_index(&Value::Number(-1.0), 3).is_err());
+        assert!(list_index(&Value::Number(0.5), 3).is_err());
+        assert!(list_index(&Value::Nil, 3).is_err());
+    }
+
+    #[test]
+    fn normalizes_number_keys() {
+        let zero = MapKey::new(Value::Number(0.0)).unwrap();
+        assert_eq!(zero, MapKey::new(Value::Number(-0.0)).unwrap());
+        let nan = MapKey::new(Value::Number(f64::NAN)).unwrap();
+        assert_eq!(nan, MapKey::new(Value::Number(-f64::NAN)).unwrap());
+        assert_ne!(zero, nan);
+        assert_ne!(zero, MapKey::new(Value::Boolean(false)).unwrap());
+        assert!(MapKey::new(Value::list(vec![])).is_err());
+    }
+
+    #[test]
+    fn displays_maps_in_insertion_order() {
+        let mut entries = IndexMap::new();
+        entries.insert(MapKey::new(Value::String(intern("b"))).unwrap(), Value::Nil);
+        entries.insert(
+            MapKey::new(Value::Number(1.0)).unwrap(),
+            Value::String(intern("one")),
+        );
+        assert_eq!(Value::map(entries).to_string(), "{\"b\": nil, 1: \"one\"}");
+    }
+
+    #[test]
+    fn converts_literal_tokens_without_panicking() {
+        let token = |token_type, lexeme: &str, literal: Option<&str>| {
+            Token::new(
+                token_type,
+                lexeme.to_string(),
+                literal.map(str::to_string),
+                1,
+            )
+        };
+        assert_eq!(
+            Value::from_token(token(TokenType::NUMBER, "0xFF", Some("255"))),
+            Ok(Value::Number(255.0))
+        );
+        assert_eq!(
+            Value::from_token(token(TokenType::NUMBER, "1_0", Some("1_0"))),
+            Err("Invalid number literal '1_0'.".to_string())
+        );
+        assert!(Value::from_token(token(TokenType::STRING, "\"a\"", None)).is_err());
+        assert!(Value::from_token(token(TokenType::PLUS, "+", None)).is_err());
+    }
+}
@@ -1,17 +1,68 @@
 use std::io::Write;
-use std::{env, fs::File, io::Read, panic, path::Path, process::exit};
+use std::{
+    cell::RefCell, env, fs, fs::File, io::Read, panic, path::Path, process::exit, rc::Rc, thread,
+};
 
-use common::interpreter::Interpreter;
+use common::debugger::{Console, Debugger};
+use common::formatter;
+use common::interpreter::{Error, Interpreter, STACK_SIZE};
+use common::json;
+use common::lint;
+use common::lsp;
 use common::parser::Parser;
+use common::profile::Profiler;
 use common::scanner::Scanner;
+use common::trace::{Format, Tracer};
+
+// the exit codes jlox and clox use, which the conformance tests check for
+const EXIT_COMPILE_ERROR: i32 = 65;
+const EXIT_RUNTIME_ERROR: i32 = 70;
+
+// deep recursion in Lox is deep recursion in Rust, so programs run on a thread with a big stack
 fn main() {
+    let command = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_command)
+        .unwrap();
+    if let Err(panic) = command.join() {
+        panic::resume_unwind(panic);
+    }
+}
+
+fn run_command() {
     let args: Vec<String> = env::args().collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
 
-    match args.len() {
-        0 | 1 => run_prompt(),
-        2 => run_file(&args[1]),
+    match args.as_slice() {
+        [] | [_] => run_prompt(),
+        [_, "tokens", path] => print_tokens(path, false),
+        [_, "tokens", path, "--json"] => print_tokens(path, true),
+        [_, "ast", path] => print_ast(path, false),
+        [_, "ast", path, "--json"] => print_ast(path, true),
+        [_, "cst", path] => print_cst(path),
+        [_, "fmt", args @ ..] if !args.is_empty() => format_files(args),
+        [_, "lint", "--config", config, paths @ ..] if !paths.is_empty() => {
+            lint_files(Some(config), paths)
+        }
+        [_, "lint", paths @ ..] if !paths.is_empty() => lint_files(None, paths),
+        [_, "lsp"] => serve_lsp(),
+        [_, "debug", path] => debug_file(path),
+        [_, "profile", path] => profile_file(path, None),
+        [_, "profile", path, "--folded", folded] => profile_file(path, Some(folded)),
+        [_, path, "--trace"] => trace_file(path, Format::Text),
+        [_, path, "--trace", "--json"] => trace_file(path, Format::Json),
+        [_, path] => run_file(path),
         _ => {
             println!("Usage: rlox [script-name]");
+            println!("       rlox <script-name> --trace [--json]");
+            println!("       rlox tokens <script-name> [--json]");
+            println!("       rlox ast <script-name> [--json]");
+            println!("       rlox cst <script-name>");
+            println!("       rlox fmt [--check] <script-name>...");
+            println!("       rlox lint [--config <file>] <script-name>...");
+            println!("       rlox lsp");
+            println!("       rlox debug <script-name>");
+            println!("       rlox profile <script-name> [--folded <file>]");
             exit(64);
         }
     }
@@ -39,38 +90,215 @@ fn run_prompt() {
             break;
         }
 
-        run(input.clone());
+        // errors have been reported, the prompt carries on either way
+        let _ = run(input.clone(), Interpreter::new());
     }
 }
 
-fn run_file(path: &String) {
-    let file_content = match File::open(Path::new(path)) {
+fn read_file(path: &str) -> String {
+    match File::open(Path::new(path)) {
         Ok(mut file) => {
             let mut content = String::new();
             file.read_to_string(&mut content).unwrap();
             content
         }
         Err(_) => panic!("Error opening file {path}"),
+    }
+}
+
+fn run_file(path: &str) {
+    if let Err(code) = run(read_file(path), Interpreter::with_file(path)) {
+        exit(code);
+    }
+}
+
+// the debugger reads its commands from stdin and shares stdout with the program
+fn debug_file(path: &str) {
+    let source = read_file(path);
+    let console = Console::new(&source, std::io::stdin().lock(), std::io::stdout());
+    let mut interpreter = Interpreter::with_file(path);
+    interpreter.set_hook(Rc::new(RefCell::new(Debugger::new(console))));
+    if let Err(code) = run(source, interpreter) {
+        exit(code);
+    }
+}
+
+// the trace goes to stderr, so the program's own output can still be told apart
+fn trace_file(path: &str, format: Format) {
+    let source = read_file(path);
+    let tracer = Tracer::new(&source, format, std::io::stderr());
+    let mut interpreter = Interpreter::with_file(path);
+    interpreter.set_hook(Rc::new(RefCell::new(tracer)));
+    if let Err(code) = run(source, interpreter) {
+        exit(code);
+    }
+}
+
+// the report goes to stderr once the program is done, even when it failed, and the folded stacks
+// for flamegraph tools to a file of their own
+fn profile_file(path: &str, folded_path: Option<&str>) {
+    let source = read_file(path);
+    let profiler = Rc::new(RefCell::new(Profiler::new()));
+    let mut interpreter = Interpreter::with_file(path);
+    interpreter.set_hook(profiler.clone());
+    let result = run(source.clone(), interpreter);
+    let profile = profiler.borrow_mut().finish();
+    eprint!("{}", profile.table(&source));
+    if let Some(folded_path) = folded_path {
+        if let Err(e) = fs::write(folded_path, profile.folded()) {
+            eprintln!("{folded_path}: {e}");
+            exit(1);
+        }
+    }
+    if let Err(code) = result {
+        exit(code);
+    }
+}
+
+fn print_tokens(path: &str, as_json: bool) {
+    let mut scanner = Scanner::new(read_file(path));
+    scanner.scan_tokens();
+    if as_json {
+        println!("{}", json::tokens_to_json(&scanner.tokens));
+    } else {
+        scanner.debug_print();
+    }
+}
+
+fn print_ast(path: &str, as_json: bool) {
+    let mut scanner = Scanner::new(read_file(path));
+    scanner.scan_tokens();
+    match Parser::new(&scanner.tokens).parse() {
+        Ok(program) if as_json => println!("{}", json::program_to_json(&program)),
+        Ok(program) => println!("{program}"),
+        Err(e) => {
+            eprintln!("Error parsing: {e}");
+            exit(65);
+        }
+    }
+}
+
+// the concrete syntax tree is printed even when there are errors, they show up as `Error` nodes
+fn print_cst(path: &str) {
+    let mut scanner = Scanner::lossless(read_file(path));
+    scanner.scan_tokens();
+    let (_, tree) = Parser::new(&scanner.tokens).parse_lossless();
+    print!("{}", tree.tree());
+}
+
+// formats the files in place, or with `--check` only lists the ones that aren't formatted
+fn format_files(args: &[&str]) {
+    let check = args.contains(&"--check");
+    let mut failed = false;
+    let mut unformatted = false;
+    for path in args.iter().filter(|arg| **arg != "--check") {
+        let source = read_file(path);
+        let formatted = match formatter::format(&source) {
+            Ok(formatted) => formatted,
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{path}: {error}");
+                }
+                failed = true;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{path}");
+            unformatted = true;
+        } else if let Err(e) = fs::write(path, formatted) {
+            eprintln!("{path}: {e}");
+            failed = true;
+        }
+    }
+    if failed {
+        exit(EXIT_COMPILE_ERROR);
+    }
+    if unformatted {
+        exit(1);
+    }
+}
+
+// without `--config`, a `.loxlint` in the current directory is used if there is one
+fn lint_files(config_path: Option<&str>, paths: &[&str]) {
+    let config_text = match config_path {
+        Some(path) => Some(read_file(path)),
+        None => fs::read_to_string(".loxlint").ok(),
+    };
+    let config = match config_text.as_deref().map(lint::Config::parse) {
+        None => lint::Config::default(),
+        Some(Ok(config)) => config,
+        Some(Err(e)) => {
+            eprintln!("{}: {e}", config_path.unwrap_or(".loxlint"));
+            exit(64);
+        }
     };
-    run(file_content)
+    let mut failed = false;
+    let mut denied = false;
+    for path in paths {
+        match lint::lint(&read_file(path), &config) {
+            Ok(diagnostics) => {
+                for diagnostic in diagnostics {
+                    denied |= diagnostic.severity == lint::Severity::Deny;
+                    println!("{path}: {diagnostic}");
+                }
+            }
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{path}: {error}");
+                }
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        exit(EXIT_COMPILE_ERROR);
+    }
+    if denied {
+        exit(1);
+    }
 }
 
-fn run(file_content: String) {
+// a language server for editors, talking over stdin and stdout
+fn serve_lsp() {
+    let stdin = std::io::stdin();
+    match lsp::serve(stdin.lock(), std::io::stdout().lock()) {
+        Ok(code) => exit(code),
+        Err(e) => {
+            eprintln!("lsp: {e}");
+            exit(1);
+        }
+    }
+}
+
+// only the program's own output goes to stdout, errors are reported on stderr
+fn run(file_content: String, mut interpreter: Interpreter) -> Result<(), i32> {
     let mut scanner = Scanner::new(file_content);
     scanner.scan_tokens();
-    scanner.debug_print();
     let parser = Parser::new(&scanner.tokens);
-    let mut interpreter = Interpreter::new();
 
-    match parser.parse() {
-        Ok(stmts) => {
-            println!("Parsed successfully");
-            interpreter.interpret(stmts);
+    // like jlox, scanning errors don't stop the parser from reporting its own
+    for error in &scanner.errors {
+        eprintln!("{error}");
+    }
+    let program = match parser.parse() {
+        Ok(program) if scanner.errors.is_empty() => program,
+        _ => {
+            for error in parser.errors.borrow().iter() {
+                eprintln!("{error}");
+            }
+            return Err(EXIT_COMPILE_ERROR);
         }
-        Err(e) => {
-            println!("Error parsing: {e}");
+    };
+    match interpreter.interpret(program) {
+        // stopped on purpose, e.g. by quitting the debugger
+        Ok(()) | Err(Error::Abort) => Ok(()),
+        Err(error) => {
+            eprintln!("{error}");
+            Err(EXIT_RUNTIME_ERROR)
         }
     }
-
-    println!("Done")
 }
@@ -0,0 +1,336 @@
+/**
+* Conformance tests in the format of the reference test suite,
+* see https://github.com/munificent/craftinginterpreters/tree/master/test
+*
+* Every `.lox` file is run through the `intepreter` binary and its stdout, stderr and exit code
+* are checked against the annotations in the file:
+*  // expect: <output>                   a line the script prints to stdout
+*  // expect runtime error: <message>    the script fails on this line, exiting with 70
+*
+* Runtime errors are printed as `Error: <message>`, where jlox prints only the message, so the
+* prefix is ignored when comparing them.
+*  // Error at 'x': <message>            a compile error reported on this line, exiting with 65
+*  // [line N] Error at 'x': <message>   a compile error reported on line N, `[java line N]` too
+*
+* `tests/lox` holds scripts this interpreter passes, laid out in directories the way the reference
+* suite is. They all have to pass. To see how far we are from the reference suite itself, point
+* `LOX_REFERENCE_SUITE` at the `test` directory of a craftinginterpreters checkout and run
+* `cargo test -p intepreter --test conformance -- --nocapture`. That prints how many tests of each
+* chapter pass and lists the failures, but never fails the build.
+*
+* Either run can be limited to some chapters with `LOX_CHAPTERS=chap09_control,chap10_functions`.
+*/
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+const EXIT_COMPILE_ERROR: i32 = 65;
+const EXIT_RUNTIME_ERROR: i32 = 70;
+
+// the chapters of the book that have tests, and the directories of the suite that test them
+const CHAPTERS: &[(&str, &[&str])] = &[
+    (
+        "chap08_statements",
+        &[
+            "assignment",
+            "block",
+            "bool",
+            "comments",
+            "empty_file",
+            "nil",
+            "number",
+            "operator",
+            "precedence",
+            "print",
+            "string",
+            "unexpected_character",
+            "variable",
+        ],
+    ),
+    (
+        "chap09_control",
+        &["for", "if", "logical_operator", "while"],
+    ),
+    ("chap10_functions", &["call", "function", "return"]),
+    ("chap11_resolving", &["closure"]),
+    (
+        "chap12_classes",
+        &["class", "constructor", "field", "method", "this"],
+    ),
+    ("chap13_inheritance", &["inheritance", "super"]),
+    // not part of the book, only found in tests/lox
+    ("extensions", &["for_in", "list", "map", "try"]),
+];
+
+#[derive(Debug, Default, PartialEq)]
+struct Expected {
+    output: Vec<String>,
+    compile_errors: Vec<String>,
+    // the message and line of the error
+    runtime_error: Option<(String, usize)>,
+    exit_code: i32,
+}
+
+impl Expected {
+    fn parse(source: &str) -> Self {
+        let mut expected = Expected::default();
+        for (i, line) in source.lines().enumerate() {
+            let line_number = i + 1;
+            let Some((_, comment)) = line.split_once("// ") else {
+                continue;
+            };
+            if let Some(output) = comment.strip_prefix("expect: ") {
+                expected.output.push(output.to_string());
+            } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+                expected.runtime_error = Some((message.to_string(), line_number));
+                expected.exit_code = EXIT_RUNTIME_ERROR;
+            } else if comment.starts_with("Error") {
+                expected
+                    .compile_errors
+                    .push(format!("[line {line_number}] {comment}"));
+                expected.exit_code = EXIT_COMPILE_ERROR;
+            } else if let Some((line, error)) = explicit_line(comment) {
+                expected
+                    .compile_errors
+                    .push(format!("[line {line}] {error}"));
+                expected.exit_code = EXIT_COMPILE_ERROR;
+            }
+        }
+        expected
+    }
+
+    // the reasons the output of a run doesn't match, empty when it passes
+    fn check(&self, stdout: &str, stderr: &str, exit_code: Option<i32>) -> Vec<String> {
+        let mut failures = vec![];
+        let stderr: Vec<&str> = stderr.lines().collect();
+
+        if let Some((message, line)) = &self.runtime_error {
+            match stderr.first() {
+                Some(first) if first.strip_prefix("Error: ") == Some(message) => {}
+                first => {
+                    failures.push(format!("expected runtime error '{message}', got {first:?}"))
+                }
+            }
+            match stderr.iter().skip(1).find_map(|frame| trace_line(frame)) {
+                Some(found) if found == *line => {}
+                found => failures.push(format!(
+                    "expected runtime error on line {line}, got {found:?}"
+                )),
+            }
+        } else {
+            let mut found: Vec<&str> = stderr.clone();
+            found.sort();
+            let mut expected: Vec<&str> = self.compile_errors.iter().map(String::as_str).collect();
+            expected.sort();
+            if found != expected {
+                failures.push(format!("expected errors {expected:?}, got {found:?}"));
+            }
+        }
+
+        let output: Vec<&str> = stdout.lines().collect();
+        if output != self.output {
+            failures.push(format!("expected output {:?}, got {output:?}", self.output));
+        }
+        if exit_code != Some(self.exit_code) {
+            failures.push(format!(
+                "expected exit code {}, got {exit_code:?}",
+                self.exit_code
+            ));
+        }
+        failures
+    }
+}
+
+// `[line N] Error...` and `[java line N] Error...`, clox only annotations are skipped
+fn explicit_line(comment: &str) -> Option<(usize, &str)> {
+    let rest = comment
+        .strip_prefix("[line ")
+        .or_else(|| comment.strip_prefix("[java line "))?;
+    let (line, error) = rest.split_once("] ")?;
+    if !error.starts_with("Error") {
+        return None;
+    }
+    Some((line.parse().ok()?, error))
+}
+
+// the line of a stack trace entry, either `[line N]` or `  at f (file.lox:N)`
+fn trace_line(frame: &str) -> Option<usize> {
+    if let Some(rest) = frame.trim().strip_prefix("[line ") {
+        return rest.strip_suffix(']')?.parse().ok();
+    }
+    let location = frame.trim().strip_prefix("at ")?.strip_suffix(')')?;
+    location.rsplit_once(':')?.1.parse().ok()
+}
+
+// the chapter a test belongs to, from its directory, or from its name for top level tests
+fn chapter(root: &Path, path: &Path) -> Option<&'static str> {
+    let relative = path.strip_prefix(root).ok()?;
+    let mut components = relative.components();
+    let first = components.next()?.as_os_str().to_str()?;
+    let group = if components.next().is_some() {
+        first
+    } else {
+        relative.file_stem()?.to_str()?
+    };
+    CHAPTERS
+        .iter()
+        .find(|(_, groups)| groups.contains(&group))
+        .map(|(chapter, _)| *chapter)
+}
+
+fn selected_chapters() -> Option<Vec<String>> {
+    let chapters = env::var("LOX_CHAPTERS").ok()?;
+    Some(chapters.split(',').map(|c| c.trim().to_string()).collect())
+}
+
+fn lox_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("can't read {}: {e}", dir.display()))
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            lox_files(&path, files);
+        } else if path.extension().is_some_and(|e| e == "lox") {
+            files.push(path);
+        }
+    }
+}
+
+#[derive(Default)]
+struct Report {
+    // chapter, passed, total
+    chapters: Vec<(&'static str, usize, usize)>,
+    failures: Vec<(PathBuf, Vec<String>)>,
+    skipped: usize,
+}
+
+fn run_suite(root: &Path) -> Report {
+    let mut files = vec![];
+    lox_files(root, &mut files);
+    let selected = selected_chapters();
+
+    let mut report = Report {
+        chapters: CHAPTERS.iter().map(|(name, _)| (*name, 0, 0)).collect(),
+        ..Report::default()
+    };
+    for path in files {
+        let Some(chapter) = chapter(root, &path) else {
+            report.skipped += 1;
+            continue;
+        };
+        if selected
+            .as_ref()
+            .is_some_and(|selected| !selected.iter().any(|c| c == chapter))
+        {
+            continue;
+        }
+
+        let source = fs::read_to_string(&path).unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_intepreter"))
+            .arg(&path)
+            .output()
+            .unwrap();
+        let failures = Expected::parse(&source).check(
+            &String::from_utf8_lossy(&output.stdout),
+            &String::from_utf8_lossy(&output.stderr),
+            output.status.code(),
+        );
+
+        let entry = report
+            .chapters
+            .iter_mut()
+            .find(|(name, _, _)| *name == chapter)
+            .unwrap();
+        entry.2 += 1;
+        if failures.is_empty() {
+            entry.1 += 1;
+        } else {
+            report.failures.push((path, failures));
+        }
+    }
+    report
+}
+
+fn print_report(report: &Report) {
+    for (path, failures) in &report.failures {
+        println!("FAIL {}", path.display());
+        for failure in failures {
+            println!("     {failure}");
+        }
+    }
+    for (chapter, passed, total) in &report.chapters {
+        if *total > 0 {
+            println!("{chapter:<20} {passed:>4} / {total:<4}");
+        }
+    }
+    if report.skipped > 0 {
+        println!(
+            "skipped {} tests outside the chapters above",
+            report.skipped
+        );
+    }
+}
+
+#[test]
+fn sample_scripts_pass() {
+    let report = run_suite(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox"));
+    print_report(&report);
+    assert!(
+        report.failures.is_empty(),
+        "{} conformance tests failed",
+        report.failures.len()
+    );
+}
+
+#[test]
+fn reference_suite() {
+    let Ok(root) = env::var("LOX_REFERENCE_SUITE") else {
+        println!("LOX_REFERENCE_SUITE is not set, skipping the reference suite");
+        return;
+    };
+    print_report(&run_suite(Path::new(&root)));
+}
+
+#[test]
+fn parses_annotations() {
+    let expected = Expected::parse(
+        "print 1; // expect: 1\nvar nil; // Error at 'nil': Expect variable name.\n\
+         // [java line 3] Error at end: Expect '}' after block.\n\
+         // [c line 3] Error at end: Expect '}' after block.",
+    );
+    assert_eq!(
+        expected,
+        Expected {
+            output: vec!["1".to_string()],
+            compile_errors: vec![
+                "[line 2] Error at 'nil': Expect variable name.".to_string(),
+                "[line 3] Error at end: Expect '}' after block.".to_string()
+            ],
+            runtime_error: None,
+            exit_code: EXIT_COMPILE_ERROR,
+        }
+    );
+
+    let expected = Expected::parse("\nnil(); // expect runtime error: Can only call functions.");
+    assert_eq!(
+        expected.runtime_error,
+        Some(("Can only call functions.".to_string(), 2))
+    );
+    assert!(expected
+        .check(
+            "",
+            "Error: Can only call functions.\n  at <script> (a.lox:2)",
+            Some(70)
+        )
+        .is_empty());
+    assert_eq!(
+        expected
+            .check("", "Error: Can only call functions.\n[line 3]", Some(70))
+            .len(),
+        1
+    );
+}
@@ -0,0 +1,55 @@
+/**
+* Tests of `intepreter debug`: commands are read from stdin, and the debugger's output is
+* interleaved with the program's on stdout.
+*/
+use std::{
+    env, fs,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+fn debug(source: &str, commands: &str) -> (Option<i32>, String) {
+    let path = env::temp_dir().join(format!("lox-debug-{}.lox", std::process::id()));
+    fs::write(&path, source).unwrap();
+    let mut debugger = Command::new(env!("CARGO_BIN_EXE_intepreter"))
+        .arg("debug")
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    debugger
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(commands.as_bytes())
+        .unwrap();
+    let output = debugger.wait_with_output().unwrap();
+    fs::remove_file(&path).unwrap();
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn stops_at_breakpoints_and_quits() {
+    let source = "var a = 1;\nprint a;\na = a + 1;\nprint a;\n";
+    let (code, output) = debug(source, "break 4\ncontinue\nprint a\nquit\n");
+    assert_eq!(code, Some(0));
+    assert_eq!(
+        output,
+        "\
+Stopped at line 1: var a = 1;
+(debug) Breakpoint at line 4.
+(debug) 1
+Breakpoint at line 4: print a;
+(debug) a = 2
+(debug) "
+    );
+
+    // the end of the commands quits too
+    let (code, output) = debug(source, "next\n");
+    assert_eq!(code, Some(0));
+    assert!(output.ends_with("Stopped at line 2: print a;\n(debug) \n"));
+}
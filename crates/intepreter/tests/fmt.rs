@@ -0,0 +1,104 @@
+/**
+* Tests of `intepreter fmt` against the sample scripts in `tests/lox`: formatting has to be
+* idempotent and must only change whitespace, every token and comment has to survive it.
+*/
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use common::{formatter, scanner::Scanner, token::TriviaKind};
+
+fn lox_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            lox_files(&path, files);
+        } else if path.extension().is_some_and(|e| e == "lox") {
+            files.push(path);
+        }
+    }
+}
+
+// the lexemes of the tokens and the text of the comments, in source order
+fn tokens_and_comments(source: &str) -> (Vec<String>, Vec<String>) {
+    let mut scanner = Scanner::lossless(source.to_string());
+    scanner.scan_tokens();
+    let mut tokens = vec![];
+    let mut comments = vec![];
+    for token in &scanner.tokens {
+        tokens.push(token.lexeme().to_string());
+        for trivia in token.leading.iter().chain(token.trailing.iter()) {
+            if trivia.kind == TriviaKind::Comment {
+                comments.push(trivia.text.trim_end().to_string());
+            }
+        }
+    }
+    (tokens, comments)
+}
+
+#[test]
+fn formats_samples_idempotently() {
+    let mut files = vec![];
+    lox_files(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lox"),
+        &mut files,
+    );
+    let mut formatted_files = 0;
+    for path in files {
+        let source = fs::read_to_string(&path).unwrap();
+        // samples of compile errors can't be formatted
+        let Ok(formatted) = formatter::format(&source) else {
+            continue;
+        };
+        formatted_files += 1;
+        assert_eq!(
+            formatter::format(&formatted).unwrap(),
+            formatted,
+            "formatting {} twice changes it",
+            path.display()
+        );
+        assert_eq!(
+            tokens_and_comments(&formatted),
+            tokens_and_comments(&source),
+            "formatting {} changes more than whitespace",
+            path.display()
+        );
+    }
+    assert!(formatted_files > 0);
+}
+
+#[test]
+fn check_reports_unformatted_files() {
+    let dir = env::temp_dir().join(format!("lox-fmt-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("script.lox");
+    fs::write(&path, "var a=1;\n").unwrap();
+    let fmt = |check: bool| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_intepreter"));
+        command.arg("fmt");
+        if check {
+            command.arg("--check");
+        }
+        command.arg(&path).output().unwrap()
+    };
+
+    let output = fmt(true);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout).trim(),
+        path.display().to_string()
+    );
+    assert_eq!(fs::read_to_string(&path).unwrap(), "var a=1;\n");
+
+    assert_eq!(fmt(false).status.code(), Some(0));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "var a = 1;\n");
+    assert_eq!(fmt(true).status.code(), Some(0));
+
+    fs::write(&path, "var a = ;\n").unwrap();
+    let output = fmt(true);
+    assert_eq!(output.status.code(), Some(65));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Expect expression."));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,46 @@
+/**
+* Tests of `intepreter lint`: diagnostics go to stdout, and the exit code says whether any of them
+* was denied by the config.
+*/
+use std::{env, fs, process::Command};
+
+#[test]
+fn lint_applies_the_config_file() {
+    let dir = env::temp_dir().join(format!("lox-lint-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("script.lox");
+    let config = dir.join("loxlint");
+    fs::write(&script, "var a = 1;\na = a;\nif (a) {}\n").unwrap();
+    let lint = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_intepreter"))
+            .current_dir(&dir)
+            .arg("lint")
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let output = lint(&["script.lox"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "\
+script.lox: [line 2] warning[self-assignment]: 'a' is assigned to itself.
+script.lox: [line 3] warning[empty-block]: Empty block.
+"
+    );
+
+    fs::write(&config, "deny self-assignment\nallow empty-block\n").unwrap();
+    let output = lint(&["--config", "loxlint", "script.lox"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "script.lox: [line 2] error[self-assignment]: 'a' is assigned to itself.\n"
+    );
+
+    fs::write(&script, "var a = ;\n").unwrap();
+    let output = lint(&["script.lox"]);
+    assert_eq!(output.status.code(), Some(65));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Expect expression."));
+    fs::remove_dir_all(&dir).unwrap();
+}
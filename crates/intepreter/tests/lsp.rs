@@ -0,0 +1,131 @@
+/**
+* A scripted client for `intepreter lsp`: a whole session is written to the server's stdin up
+* front, and the framed replies it writes to stdout are checked once it exits.
+*/
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use serde_json::{json, Value as Json};
+
+const URI: &str = "file:///script.lox";
+
+fn frame(message: &Json) -> String {
+    let body = message.to_string();
+    format!("Content-Length: {}\r\n\r\n{body}", body.len())
+}
+
+fn unframe(mut output: &str) -> Vec<Json> {
+    let mut messages = vec![];
+    while let Some((headers, rest)) = output.split_once("\r\n\r\n") {
+        let length: usize = headers
+            .strip_prefix("Content-Length: ")
+            .unwrap()
+            .parse()
+            .unwrap();
+        messages.push(serde_json::from_str(&rest[..length]).unwrap());
+        output = &rest[length..];
+    }
+    messages
+}
+
+// runs a session and returns the exit code and every message the server sent
+fn session(messages: &[Json]) -> (Option<i32>, Vec<Json>) {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_intepreter"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let input: String = messages.iter().map(frame).collect();
+    server
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = server.wait_with_output().unwrap();
+    (
+        output.status.code(),
+        unframe(&String::from_utf8(output.stdout).unwrap()),
+    )
+}
+
+fn request(id: u32, method: &str, params: Json) -> Json {
+    json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+#[test]
+fn serves_a_session() {
+    let text = "fun twice(x) {\n  return x * 2;\n}\nprint twice(21);\nprint twise(1);\n";
+    let at = |line: u32, character: u32| {
+        json!({
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character },
+            "context": { "includeDeclaration": false },
+        })
+    };
+    let (code, replies) = session(&[
+        request(1, "initialize", json!({ "capabilities": {} })),
+        notification("initialized", json!({})),
+        notification(
+            "textDocument/didOpen",
+            json!({ "textDocument": { "uri": URI, "languageId": "lox", "version": 1, "text": text } }),
+        ),
+        request(2, "textDocument/definition", at(3, 8)),
+        request(3, "textDocument/references", at(1, 10)),
+        request(4, "textDocument/hover", at(0, 5)),
+        request(5, "shutdown", Json::Null),
+        notification("exit", Json::Null),
+    ]);
+    assert_eq!(code, Some(0));
+    assert_eq!(replies.len(), 6);
+
+    assert_eq!(replies[0]["id"], 1);
+    assert_eq!(
+        replies[0]["result"]["capabilities"]["definitionProvider"],
+        true
+    );
+
+    assert_eq!(replies[1]["method"], "textDocument/publishDiagnostics");
+    let diagnostics = &replies[1]["params"]["diagnostics"];
+    assert_eq!(diagnostics.as_array().unwrap().len(), 1);
+    assert_eq!(
+        diagnostics[0]["message"],
+        "Undefined variable 'twise'.\nhelp: did you mean 'twice'?"
+    );
+    assert_eq!(
+        diagnostics[0]["range"]["start"],
+        json!({ "line": 4, "character": 6 })
+    );
+
+    let range = |line: u32, start: u32, end: u32| json!({ "start": { "line": line, "character": start }, "end": { "line": line, "character": end } });
+    assert_eq!(
+        replies[2]["result"],
+        json!({ "uri": URI, "range": range(0, 4, 9) })
+    );
+    assert_eq!(
+        replies[3]["result"],
+        json!([{ "uri": URI, "range": range(1, 9, 10) }])
+    );
+    assert_eq!(
+        replies[4]["result"]["contents"]["value"],
+        "```lox\nfun twice(x) {\n```\n\nDeclared on line 1."
+    );
+    assert_eq!(
+        replies[5],
+        json!({ "jsonrpc": "2.0", "id": 5, "result": null })
+    );
+}
+
+#[test]
+fn exits_with_an_error_without_shutdown() {
+    let (code, replies) = session(&[notification("exit", Json::Null)]);
+    assert_eq!(code, Some(1));
+    assert!(replies.is_empty());
+}
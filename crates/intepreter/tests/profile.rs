@@ -0,0 +1,52 @@
+/**
+* Tests of `intepreter profile`: the program runs as usual, the report is written to stderr and
+* the folded stacks to the file given with `--folded`.
+*/
+use std::{env, fs, process::Command};
+
+#[test]
+fn reports_lines_functions_and_folded_stacks() {
+    let dir = env::temp_dir();
+    let path = dir.join(format!("lox-profile-{}.lox", std::process::id()));
+    let folded = dir.join(format!("lox-profile-{}.folded", std::process::id()));
+    let source = "\
+fun square(n) {
+  return n * n;
+}
+var total = 0;
+for (var i = 0; i < 100; i = i + 1) total = total + square(i);
+print total;
+";
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_intepreter"))
+        .arg("profile")
+        .arg(&path)
+        .arg("--folded")
+        .arg(&folded)
+        .output()
+        .unwrap();
+    let stacks = fs::read_to_string(&folded).unwrap();
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&folded).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "328350\n");
+    let report = String::from_utf8(output.stderr).unwrap();
+    // the times differ from run to run, the counts don't
+    let counts: Vec<(&str, &str)> = report
+        .lines()
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            Some((columns.next()?, columns.next()?))
+        })
+        .collect();
+    assert!(counts.contains(&("2", "100")));
+    assert!(counts.contains(&("square:1", "100")));
+    assert!(report.starts_with("Lines by self time:\n"));
+    assert!(report.contains("\nFunctions by total time:\n"));
+    for line in stacks.lines() {
+        let (stack, micros) = line.rsplit_once(' ').unwrap();
+        assert!(stack == "<script>" || stack == "<script>;square:1");
+        assert!(micros.parse::<u64>().unwrap() > 0);
+    }
+}
@@ -0,0 +1,51 @@
+/**
+* Tests of `intepreter <script> --trace`: the trace is written to stderr, the program's output
+* to stdout stays the same.
+*/
+use std::{env, fs, process::Command};
+
+fn trace(source: &str, args: &[&str]) -> (String, String) {
+    let path = env::temp_dir().join(format!("lox-trace-{}.lox", std::process::id()));
+    fs::write(&path, source).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_intepreter"))
+        .arg(&path)
+        .args(args)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+fn traces_to_stderr() {
+    let source = "fun twice(n) {\n  return n * 2;\n}\nprint twice(4);\n";
+    let (stdout, stderr) = trace(source, &["--trace"]);
+    assert_eq!(stdout, "8\n");
+    assert_eq!(
+        stderr,
+        "\
+[line 1] fun twice(n) {
+define twice = <fn twice>
+[line 4] print twice(4);
+call twice(4)
+  [line 2] return n * 2;
+return twice -> 8
+"
+    );
+
+    let (stdout, stderr) = trace(source, &["--trace", "--json"]);
+    assert_eq!(stdout, "8\n");
+    let events: Vec<serde_json::Value> = stderr
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(events.len(), 6);
+    assert_eq!(
+        events[4],
+        serde_json::json!({"event": "statement", "depth": 1, "line": 2, "source": "return n * 2;"})
+    );
+}